@@ -0,0 +1,59 @@
+//Compares the linear HittableList scan against the BVH on random_scene.
+//Run with `cargo bench --bench bvh`.
+
+use cgmath::*;
+use ray_tracer::bvh::BVH;
+use ray_tracer::camera::Camera;
+use ray_tracer::hittable::Hittable;
+use ray_tracer::hittable_list;
+use ray_tracer::ray::Ray;
+use ray_tracer::sampler::{IndependentSampler, Sampler};
+use std::time::Instant;
+
+const RAYS: usize = 200_000;
+
+fn trace(world: &dyn Hittable, rays: &[Ray]) -> (f64, usize) {
+    let mut sampler = IndependentSampler::new(0);
+    let now = Instant::now();
+    let hits = rays
+        .iter()
+        .filter(|r| world.hit(r, 0.001, f64::INFINITY, &mut sampler).is_some())
+        .count();
+    (now.elapsed().as_secs_f64(), hits)
+}
+
+fn main() {
+    let cam = Camera::new(
+        Point3::new(13.0, 2.0, 3.0),
+        Point3::new(0.0, 0.0, 0.0),
+        Vector3::new(0.0, 1.0, 0.0),
+        20.0,
+        3.0 / 2.0,
+        0.1,
+        10.0,
+        0.0,
+        1.0,
+    );
+
+    let mut sampler = IndependentSampler::new(0);
+    let rays: Vec<Ray> = (0..RAYS)
+        .map(|_| {
+            let (u, v) = sampler.get_2d();
+            cam.cast_ray(u, v, &mut sampler)
+        })
+        .collect();
+
+    let list = hittable_list::random_scene(&mut IndependentSampler::new(1));
+    let now = Instant::now();
+    let bvh = BVH::new(hittable_list::random_scene(&mut IndependentSampler::new(1)), 0.0, 1.0);
+    let build = now.elapsed().as_secs_f64();
+
+    let (list_secs, list_hits) = trace(&list, &rays);
+    let (bvh_secs, bvh_hits) = trace(&bvh, &rays);
+    assert_eq!(list_hits, bvh_hits, "BVH and linear scan disagree");
+
+    println!("random_scene: {} objects, {} primary rays", list.objects.len(), RAYS);
+    println!("HittableList: {:>8.3} s", list_secs);
+    println!("BVH:          {:>8.3} s (+{:.3} s build)", bvh_secs, build);
+    println!("speedup:      {:>8.1}x", list_secs / bvh_secs);
+}
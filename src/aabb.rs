@@ -1,5 +1,5 @@
 use crate::ray::Ray;
-use cgmath::Point3;
+use cgmath::{Point3, Vector3};
 
 #[derive(Clone)]
 pub struct AABB {
@@ -30,9 +30,7 @@ impl AABB {
             let mut t0 = (self.min()[i] - r.origin[i]) * inv_d;
             let mut t1 = (self.max()[i] - r.origin[i]) * inv_d;
             if inv_d < 0.0 {
-                let tmp = t0;
-                t0 = t1;
-                t1 = tmp;
+                std::mem::swap(&mut t0, &mut t1);
             }
 
             *t_min = if t0 > *t_min { t0 } else { *t_min };
@@ -45,6 +43,65 @@ impl AABB {
         true
     }
 
+    //same slab test as `hit`, with the reciprocal direction computed once per ray by the caller
+    pub fn hit_inv(&self, r: &Ray, inv_d: Vector3<f64>, t_min: f64, t_max: f64) -> bool {
+        let (mut t_min, mut t_max) = (t_min, t_max);
+        for i in 0..3 {
+            let mut t0 = (self.minimum[i] - r.origin[i]) * inv_d[i];
+            let mut t1 = (self.maximum[i] - r.origin[i]) * inv_d[i];
+            if inv_d[i] < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+
+            t_min = if t0 > t_min { t0 } else { t_min };
+            t_max = if t1 < t_max { t1 } else { t_max };
+            if t_min > t_max {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    //an inverted box that any surrounding_box call will replace
+    pub fn empty() -> Self {
+        AABB::new(
+            Point3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY),
+            Point3::new(-f64::INFINITY, -f64::INFINITY, -f64::INFINITY),
+        )
+    }
+
+    pub fn centroid(&self) -> Point3<f64> {
+        Point3::new(
+            0.5 * (self.minimum.x + self.maximum.x),
+            0.5 * (self.minimum.y + self.maximum.y),
+            0.5 * (self.minimum.z + self.maximum.z),
+        )
+    }
+
+    pub fn surface_area(&self) -> f64 {
+        let d = self.maximum - self.minimum;
+        if d.x < 0.0 || d.y < 0.0 || d.z < 0.0 {
+            return 0.0;
+        }
+        2.0 * (d.x * d.y + d.y * d.z + d.z * d.x)
+    }
+
+    pub fn longest_axis(&self) -> usize {
+        let d = self.maximum - self.minimum;
+        if d.x > d.y && d.x > d.z {
+            0
+        } else if d.y > d.z {
+            1
+        } else {
+            2
+        }
+    }
+
+    pub fn include_point(&self, p: Point3<f64>) -> Self {
+        AABB::surrounding_box(self, &AABB::new(p, p))
+    }
+
     pub fn surrounding_box(box0: &AABB, box1: &AABB) -> Self {
         let small = Point3::new(
             box0.min().x.min(box1.min().x),
@@ -1,158 +1,487 @@
 use crate::aabb::AABB;
 use crate::hittable::HitRecord;
 use crate::hittable::Hittable;
+use crate::hittable_list::HittableList;
 use crate::ray::Ray;
-use crate::utilities;
-use crate::vec3::Point3;
-use std::cmp::Ordering;
-use std::rc::Rc;
-
-pub struct BVHNode {
-    pub bound: AABB,
-    pub left: Option<Rc<dyn Hittable>>,
-    pub right: Option<Rc<dyn Hittable>>,
+use crate::sampler::Sampler;
+use cgmath::{Point3, Vector3};
+use std::sync::Arc;
+
+const SAH_BUCKETS: usize = 12;
+const MAX_PRIMS_IN_LEAF: usize = 4;
+//cost of visiting an interior node relative to intersecting one primitive
+const TRAVERSAL_COST: f64 = 0.125;
+//below this depth nodes are split at the median instead of by SAH. Halving can only
+//add 32 more levels for the at most 2^32 primitives `offset` can address, so no leaf
+//is deeper than MAX_DEPTH and `hit` can use a fixed stack.
+const SAH_MAX_DEPTH: usize = 32;
+const MAX_DEPTH: usize = 64;
+
+//one node of the flattened tree. Interior nodes store their first child right after
+//themselves and the index of the second child in `offset`; leaves store the range
+//`offset..offset + count` into `BVH::primitives`.
+#[derive(Clone)]
+struct LinearNode {
+    bound: AABB,
+    offset: u32,
+    count: u16,
+    axis: u8,
 }
 
-impl BVHNode {
-    pub fn new(
-        src_objects: &Vec<Rc<dyn Hittable>>,
-        start: usize,
-        end: usize,
-        time0: f64,
-        time1: f64,
-    ) -> Self {
-        let left: Option<Rc<dyn Hittable>>;
-        let right: Option<Rc<dyn Hittable>>;
-        let axis = utilities::random_int_with_bounds(0, 3);
-        let comparator = match axis {
-            0 => Self::box_x_compare,
-            1 => Self::box_y_compare,
-            _ => Self::box_z_compare,
-        };
+//per-primitive data only needed while building
+struct PrimitiveInfo {
+    index: usize,
+    bound: AABB,
+    centroid: Point3<f64>,
+}
 
-        let object_span = end - start;
+//primitives without a usable bound (none at all, or one with a NaN or infinite centroid)
+//cannot be placed in the tree; they are kept after the others in `primitives` and
+//tested against every ray instead
+pub struct BVH<P: Hittable = Arc<dyn Hittable>> {
+    nodes: Vec<LinearNode>,
+    primitives: Vec<P>,
+    unbounded: usize,
+}
 
-        match object_span {
-            1 => {
-                left = Some(Rc::clone(&src_objects[start]));
-                right = Some(Rc::clone(&src_objects[start]));
-            }
-            2 => {
-                if comparator(&src_objects[start], &src_objects[start + 1]) == Ordering::Less {
-                    left = Some(Rc::clone(&src_objects[start]));
-                    right = Some(Rc::clone(&src_objects[start + 1]));
-                } else {
-                    left = Some(Rc::clone(&src_objects[start + 1]));
-                    right = Some(Rc::clone(&src_objects[start]));
-                }
-            }
-            _ => {
-                let mut part = Vec::new();
-                for i in start..end {
-                    part.push(Rc::clone(&src_objects[i]));
-                }
-                part.sort_by(comparator);
-
-                let mid = start + object_span / 2;
-                left = Some(Rc::new(BVHNode::new(&part, 0, mid - start, time0, time1)));
-                right = Some(Rc::new(BVHNode::new(
-                    &part,
-                    mid - start,
-                    end - start,
-                    time0,
-                    time1,
-                )));
+impl BVH {
+    //time0 - time1: the shutter interval, so moving objects are bounded over their whole path
+    pub fn new(list: HittableList, time0: f64, time1: f64) -> Self {
+        BVH::from_primitives(list.objects, time0, time1)
+    }
+}
+
+impl<P: Hittable> BVH<P> {
+    pub fn from_primitives(primitives: Vec<P>, time0: f64, time1: f64) -> Self {
+        Self::from_primitives_ordered(primitives, time0, time1).0
+    }
+
+    //also returns, for every primitive in the tree's order, its index in `primitives`
+    pub fn from_primitives_ordered(primitives: Vec<P>, time0: f64, time1: f64) -> (Self, Vec<usize>) {
+        let mut infos = Vec::with_capacity(primitives.len());
+        let mut unbounded = Vec::new();
+        for (index, p) in primitives.iter().enumerate() {
+            let mut bound = AABB::empty();
+            if p.bounding_box(time0, time1, &mut bound) && is_finite(bound.centroid()) {
+                infos.push(PrimitiveInfo {
+                    index,
+                    centroid: bound.centroid(),
+                    bound,
+                });
+            } else {
+                unbounded.push(index);
             }
         }
 
-        let mut box_left = AABB::new(Point3::new(0.0, 0.0, 0.0), Point3::new(0.0, 0.0, 0.0));
-        let mut box_right = AABB::new(Point3::new(0.0, 0.0, 0.0), Point3::new(0.0, 0.0, 0.0));
-
-        if !(left.clone().unwrap()).bounding_box(time0, time1, &mut box_left)
-            || !(right.clone().unwrap()).bounding_box(time0, time1, &mut box_right)
-        {
-            eprintln!("No bounding box in BVHNode constructor");
+        let mut nodes = Vec::with_capacity(2 * infos.len());
+        if !infos.is_empty() {
+            Self::build(&mut infos, 0, 0, &mut nodes);
         }
 
-        let bound = AABB::surrounding_box(&box_left, &box_right);
+        //reorder the primitives so that every leaf refers to a contiguous range
+        let mut slots: Vec<Option<P>> = primitives.into_iter().map(Some).collect();
+        let order: Vec<usize> = infos.iter().map(|info| info.index).chain(unbounded.iter().copied()).collect();
+        let primitives = order.iter().map(|&index| slots[index].take().unwrap()).collect();
 
-        BVHNode { left, right, bound }
+        (
+            BVH {
+                nodes,
+                primitives,
+                unbounded: unbounded.len(),
+            },
+            order,
+        )
     }
 
-    pub fn bounding_box(&self, _time0: f64, _time1: f64, output_box: &mut AABB) -> bool {
-        *output_box = self.bound.clone();
-        true
+    pub fn primitives(&self) -> &[P] {
+        &self.primitives
     }
 
-    fn box_compare(a: &Rc<dyn Hittable>, b: &Rc<dyn Hittable>, axis: usize) -> Ordering {
-        let mut box_a: AABB = AABB::new(Point3::new(0.0, 0.0, 0.0), Point3::new(0.0, 0.0, 0.0));
-        let mut box_b: AABB = AABB::new(Point3::new(0.0, 0.0, 0.0), Point3::new(0.0, 0.0, 0.0));
+    //primitives may be changed in place, e.g. moved; call `refit` afterwards
+    pub fn primitives_mut(&mut self) -> &mut [P] {
+        &mut self.primitives
+    }
 
-        if !a.bounding_box(0.0, 0.0, &mut box_a) || !b.bounding_box(0.0, 0.0, &mut box_b) {
-            eprintln!("No bounding box in bvh_node constructor.");
-        }
+    pub fn into_primitives(self) -> Vec<P> {
+        self.primitives
+    }
 
-        if box_a.min()[axis] < box_b.min()[axis] {
-            Ordering::Less
-        } else if box_a.min()[axis] == box_b.min()[axis] {
-            Ordering::Equal
-        } else {
-            Ordering::Greater
+    //recomputes every node's bound from the primitives while keeping the tree as it is.
+    //Much cheaper than a rebuild, but the tree gets worse the further primitives move
+    //from where they were at build time; see `sah_cost`. A primitive that has lost its
+    //bound is left out of its node's box until the next rebuild.
+    pub fn refit(&mut self, time0: f64, time1: f64) {
+        //children are stored after their parent, so a reverse sweep sees them first
+        for i in (0..self.nodes.len()).rev() {
+            let node = &self.nodes[i];
+            let bound = if node.count > 0 {
+                let start = node.offset as usize;
+                self.primitives[start..start + node.count as usize]
+                    .iter()
+                    .fold(AABB::empty(), |b, p| {
+                        let mut bound = AABB::empty();
+                        if p.bounding_box(time0, time1, &mut bound) && is_finite(bound.centroid()) {
+                            AABB::surrounding_box(&b, &bound)
+                        } else {
+                            b
+                        }
+                    })
+            } else {
+                AABB::surrounding_box(&self.nodes[i + 1].bound, &self.nodes[node.offset as usize].bound)
+            };
+            self.nodes[i].bound = bound;
         }
     }
 
-    fn box_x_compare(a: &Rc<dyn Hittable>, b: &Rc<dyn Hittable>) -> Ordering {
-        Self::box_compare(a, b, 0)
+    //the expected cost of tracing a ray through the tree under the surface area
+    //heuristic, relative to intersecting one primitive
+    pub fn sah_cost(&self) -> f64 {
+        let root_area = match self.nodes.first() {
+            Some(root) if root.bound.surface_area() > 0.0 => root.bound.surface_area(),
+            _ => return 0.0,
+        };
+        self.nodes
+            .iter()
+            .map(|node| {
+                let weight = if node.count > 0 { node.count as f64 } else { TRAVERSAL_COST };
+                weight * node.bound.surface_area() / root_area
+            })
+            .sum()
     }
 
-    fn box_y_compare(a: &Rc<dyn Hittable>, b: &Rc<dyn Hittable>) -> Ordering {
-        Self::box_compare(a, b, 1)
+    //appends the subtree for `infos` (which starts at `first` in the final primitive
+    //order, and `depth` levels below the root) in depth-first order and returns the
+    //index of its root node
+    fn build(infos: &mut [PrimitiveInfo], first: usize, depth: usize, nodes: &mut Vec<LinearNode>) -> usize {
+        debug_assert!(depth <= MAX_DEPTH, "BVH deeper than the traversal stack");
+        let bound = infos
+            .iter()
+            .fold(AABB::empty(), |b, info| AABB::surrounding_box(&b, &info.bound));
+        let node_index = nodes.len();
+        nodes.push(LinearNode {
+            bound: bound.clone(),
+            offset: first as u32,
+            count: infos.len() as u16,
+            axis: 0,
+        });
+
+        if infos.len() == 1 {
+            return node_index;
+        }
+
+        let centroid_bound = infos
+            .iter()
+            .fold(AABB::empty(), |b, info| b.include_point(info.centroid));
+        let axis = centroid_bound.longest_axis();
+        let (lo, hi) = (centroid_bound.min()[axis], centroid_bound.max()[axis]);
+
+        //all centroids coincide, no split can separate them by position
+        if hi <= lo {
+            if infos.len() <= MAX_PRIMS_IN_LEAF {
+                return node_index;
+            }
+            return Self::split_interior(infos, first, depth, infos.len() / 2, axis, node_index, nodes);
+        }
+
+        //SAH can peel off a few primitives at a time on badly clustered input, which
+        //would let the tree grow without bound
+        if depth >= SAH_MAX_DEPTH {
+            if infos.len() <= MAX_PRIMS_IN_LEAF {
+                return node_index;
+            }
+            infos.sort_by(|a, b| a.centroid[axis].total_cmp(&b.centroid[axis]));
+            return Self::split_interior(infos, first, depth, infos.len() / 2, axis, node_index, nodes);
+        }
+
+        let bucket_of = |info: &PrimitiveInfo| {
+            let b = (SAH_BUCKETS as f64 * (info.centroid[axis] - lo) / (hi - lo)) as usize;
+            b.min(SAH_BUCKETS - 1)
+        };
+
+        let mut counts = [0usize; SAH_BUCKETS];
+        let mut bounds = vec![AABB::empty(); SAH_BUCKETS];
+        for info in infos.iter() {
+            let b = bucket_of(info);
+            counts[b] += 1;
+            bounds[b] = AABB::surrounding_box(&bounds[b], &info.bound);
+        }
+
+        //cost of splitting after each bucket, from a forward and a backward sweep
+        let mut costs = [0.0; SAH_BUCKETS - 1];
+        let (mut count_below, mut bound_below) = (0, AABB::empty());
+        for i in 0..SAH_BUCKETS - 1 {
+            count_below += counts[i];
+            bound_below = AABB::surrounding_box(&bound_below, &bounds[i]);
+            costs[i] = count_below as f64 * bound_below.surface_area();
+        }
+        let (mut count_above, mut bound_above) = (0, AABB::empty());
+        for i in (1..SAH_BUCKETS).rev() {
+            count_above += counts[i];
+            bound_above = AABB::surrounding_box(&bound_above, &bounds[i]);
+            costs[i - 1] += count_above as f64 * bound_above.surface_area();
+        }
+
+        let (min_bucket, min_cost) = costs
+            .iter()
+            .enumerate()
+            .fold((0, f64::INFINITY), |best, (i, &c)| if c < best.1 { (i, c) } else { best });
+
+        let area = bound.surface_area();
+        let split_cost = if area > 0.0 {
+            TRAVERSAL_COST + min_cost / area
+        } else {
+            TRAVERSAL_COST
+        };
+        let leaf_cost = infos.len() as f64;
+
+        if infos.len() <= MAX_PRIMS_IN_LEAF && leaf_cost <= split_cost {
+            return node_index;
+        }
+
+        //stable partition keeps the build independent of the input's memory layout
+        infos.sort_by_key(|info| bucket_of(info) > min_bucket);
+        let mid = infos.iter().take_while(|info| bucket_of(info) <= min_bucket).count();
+        let mid = if mid == 0 || mid == infos.len() {
+            infos.sort_by(|a, b| a.centroid[axis].total_cmp(&b.centroid[axis]));
+            infos.len() / 2
+        } else {
+            mid
+        };
+
+        Self::split_interior(infos, first, depth, mid, axis, node_index, nodes)
     }
 
-    fn box_z_compare(a: &Rc<dyn Hittable>, b: &Rc<dyn Hittable>) -> Ordering {
-        Self::box_compare(a, b, 2)
+    fn split_interior(
+        infos: &mut [PrimitiveInfo],
+        first: usize,
+        depth: usize,
+        mid: usize,
+        axis: usize,
+        node_index: usize,
+        nodes: &mut Vec<LinearNode>,
+    ) -> usize {
+        let (below, above) = infos.split_at_mut(mid);
+        Self::build(below, first, depth + 1, nodes);
+        let second_child = Self::build(above, first + mid, depth + 1, nodes);
+
+        let node = &mut nodes[node_index];
+        node.offset = second_child as u32;
+        node.count = 0;
+        node.axis = axis as u8;
+        node_index
     }
 }
 
-impl Hittable for BVHNode {
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-        let (mut t_min, mut t_max) = (t_min, t_max);
-        if !self.bound.hit(r, &mut t_min, &mut t_max) {
-            return None;
-        }
+fn is_finite(p: Point3<f64>) -> bool {
+    p.x.is_finite() && p.y.is_finite() && p.z.is_finite()
+}
 
-        let (mut hit_left, mut hit_right) = (false, false); 
-        let mut t = t_max;
+impl<P: Hittable> Hittable for BVH<P> {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, sampler: &mut dyn Sampler) -> Option<HitRecord> {
         let mut rec = None;
-
-        if let Some(left_ptr) = &self.left {
-            match left_ptr.hit(r, t_min, t_max) {
-                None => {
-                    hit_left = false;
-                },
-                Some(left_rec) => {
-                    rec = Some(left_rec);
-                }
+        let mut closest_so_far = t_max;
+        for object in &self.primitives[self.primitives.len() - self.unbounded..] {
+            if let Some(record) = object.hit(r, t_min, closest_so_far, sampler) {
+                closest_so_far = record.t;
+                rec = Some(record);
             }
         }
+        if self.nodes.is_empty() {
+            return rec;
+        }
 
-        if let Some(right_ptr) = &self.right {
-            match right_ptr.hit(r, t_min, t_max) {
-                None => {
-                    hit_right = false;
-                }
-                Some(right_ptr) => {
-                    t_max = if hit_left { rec.t } else { t_max };
-                    right_ptr.hit(r, t_min, t_max, rec)
+        let inv_d = Vector3::new(1.0 / r.direction.x, 1.0 / r.direction.y, 1.0 / r.direction.z);
+        let dir_is_neg = [inv_d.x < 0.0, inv_d.y < 0.0, inv_d.z < 0.0];
+
+        //one entry per interior node on the way down, see MAX_DEPTH
+        let mut to_visit = [0usize; MAX_DEPTH];
+        let mut to_visit_len = 0;
+        let mut current = 0;
+
+        loop {
+            let node = &self.nodes[current];
+            if node.bound.hit_inv(r, inv_d, t_min, closest_so_far) {
+                if node.count > 0 {
+                    let start = node.offset as usize;
+                    for object in &self.primitives[start..start + node.count as usize] {
+                        if let Some(record) = object.hit(r, t_min, closest_so_far, sampler) {
+                            closest_so_far = record.t;
+                            rec = Some(record);
+                        }
+                    }
+                } else {
+                    //visit the child nearer to the ray origin first so the far one is
+                    //likely culled by the shortened closest_so_far
+                    let (near, far) = if dir_is_neg[node.axis as usize] {
+                        (node.offset as usize, current + 1)
+                    } else {
+                        (current + 1, node.offset as usize)
+                    };
+                    to_visit[to_visit_len] = far;
+                    to_visit_len += 1;
+                    current = near;
+                    continue;
                 }
             }
-        };
+
+            if to_visit_len == 0 {
+                break;
+            }
+            to_visit_len -= 1;
+            current = to_visit[to_visit_len];
+        }
 
         rec
     }
 
-    fn bounding_box(&self, time0: f64, time1: f64, output_box: &mut AABB) -> bool {
-        *output_box = self.bound.clone();
-        true
+    fn bounding_box(&self, _time0: f64, _time1: f64, output_box: &mut AABB) -> bool {
+        if self.unbounded > 0 {
+            return false;
+        }
+        match self.nodes.first() {
+            Some(root) => {
+                *output_box = root.bound.clone();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::material::Lambertian;
+    use crate::objects::Sphere;
+    use crate::sampler::IndependentSampler;
+
+    fn sphere(center: Point3<f64>, radius: f64) -> Arc<dyn Hittable> {
+        Arc::new(Sphere::new(center, radius, Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)))))
+    }
+
+    //hides the bound of the object it wraps
+    struct Unbounded(Arc<dyn Hittable>);
+
+    impl Hittable for Unbounded {
+        fn hit(&self, r: &Ray, t_min: f64, t_max: f64, sampler: &mut dyn Sampler) -> Option<HitRecord> {
+            self.0.hit(r, t_min, t_max, sampler)
+        }
+
+        fn bounding_box(&self, _time0: f64, _time1: f64, _output_box: &mut AABB) -> bool {
+            false
+        }
+    }
+
+    fn depth<P: Hittable>(bvh: &BVH<P>, node: usize) -> usize {
+        let n = &bvh.nodes[node];
+        if n.count > 0 {
+            0
+        } else {
+            1 + depth(bvh, node + 1).max(depth(bvh, n.offset as usize))
+        }
+    }
+
+    #[test]
+    fn hits_match_a_linear_search() {
+        let mut sampler = IndependentSampler::new(9);
+        let mut random = move || sampler.get_1d();
+        let mut list = HittableList::new();
+        let mut shared = Vec::new();
+        for _ in 0..300 {
+            let s = sphere(
+                Point3::new(20.0 * random() - 10.0, 20.0 * random() - 10.0, 20.0 * random() - 10.0),
+                0.1 + 0.5 * random(),
+            );
+            shared.push(Arc::clone(&s));
+            list.add_shared(s);
+        }
+        let bvh = BVH::from_primitives(shared, 0.0, 1.0);
+
+        let mut sampler = IndependentSampler::new(1);
+        for _ in 0..500 {
+            let origin = Point3::new(30.0 * random() - 15.0, 30.0 * random() - 15.0, 30.0 * random() - 15.0);
+            let direction = Vector3::new(random() - 0.5, random() - 0.5, random() - 0.5);
+            let r = Ray::new(origin, direction, 0.0);
+            let expected = list.hit(&r, 0.001, f64::INFINITY, &mut sampler).map(|rec| rec.t);
+            let found = bvh.hit(&r, 0.001, f64::INFINITY, &mut sampler).map(|rec| rec.t);
+            assert_eq!(expected, found);
+        }
+    }
+
+    #[test]
+    fn clustered_input_stays_within_the_traversal_stack() {
+        //every split SAH finds separates just the farthest sphere or two
+        let spheres: Vec<Arc<dyn Hittable>> = (0..300)
+            .map(|i| sphere(Point3::new(2f64.powi(i), 0.0, 0.0), 0.25))
+            .collect();
+        let bvh = BVH::from_primitives(spheres, 0.0, 1.0);
+        assert!(depth(&bvh, 0) <= MAX_DEPTH);
+
+        let mut sampler = IndependentSampler::new(1);
+        let along = Ray::new(Point3::new(-10.0, 0.0, 0.0), Vector3::new(1.0, 0.0, 0.0), 0.0);
+        let rec = bvh.hit(&along, 0.001, f64::INFINITY, &mut sampler).unwrap();
+        assert!((rec.p.x - 0.75).abs() < 1e-9);
+        let back = Ray::new(Point3::new(2f64.powi(300), 0.0, 0.0), Vector3::new(-1.0, 0.0, 0.0), 0.0);
+        assert!(bvh.hit(&back, 0.001, f64::INFINITY, &mut sampler).is_some());
+    }
+
+    #[test]
+    fn primitives_without_a_usable_bound_are_still_hit() {
+        let spheres: Vec<Arc<dyn Hittable>> = vec![
+            sphere(Point3::new(0.0, 0.0, 0.0), 1.0),
+            Arc::new(Unbounded(sphere(Point3::new(0.0, 0.0, -5.0), 1.0))),
+            sphere(Point3::new(0.0, 0.0, 5.0), 1.0),
+        ];
+        let (bvh, order) = BVH::from_primitives_ordered(spheres, 0.0, 1.0);
+        let mut sorted = order.clone();
+        sorted.sort_unstable();
+        assert_eq!(sorted, (0..3).collect::<Vec<_>>());
+        assert!(!bvh.bounding_box(0.0, 1.0, &mut AABB::empty()));
+
+        let mut sampler = IndependentSampler::new(1);
+        for (z, expected) in [(-10.0, -6.0), (10.0, 6.0)] {
+            let r = Ray::new(Point3::new(0.0, 0.0, z), Vector3::new(0.0, 0.0, -z.signum()), 0.0);
+            let rec = bvh.hit(&r, 0.001, f64::INFINITY, &mut sampler).unwrap();
+            assert!((rec.p.z - expected).abs() < 1e-9, "{}", rec.p.z);
+        }
+
+        //a NaN centroid must not stop the build either
+        let spheres = vec![sphere(Point3::new(f64::NAN, 0.0, 0.0), 1.0), sphere(Point3::new(0.0, 0.0, 0.0), 1.0)];
+        assert_eq!(BVH::from_primitives(spheres, 0.0, 1.0).primitives().len(), 2);
+    }
+
+    #[test]
+    fn coincident_centroids_are_split_into_small_leaves() {
+        let spheres: Vec<Arc<dyn Hittable>> = (0..1000)
+            .map(|i| sphere(Point3::new(0.0, 0.0, 0.0), 1.0 + i as f64 * 1e-3))
+            .collect();
+        let bvh = BVH::from_primitives(spheres, 0.0, 1.0);
+        assert!(bvh.nodes.iter().all(|node| node.count as usize <= MAX_PRIMS_IN_LEAF));
+        assert!(depth(&bvh, 0) <= MAX_DEPTH);
+    }
+
+    #[test]
+    fn empty_bvh_hits_nothing() {
+        let bvh = BVH::new(HittableList::new(), 0.0, 1.0);
+        let r = Ray::new(Point3::new(0.0, 0.0, 0.0), Vector3::new(1.0, 0.0, 0.0), 0.0);
+        assert!(bvh.hit(&r, 0.001, f64::INFINITY, &mut IndependentSampler::new(1)).is_none());
+        assert!(!bvh.bounding_box(0.0, 1.0, &mut AABB::empty()));
+        assert_eq!(bvh.sah_cost(), 0.0);
+    }
+
+    #[test]
+    fn refit_without_movement_keeps_the_cost() {
+        let spheres: Vec<Arc<dyn Hittable>> = (0..50)
+            .map(|i| sphere(Point3::new((i % 7) as f64, (i / 7) as f64, 0.0), 0.3))
+            .collect();
+        let (mut bvh, order) = BVH::from_primitives_ordered(spheres, 0.0, 1.0);
+        let mut sorted = order.clone();
+        sorted.sort_unstable();
+        assert_eq!(sorted, (0..50).collect::<Vec<_>>());
+
+        let cost = bvh.sah_cost();
+        bvh.refit(0.0, 1.0);
+        assert!((bvh.sah_cost() - cost).abs() < 1e-12);
     }
 }
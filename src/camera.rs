@@ -1,6 +1,8 @@
 use crate::ray::Ray;
+use crate::sampler::Sampler;
 use crate::utilities;
 use cgmath::*;
+use std::f64::consts::{FRAC_PI_2, FRAC_PI_4};
 
 pub struct Camera {
     origin: Point3<f64>,
@@ -9,13 +11,13 @@ pub struct Camera {
     lower_left_corner: Point3<f64>,
     u: Vector3<f64>,
     v: Vector3<f64>,
-    w: Vector3<f64>,
     lens_radius: f64,
     time0: f64,
     time1: f64,
 }
 
 impl Camera {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         look_from: Point3<f64>,
         look_at: Point3<f64>,
@@ -51,15 +53,14 @@ impl Camera {
             lower_left_corner: look_from - horizontal / 2.0 - vertical / 2.0 - w * focus_dist,
             u,
             v,
-            w,
             lens_radius: aperture / 2.0,
             time0,
             time1,
         }
     }
 
-    pub fn cast_ray(&self, s: f64, t: f64) -> Ray {
-        let rd = random_in_unit_dist() * self.lens_radius;
+    pub fn cast_ray(&self, s: f64, t: f64, sampler: &mut dyn Sampler) -> Ray {
+        let rd = random_in_unit_dist(sampler) * self.lens_radius;
         let offset = self.u * rd.x + self.v * rd.y;
 
         Ray {
@@ -67,22 +68,26 @@ impl Camera {
             direction: self.lower_left_corner + self.horizontal * s + self.vertical * t
                 - self.origin
                 - offset,
-            time: utilities::random_double_with_bounds(self.time0, self.time1),
+            time: utilities::random_double_with_bounds(sampler, self.time0, self.time1),
+            wavelength: None,
         }
     }
 }
 
-pub fn random_in_unit_dist() -> Vector3<f64> {
-    loop {
-        let p = Vector3::new(
-            utilities::random_double_with_bounds(-1.0, 1.0),
-            utilities::random_double_with_bounds(-1.0, 1.0),
-            0.0,
-        );
-        if p.magnitude2() >= 1.0 {
-            continue;
-        }
-        return p;
+//Shirley-Chiu concentric mapping of the unit square onto the disk. Unlike rejection
+//sampling it always consumes exactly two values and keeps their stratification.
+pub fn random_in_unit_dist(sampler: &mut dyn Sampler) -> Vector3<f64> {
+    let (u, v) = sampler.get_2d();
+    let (a, b) = (2.0 * u - 1.0, 2.0 * v - 1.0);
+    if a == 0.0 && b == 0.0 {
+        return Vector3::new(0.0, 0.0, 0.0);
     }
+
+    let (r, theta) = if a.abs() > b.abs() {
+        (a, FRAC_PI_4 * (b / a))
+    } else {
+        (b, FRAC_PI_2 - FRAC_PI_4 * (a / b))
+    };
+    Vector3::new(r * theta.cos(), r * theta.sin(), 0.0)
 }
 
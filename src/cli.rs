@@ -0,0 +1,228 @@
+use ray_tracer::color::ColorMode;
+use ray_tracer::output::ImageFormat;
+use ray_tracer::sampler::SamplerKind;
+use ray_tracer::scene::{Scene, BUILTIN_SCENES};
+use std::path::Path;
+
+pub struct Options {
+    pub scene: String,
+    pub width: Option<usize>,
+    pub height: Option<usize>,
+    pub samples_per_pixel: Option<usize>,
+    pub max_depth: Option<usize>,
+    pub max_diffuse: Option<usize>,
+    pub max_specular: Option<usize>,
+    pub max_volume: Option<usize>,
+    pub rr_depth: Option<usize>,
+    pub sampler: Option<SamplerKind>,
+    pub color_mode: Option<ColorMode>,
+    pub seed: u64,
+    pub threads: usize,
+    pub output: String,
+    pub format: ImageFormat,
+}
+
+pub enum Command {
+    Help,
+    Render(Options),
+}
+
+pub fn usage() -> String {
+    format!(
+        "Usage: ray_tracer [OPTIONS]
+
+Options:
+  -s, --scene <NAME|FILE>   built-in scene, scene file or glTF file [default: random]
+  -W, --width <PIXELS>      image width; the height follows the scene's aspect ratio unless given
+  -H, --height <PIXELS>     image height; the width follows the scene's aspect ratio unless given
+  -n, --samples <N>         samples per pixel
+  -d, --max-depth <N>       maximum number of bounces per path
+      --max-diffuse <N>     maximum number of diffuse bounces per path
+      --max-specular <N>    maximum number of specular bounces per path
+      --max-volume <N>      maximum number of scattering events in media per path
+      --rr-depth <N>        bounces before Russian roulette may end a path [default: 3]
+      --sampler <NAME>      sample sequence for pixels, lens, time and bounces [default: sobol]
+      --color-mode <MODE>   trace RGB, or sampled wavelengths for dispersion [default: rgb]
+      --seed <N>            seed for all random numbers [default: 0]
+  -j, --threads <N>         number of render threads [default: all cores]
+  -o, --output <PATH>       output image [default: output.png]
+  -f, --format <FORMAT>     output format, guessed from the output extension if omitted
+  -h, --help                print this help
+
+Built-in scenes: {}
+Samplers: {}
+Color modes: {}
+Formats: {}",
+        BUILTIN_SCENES.join(", "),
+        SamplerKind::NAMES.join(", "),
+        ColorMode::NAMES.join(", "),
+        ImageFormat::NAMES.join(", ")
+    )
+}
+
+pub fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Command, String> {
+    let mut options = Options {
+        scene: String::from("random"),
+        width: None,
+        height: None,
+        samples_per_pixel: None,
+        max_depth: None,
+        max_diffuse: None,
+        max_specular: None,
+        max_volume: None,
+        rr_depth: None,
+        sampler: None,
+        color_mode: None,
+        seed: 0,
+        threads: std::thread::available_parallelism().map_or(1, |n| n.get()),
+        output: String::from("output.png"),
+        format: ImageFormat::Png,
+    };
+    let mut format = None;
+
+    while let Some(arg) = args.next() {
+        //accept both `--flag value` and `--flag=value`
+        let (flag, inline_value) = match arg.find('=') {
+            Some(eq) if arg.starts_with("--") => (arg[..eq].to_string(), Some(arg[eq + 1..].to_string())),
+            _ => (arg.clone(), None),
+        };
+        if flag == "-h" || flag == "--help" {
+            return Ok(Command::Help);
+        }
+
+        if !flag.starts_with('-') {
+            return Err(format!("unexpected argument `{}`", flag));
+        }
+        let value = match inline_value.or_else(|| args.next()) {
+            Some(value) => value,
+            None => return Err(format!("missing value for `{}`", flag)),
+        };
+
+        match flag.as_str() {
+            "-s" | "--scene" => options.scene = value,
+            "-W" | "--width" => options.width = Some(image_size(&flag, &value)?),
+            "-H" | "--height" => options.height = Some(image_size(&flag, &value)?),
+            "-n" | "--samples" => options.samples_per_pixel = Some(positive(&flag, &value)?),
+            "-d" | "--max-depth" => options.max_depth = Some(positive(&flag, &value)?),
+            "--max-diffuse" => options.max_diffuse = Some(positive(&flag, &value)?),
+            "--max-specular" => options.max_specular = Some(positive(&flag, &value)?),
+            "--max-volume" => options.max_volume = Some(positive(&flag, &value)?),
+            "--rr-depth" => options.rr_depth = Some(positive(&flag, &value)?),
+            "--sampler" => {
+                options.sampler = Some(
+                    SamplerKind::from_name(&value).ok_or_else(|| format!("unknown sampler `{}`", value))?,
+                )
+            }
+            "--color-mode" => {
+                options.color_mode = Some(
+                    ColorMode::from_name(&value).ok_or_else(|| format!("unknown color mode `{}`", value))?,
+                )
+            }
+            "--seed" => {
+                options.seed = value
+                    .parse()
+                    .map_err(|_| format!("`{}` expects a non-negative integer, found `{}`", flag, value))?
+            }
+            "-j" | "--threads" => options.threads = positive(&flag, &value)?,
+            "-o" | "--output" => options.output = value,
+            "-f" | "--format" => {
+                format = Some(
+                    ImageFormat::from_name(&value)
+                        .ok_or_else(|| format!("unsupported output format `{}`", value))?,
+                )
+            }
+            _ => return Err(format!("unknown option `{}`", flag)),
+        }
+    }
+
+    options.format = match format.or_else(|| ImageFormat::from_path(Path::new(&options.output))) {
+        Some(format) => format,
+        None => return Err(format!("cannot guess the format of `{}`, use --format", options.output)),
+    };
+
+    Ok(Command::Render(options))
+}
+
+fn positive(flag: &str, value: &str) -> Result<usize, String> {
+    match value.parse::<usize>() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(format!("`{}` expects a positive integer, found `{}`", flag, value)),
+    }
+}
+
+//pixel centers are spread from one edge of the view to the other, which takes at least
+//two of them on each axis
+fn image_size(flag: &str, value: &str) -> Result<usize, String> {
+    match value.parse::<usize>() {
+        Ok(n) if n >= 2 => Ok(n),
+        _ => Err(format!("`{}` expects an image size of at least 2 pixels, found `{}`", flag, value)),
+    }
+}
+
+impl Options {
+    //command-line settings take precedence over the ones stored in the scene
+    pub fn apply(&self, scene: &mut Scene) {
+        let aspect_ratio = scene.image_width as f64 / scene.image_height as f64;
+        match (self.width, self.height) {
+            (Some(width), Some(height)) => {
+                scene.image_width = width;
+                scene.image_height = height;
+            }
+            (Some(width), None) => {
+                scene.image_width = width;
+                scene.image_height = ((width as f64 / aspect_ratio) as usize).max(2);
+            }
+            (None, Some(height)) => {
+                scene.image_width = ((height as f64 * aspect_ratio) as usize).max(2);
+                scene.image_height = height;
+            }
+            (None, None) => {}
+        }
+        if let Some(samples_per_pixel) = self.samples_per_pixel {
+            scene.samples_per_pixel = samples_per_pixel;
+        }
+        let limits = &mut scene.limits;
+        limits.max_depth = self.max_depth.unwrap_or(limits.max_depth);
+        limits.max_diffuse = self.max_diffuse.unwrap_or(limits.max_diffuse);
+        limits.max_specular = self.max_specular.unwrap_or(limits.max_specular);
+        limits.max_volume = self.max_volume.unwrap_or(limits.max_volume);
+        limits.rr_min_bounces = self.rr_depth.unwrap_or(limits.rr_min_bounces);
+        scene.sampler = self.sampler.unwrap_or(scene.sampler);
+        scene.color_mode = self.color_mode.unwrap_or(scene.color_mode);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::Point3;
+    use ray_tracer::hittable_list::HittableList;
+    use ray_tracer::scene::CameraSettings;
+
+    fn options(args: &[&str]) -> Result<Options, String> {
+        match parse(args.iter().map(|a| a.to_string()))? {
+            Command::Render(options) => Ok(options),
+            Command::Help => Err(String::from("help")),
+        }
+    }
+
+    #[test]
+    fn image_sizes_below_2_are_rejected() {
+        for flag in ["-W", "-H", "--width", "--height"] {
+            let error = options(&[flag, "1"]).err().unwrap();
+            assert!(error.contains("at least 2 pixels"), "{}", error);
+        }
+        let parsed = options(&["-W", "2", "-H", "3"]).unwrap();
+        assert_eq!((parsed.width, parsed.height), (Some(2), Some(3)));
+    }
+
+    #[test]
+    fn a_derived_size_is_at_least_2() {
+        let camera = CameraSettings::new(Point3::new(0.0, 0.0, 1.0), Point3::new(0.0, 0.0, 0.0), 40.0);
+        let mut scene = Scene::new(HittableList::new(), camera);
+        scene.image_width = 300;
+        scene.image_height = 100;
+        options(&["-W", "2"]).unwrap().apply(&mut scene);
+        assert_eq!((scene.image_width, scene.image_height), (2, 2));
+    }
+}
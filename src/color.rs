@@ -1,6 +1,11 @@
+use crate::environment::Environment;
 use crate::hittable::{HitRecord, Hittable};
-use crate::hittable_list::HittableList;
+use crate::light::{LightList, LightSample};
+use crate::material::ScatterKind;
+use crate::pdf::{HittablePdf, MixturePdf, Pdf};
 use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::spectrum::Wavelengths;
 use crate::utilities;
 use cgmath::*;
 
@@ -12,50 +17,256 @@ pub const BLACK: Color = Color::new(0.0, 0.0, 0.0);
 pub const WHITE: Color = Color::new(1.0, 1.0, 1.0);
 pub const SKYBLUE: Color = Color::new(0.7, 0.8, 1.0);
 
+//relative luminance of linear Rec. 709 primaries
+pub fn luminance(c: Color) -> f64 {
+    0.2126 * c.x + 0.7152 * c.y + 0.0722 * c.z
+}
 
-pub fn write_color(pixel_color: Color, sample_per_pixel: usize) -> String{
-    let mut r = pixel_color.x;
-    let mut g = pixel_color.y;
-    let mut b = pixel_color.z;
+//gamma-correct (gamma 2) and quantize a linear color for 8-bit output
+pub fn to_rgb8(pixel_color: Color) -> [u8; 3] {
+    let r = pixel_color.x.sqrt();
+    let g = pixel_color.y.sqrt();
+    let b = pixel_color.z.sqrt();
 
-    let scale = 1.0 / (sample_per_pixel as f64);
-    r = (scale * r).sqrt();
-    g = (scale * g).sqrt();
-    b = (scale * b).sqrt();
+    [
+        (256.0 * utilities::clamp(r, 0.0, 0.999)) as u8,
+        (256.0 * utilities::clamp(g, 0.0, 0.999)) as u8,
+        (256.0 * utilities::clamp(b, 0.0, 0.999)) as u8,
+    ]
+}
 
-    format!(
-        "{} {} {}\n",
-        (256.0 * utilities::clamp(r, 0.0, 0.999)) as u32,
-        (256.0 * utilities::clamp(g, 0.0, 0.999)) as u32,
-        (256.0 * utilities::clamp(b, 0.0, 0.999)) as u32
-    )
+//what a path carries: an RGB triple, or the radiance at three wavelengths (see spectrum.rs)
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ColorMode {
+    Rgb,
+    Spectral,
 }
 
-pub fn ray_color(r: &Ray, background: Color, world: &HittableList, depth: usize) -> Color {
-    let rec: HitRecord;
-    if depth == 0 {
-        return BLACK;
-    }
+impl ColorMode {
+    pub const NAMES: [&'static str; 2] = ["rgb", "spectral"];
 
-    match world.hit(r, 0.001, std::f64::INFINITY) {
-        Some(record) => {
-            rec = record;
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "rgb" => Some(ColorMode::Rgb),
+            "spectral" => Some(ColorMode::Spectral),
+            _ => None,
         }
-        None => {
-            return background;
+    }
+}
+
+//bounds on path length. Every limit counts scattering events of one kind; max_depth
+//counts all of them. Past rr_min_bounces, paths are ended by Russian roulette.
+#[derive(Clone, Copy)]
+pub struct PathLimits {
+    pub max_depth: usize,
+    pub max_diffuse: usize,
+    pub max_specular: usize,
+    pub max_volume: usize,
+    pub rr_min_bounces: usize,
+}
+
+impl Default for PathLimits {
+    fn default() -> Self {
+        PathLimits::new(20)
+    }
+}
+
+impl PathLimits {
+    pub fn new(max_depth: usize) -> Self {
+        PathLimits {
+            max_depth,
+            max_diffuse: usize::MAX,
+            max_specular: usize::MAX,
+            max_volume: usize::MAX,
+            rr_min_bounces: 3,
         }
     }
+}
+
+//power heuristic with beta = 2
+fn mis_weight(pdf: f64, other_pdf: f64) -> f64 {
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+    if a + b <= 0.0 {
+        0.0
+    } else {
+        a / (a + b)
+    }
+}
+
+//lights: the lights to sample directly, None if there are none.
+//wavelengths: given in spectral mode, where the path carries radiance at these
+//wavelengths instead of RGB. The result is RGB either way.
+pub fn ray_color(
+    r: &Ray,
+    background: &dyn Environment,
+    world: &dyn Hittable,
+    lights: Option<&LightList>,
+    limits: &PathLimits,
+    wavelengths: Option<&Wavelengths>,
+    sampler: &mut dyn Sampler,
+) -> Color {
+    //in spectral mode every color is read at the path's wavelengths, one per channel
+    let at_wavelength = |c: Color| match wavelengths {
+        Some(wavelengths) => wavelengths.spectrum(c),
+        None => c,
+    };
+    //the one that decides where dispersive surfaces send the path
+    let wavelength = wavelengths.map(|w| w.hero());
+    let mut secondaries_terminated = false;
 
-    let mut scattered = Ray::new(rec.p, rec.normal, r.time);
-    let mut attenuation = WHITE;
-    let emitted = rec.mat_ptr.emitted(rec.u, rec.v, rec.p);
+    let mut radiance = BLACK;
+    let mut throughput = WHITE;
+    let mut ray = Ray::new(r.origin, r.direction, r.time);
+    ray.wavelength = wavelength;
+    //density with which the previous diffuse bounce chose `ray`, so that emission found
+    //next can be weighted against light sampling. None for camera and specular rays.
+    let mut bsdf_pdf: Option<f64> = None;
+    let (mut diffuse_bounces, mut specular_bounces, mut volume_bounces) = (0, 0, 0);
 
-    if !rec
-        .mat_ptr
-        .scatter(r, &rec, &mut attenuation, &mut scattered)
-    {
-        return emitted;
+    for depth in 0..limits.max_depth {
+        sampler.start_bounce(depth);
+        let mut rec: HitRecord = match world.hit(&ray, 0.001, f64::INFINITY, sampler) {
+            Some(record) => record,
+            None => {
+                let mut arriving = at_wavelength(background.radiance(ray.direction));
+                if let (Some(bsdf_pdf), Some(lights)) = (bsdf_pdf, lights) {
+                    if arriving != BLACK {
+                        arriving *= mis_weight(bsdf_pdf, lights.pdf_value(ray.origin, ray.direction));
+                    }
+                }
+                radiance += throughput.mul_element_wise(arriving);
+                break;
+            }
+        };
+        while let Some(material) = rec.mat_ptr.select(&ray, &rec, sampler) {
+            rec.mat_ptr = material;
+        }
+
+        let mut emitted = at_wavelength(rec.mat_ptr.emitted(&ray, &rec));
+        if let (Some(bsdf_pdf), Some(lights)) = (bsdf_pdf, lights) {
+            if emitted != BLACK {
+                emitted *= mis_weight(bsdf_pdf, lights.pdf_value(ray.origin, ray.direction));
+            }
+        }
+        radiance += throughput.mul_element_wise(emitted);
+
+        let srec = match rec.mat_ptr.scatter(&ray, &rec, sampler) {
+            Some(srec) => srec,
+            None => break,
+        };
+
+        //other wavelengths would have taken other directions, so only the hero goes on,
+        //standing in for all three
+        if wavelength.is_some() && !secondaries_terminated && rec.mat_ptr.is_dispersive() {
+            throughput = Color::new(3.0 * throughput.x, 0.0, 0.0);
+            secondaries_terminated = true;
+        }
+
+        match srec.kind {
+            ScatterKind::Specular(specular_ray) => {
+                specular_bounces += 1;
+                if specular_bounces > limits.max_specular {
+                    break;
+                }
+                throughput = throughput.mul_element_wise(at_wavelength(srec.attenuation));
+                ray = specular_ray;
+                ray.wavelength = wavelength;
+                bsdf_pdf = None;
+            }
+            ScatterKind::Diffuse(ref bsdf) | ScatterKind::Volume(ref bsdf) => {
+                let (bounces, limit) = match srec.kind {
+                    ScatterKind::Volume(_) => (&mut volume_bounces, limits.max_volume),
+                    _ => (&mut diffuse_bounces, limits.max_diffuse),
+                };
+                *bounces += 1;
+                if *bounces > limit {
+                    break;
+                }
+
+                //a phase function is no guide to where light comes from, so in media half
+                //the continuing rays head for emissive objects; MIS shares what they find
+                //with next-event estimation through `pdf`
+                let to_shapes;
+                let mixture;
+                let pdf: &dyn Pdf = match (&srec.kind, lights) {
+                    (ScatterKind::Volume(_), Some(lights)) if !lights.shapes().objects.is_empty() => {
+                        to_shapes = HittablePdf::new(lights.shapes(), rec.p);
+                        mixture = MixturePdf::new(bsdf.as_ref(), &to_shapes);
+                        &mixture
+                    }
+                    _ => bsdf.as_ref(),
+                };
+
+                //next-event estimation: aim one ray at the lights
+                if let Some(lights) = lights {
+                    let sample = lights.sample(rec.p, sampler);
+                    let direction = match sample {
+                        LightSample::Area(direction) | LightSample::Delta { direction, .. } => direction,
+                    };
+                    let mut to_light = Ray::new(rec.p, direction, ray.time);
+                    to_light.wavelength = wavelength;
+                    let scattering = at_wavelength(rec.mat_ptr.scattering(&ray, &rec, srec.attenuation, &to_light));
+
+                    if scattering != BLACK {
+                        match sample {
+                            LightSample::Area(_) => {
+                                let light_pdf = lights.pdf_value(rec.p, to_light.direction);
+                                if light_pdf > 0.0 {
+                                    let light_emitted = match world.hit(&to_light, 0.001, f64::INFINITY, sampler) {
+                                        Some(light_rec) => light_rec.mat_ptr.emitted(&to_light, &light_rec),
+                                        None => background.radiance(to_light.direction),
+                                    };
+                                    //on the last bounce the BSDF-sampled ray is never traced, so
+                                    //light sampling has to account for the whole contribution
+                                    let weight = if depth + 1 == limits.max_depth {
+                                        1.0
+                                    } else {
+                                        mis_weight(light_pdf, pdf.value(to_light.direction))
+                                    };
+                                    radiance += at_wavelength(light_emitted)
+                                        .mul_element_wise(scattering)
+                                        .mul_element_wise(throughput)
+                                        * (weight / light_pdf);
+                                }
+                            }
+                            //the direction is a unit vector, so t is the distance
+                            LightSample::Delta { distance, radiance: arriving, .. } => {
+                                if world.hit(&to_light, 0.001, distance - 0.001, sampler).is_none() {
+                                    radiance += at_wavelength(arriving)
+                                        .mul_element_wise(scattering)
+                                        .mul_element_wise(throughput);
+                                }
+                            }
+                        }
+                    }
+                }
+
+                let mut scattered = Ray::new(rec.p, pdf.generate(sampler), ray.time);
+                scattered.wavelength = wavelength;
+                let pdf_val = pdf.value(scattered.direction);
+                if pdf_val <= 0.0 {
+                    break;
+                }
+                let scattering = at_wavelength(rec.mat_ptr.scattering(&ray, &rec, srec.attenuation, &scattered));
+
+                throughput = throughput.mul_element_wise(scattering) / pdf_val;
+                ray = scattered;
+                bsdf_pdf = Some(pdf_val);
+            }
+        }
+
+        //Russian roulette: continue with a probability that follows the throughput
+        if depth + 1 >= limits.rr_min_bounces {
+            let survive = throughput.x.max(throughput.y).max(throughput.z).min(0.95);
+            if survive <= 0.0 || utilities::random_double(sampler) >= survive {
+                break;
+            }
+            throughput /= survive;
+        }
     }
 
-    emitted + ray_color(&scattered, background, world, depth - 1).mul_element_wise(attenuation) 
+    match wavelengths {
+        Some(wavelengths) => wavelengths.to_rgb(radiance),
+        None => radiance,
+    }
 }
@@ -0,0 +1,297 @@
+//What rays that leave the scene see: a constant color, an equirectangular HDR image or
+//a procedural sky. Bright environments light the scene themselves, and are then sampled
+//like any other light through an EnvironmentLight, which draws directions from a table
+//of the environment's luminance.
+//
+//Directions map to the image as longitude and latitude: u runs around the y axis with
+//-z at the center of the image, v from straight up (v = 0) to straight down (v = 1).
+
+use crate::color::{luminance, Color};
+use crate::light::{Light, LightSample};
+use crate::sampler::Sampler;
+use crate::utilities;
+use cgmath::*;
+use image::codecs::hdr::HdrDecoder;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+const PI: f64 = std::f64::consts::PI;
+
+pub trait Environment: Send + Sync {
+    //radiance arriving from far away, seen when looking along `direction`
+    fn radiance(&self, direction: Vector3<f64>) -> Color;
+}
+
+impl Environment for Color {
+    fn radiance(&self, _direction: Vector3<f64>) -> Color {
+        *self
+    }
+}
+
+//image coordinates in [0, 1] of a direction, which need not be normalized
+pub fn direction_to_uv(direction: Vector3<f64>) -> (f64, f64) {
+    let d = utilities::unit_vector(direction);
+    (0.5 + d.x.atan2(-d.z) / (2.0 * PI), d.y.clamp(-1.0, 1.0).acos() / PI)
+}
+
+pub fn uv_to_direction(u: f64, v: f64) -> Vector3<f64> {
+    let (phi, theta) = (2.0 * PI * (u - 0.5), PI * v);
+    Vector3::new(theta.sin() * phi.sin(), theta.cos(), -theta.sin() * phi.cos())
+}
+
+//an equirectangular image of the surroundings, typically a Radiance .hdr panorama
+pub struct EnvironmentMap {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>, //row by row from the top
+    rotation: Matrix3<f64>, //from world directions to the image's
+    intensity: f64,
+}
+
+impl EnvironmentMap {
+    pub fn new(width: usize, height: usize, pixels: Vec<Color>) -> Self {
+        assert_eq!(pixels.len(), width * height, "an environment map needs width * height pixels");
+        EnvironmentMap {
+            width,
+            height,
+            pixels,
+            rotation: Matrix3::identity(),
+            intensity: 1.0,
+        }
+    }
+
+    //a Radiance .hdr file; OpenEXR cannot be read with the image crate in use
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let extension = path.extension().map(|e| e.to_string_lossy().to_lowercase());
+        match extension.as_deref() {
+            Some("hdr") | Some("pic") => {}
+            Some("exr") => return Err(String::from("OpenEXR is not supported; convert the map to Radiance .hdr")),
+            _ => return Err(String::from("environment maps must be Radiance .hdr files")),
+        }
+        let file = File::open(path).map_err(|e| e.to_string())?;
+        let decoder = HdrDecoder::new(BufReader::new(file)).map_err(|e| e.to_string())?;
+        let meta = decoder.metadata();
+        let pixels = decoder
+            .read_image_hdr()
+            .map_err(|e| e.to_string())?
+            .into_iter()
+            .map(|p| Color::new(p[0] as f64, p[1] as f64, p[2] as f64))
+            .collect();
+        Ok(EnvironmentMap::new(meta.width as usize, meta.height as usize, pixels))
+    }
+
+    //turns the surroundings about the y axis
+    pub fn with_rotation(mut self, degrees: f64) -> Self {
+        self.rotation = Matrix3::from_angle_y(Deg(-degrees));
+        self
+    }
+
+    pub fn with_intensity(mut self, intensity: f64) -> Self {
+        self.intensity = intensity;
+        self
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+}
+
+impl Environment for EnvironmentMap {
+    //the nearest pixel, so that the radiance is constant over each cell of the light's table
+    fn radiance(&self, direction: Vector3<f64>) -> Color {
+        let (u, v) = direction_to_uv(self.rotation * direction);
+        let i = ((u * self.width as f64) as usize).min(self.width - 1);
+        let j = ((v * self.height as f64) as usize).min(self.height - 1);
+        self.pixels[j * self.width + i] * self.intensity
+    }
+}
+
+//a clear sky by the model of Preetham, Shirley and Smits, "A Practical Analytic Model
+//for Daylight" (1999). The sun itself is not part of it; a directional light can stand in.
+pub struct Sky {
+    sun: Vector3<f64>,
+    //zenith luminance and chromaticity, over the Perez function toward the zenith
+    zenith: Vector3<f64>,
+    perez: [[f64; 5]; 3], //A to E, for Y, x and y
+    intensity: f64,
+}
+
+//from kcd/m², so that a clear sky comes out in the range of the scene's other lights
+const SKY_SCALE: f64 = 0.1;
+
+impl Sky {
+    //sun: the direction toward the sun. turbidity: the haziness of the air, about 2 for
+    //a very clear sky to 10 for a hazy one.
+    pub fn new(sun: Vector3<f64>, turbidity: f64) -> Self {
+        let sun = utilities::unit_vector(sun);
+        let t = turbidity.clamp(1.7, 10.0);
+        //the model is only defined for a sun above the horizon
+        let theta_s = sun.y.clamp(0.0, 1.0).acos().min(0.5 * PI - 1e-3);
+
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_s);
+        let zenith_y = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+        let (th, th2, th3) = (theta_s, theta_s * theta_s, theta_s * theta_s * theta_s);
+        let zenith_x = t * t * (0.00166 * th3 - 0.00375 * th2 + 0.00209 * th)
+            + t * (-0.02903 * th3 + 0.06377 * th2 - 0.03202 * th + 0.00394)
+            + (0.11693 * th3 - 0.21196 * th2 + 0.06052 * th + 0.25886);
+        let zenith_yc = t * t * (0.00275 * th3 - 0.00610 * th2 + 0.00317 * th)
+            + t * (-0.04214 * th3 + 0.08970 * th2 - 0.04153 * th + 0.00516)
+            + (0.15346 * th3 - 0.26756 * th2 + 0.06670 * th + 0.26688);
+
+        let perez = [
+            [0.1787 * t - 1.4630, -0.3554 * t + 0.4275, -0.0227 * t + 5.3251, 0.1206 * t - 2.5771, -0.0670 * t + 0.3703],
+            [-0.0193 * t - 0.2592, -0.0665 * t + 0.0008, -0.0004 * t + 0.2125, -0.0641 * t - 0.8989, -0.0033 * t + 0.0452],
+            [-0.0167 * t - 0.2608, -0.0950 * t + 0.0092, -0.0079 * t + 0.2102, -0.0441 * t - 1.6537, -0.0109 * t + 0.0529],
+        ];
+        let zenith = Vector3::new(zenith_y.max(0.0), zenith_x, zenith_yc);
+        let mut sky = Sky {
+            sun,
+            zenith,
+            perez,
+            intensity: 1.0,
+        };
+        for i in 0..3 {
+            sky.zenith[i] /= Sky::perez(&sky.perez[i], 0.0, theta_s);
+        }
+        sky
+    }
+
+    pub fn with_intensity(mut self, intensity: f64) -> Self {
+        self.intensity = intensity;
+        self
+    }
+
+    //the Perez distribution at zenith angle theta and angle gamma from the sun
+    fn perez(c: &[f64; 5], theta: f64, gamma: f64) -> f64 {
+        let cos_gamma = gamma.cos();
+        (1.0 + c[0] * (c[1] / theta.cos()).exp()) * (1.0 + c[2] * (c[3] * gamma).exp() + c[4] * cos_gamma * cos_gamma)
+    }
+}
+
+impl Environment for Sky {
+    //below the horizon the sky is continued by its color at the horizon, dimmed as if
+    //reflected by the ground
+    fn radiance(&self, direction: Vector3<f64>) -> Color {
+        let d = utilities::unit_vector(direction);
+        let ground = if d.y < 0.0 { 0.3 } else { 1.0 };
+        let theta = d.y.abs().acos().min(0.5 * PI - 1e-3);
+        let horizontal = Vector3::new(d.x, d.y.abs(), d.z);
+        let gamma = horizontal.dot(self.sun).clamp(-1.0, 1.0).acos();
+
+        let luminance = self.zenith[0] * Sky::perez(&self.perez[0], theta, gamma);
+        let x = self.zenith[1] * Sky::perez(&self.perez[1], theta, gamma);
+        let y = self.zenith[2] * Sky::perez(&self.perez[2], theta, gamma);
+        if y <= 0.0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+
+        //xyY to XYZ to linear sRGB
+        let xyz = Vector3::new(x / y * luminance, luminance, (1.0 - x - y) / y * luminance);
+        let xyz_to_rgb = Matrix3::new(
+            3.2404542, -0.9692660, 0.0556434, //
+            -1.5371385, 1.8760108, -0.2040259, //
+            -0.4985314, 0.0415560, 1.0572252,
+        );
+        (xyz_to_rgb * xyz).map(|c| c.max(0.0)) * (SKY_SCALE * ground * self.intensity)
+    }
+}
+
+//a piecewise-constant density on [0, 1]
+struct Distribution1D {
+    func: Vec<f64>,
+    cdf: Vec<f64>,
+    integral: f64,
+}
+
+impl Distribution1D {
+    fn new(func: Vec<f64>) -> Self {
+        let n = func.len();
+        let mut cdf = vec![0.0; n + 1];
+        for i in 0..n {
+            cdf[i + 1] = cdf[i] + func[i] / n as f64;
+        }
+        let integral = cdf[n];
+        for (i, c) in cdf.iter_mut().enumerate() {
+            //nothing to follow, so fall back to uniform
+            *c = if integral > 0.0 { *c / integral } else { i as f64 / n as f64 };
+        }
+        Distribution1D { func, cdf, integral }
+    }
+
+    //a point in [0, 1) and its density
+    fn sample(&self, u: f64) -> (f64, f64) {
+        let n = self.func.len();
+        let i = (self.cdf.partition_point(|&c| c <= u) - 1).min(n - 1);
+        let width = self.cdf[i + 1] - self.cdf[i];
+        let du = if width > 0.0 { (u - self.cdf[i]) / width } else { 0.0 };
+        ((i as f64 + du) / n as f64, self.pdf(i))
+    }
+
+    fn pdf(&self, i: usize) -> f64 {
+        if self.integral > 0.0 {
+            self.func[i] / self.integral
+        } else {
+            1.0
+        }
+    }
+
+    fn index(&self, x: f64) -> usize {
+        ((x * self.func.len() as f64) as usize).min(self.func.len() - 1)
+    }
+}
+
+//samples directions toward an environment in proportion to its luminance, from a table
+//of width x height cells. Rows near the poles cover less solid angle and are weighted by
+//sin(theta) accordingly.
+pub struct EnvironmentLight {
+    rows: Vec<Distribution1D>, //the columns of each row
+    marginal: Distribution1D, //the rows
+}
+
+impl EnvironmentLight {
+    pub fn new(environment: &dyn Environment, width: usize, height: usize) -> Self {
+        let (width, height) = (width.max(1), height.max(1));
+        let rows: Vec<Distribution1D> = (0..height)
+            .map(|j| {
+                let v = (j as f64 + 0.5) / height as f64;
+                let sin_theta = (PI * v).sin();
+                Distribution1D::new(
+                    (0..width)
+                        .map(|i| {
+                            let u = (i as f64 + 0.5) / width as f64;
+                            luminance(environment.radiance(uv_to_direction(u, v))).max(0.0) * sin_theta
+                        })
+                        .collect(),
+                )
+            })
+            .collect();
+        let marginal = Distribution1D::new(rows.iter().map(|row| row.integral).collect());
+        EnvironmentLight { rows, marginal }
+    }
+}
+
+impl Light for EnvironmentLight {
+    fn sample(&self, _origin: Point3<f64>, sampler: &mut dyn Sampler) -> LightSample {
+        let (u1, u2) = sampler.get_2d();
+        let (v, _) = self.marginal.sample(u1);
+        let (u, _) = self.rows[self.marginal.index(v)].sample(u2);
+        LightSample::Area(uv_to_direction(u, v))
+    }
+
+    fn pdf_value(&self, _origin: Point3<f64>, direction: Vector3<f64>) -> f64 {
+        let (u, v) = direction_to_uv(direction);
+        let sin_theta = (PI * v).sin();
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+        let j = self.marginal.index(v);
+        let row = &self.rows[j];
+        //the density over the image, and the area of the sphere per unit of image
+        self.marginal.pdf(j) * row.pdf(row.index(u)) / (2.0 * PI * PI * sin_theta)
+    }
+}
@@ -0,0 +1,444 @@
+//glTF 2.0 import (.gltf with external or embedded buffers, and binary .glb). The node
+//hierarchy of the default scene is walked with its transforms; each mesh is built once
+//in its own space and every node that refers to it becomes an Instance. Perspective
+//cameras become CameraSettings. Metallic-roughness materials are mapped as follows:
+//emissive -> DiffuseLight, KHR_materials_transmission -> Dielectric (with
+//KHR_materials_ior, the roughness and KHR_materials_volume absorption), otherwise
+//MetallicRoughness with the base color texture and the roughness and metallic factors
+//(their texture is not read). Skins, morph targets and animations are ignored.
+
+use crate::color::Color;
+use crate::hittable::Hittable;
+use crate::bvh::BVH;
+use crate::hittable_list::HittableList;
+use crate::instance::Instance;
+use crate::material::{Dielectric, DiffuseLight, Material, MetallicRoughness};
+use crate::mesh::{MeshData, MeshError, TriangleMesh};
+use crate::scene::{CameraSettings, Scene};
+use crate::texture::{ImageTexture, SolidColor, Texture};
+use crate::utilities;
+use cgmath::*;
+use gltf::image::Format;
+use gltf::mesh::Mode;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+pub struct GltfCamera {
+    pub settings: CameraSettings,
+    pub aspect_ratio: Option<f64>,
+}
+
+pub struct GltfScene {
+    pub world: HittableList,
+    pub cameras: Vec<GltfCamera>,
+}
+
+struct Importer {
+    images: Vec<gltf::image::Data>,
+    buffers: Vec<gltf::buffer::Data>,
+    //keyed by glTF mesh index, None for meshes without triangles
+    meshes: HashMap<usize, Option<Arc<dyn Hittable>>>,
+    //keyed by glTF material index, None for the default material
+    materials: HashMap<Option<usize>, Arc<dyn Material>>,
+    material_override: Option<Arc<dyn Material>>,
+    world: HittableList,
+    cameras: Vec<GltfCamera>,
+}
+
+//material_override replaces every material in the file when given
+pub fn load(path: &Path, material_override: Option<Arc<dyn Material>>) -> Result<GltfScene, MeshError> {
+    let (document, buffers, images) = gltf::import(path).map_err(|e| MeshError {
+        line: 0,
+        message: format!("cannot read {}: {}", path.display(), e),
+    })?;
+
+    let mut importer = Importer {
+        images,
+        buffers,
+        meshes: HashMap::new(),
+        materials: HashMap::new(),
+        material_override,
+        world: HittableList::new(),
+        cameras: Vec::new(),
+    };
+
+    let scene = document.default_scene().or_else(|| document.scenes().next());
+    match scene {
+        Some(scene) => {
+            for node in scene.nodes() {
+                importer.node(&node, Matrix4::identity())?;
+            }
+        }
+        None => {
+            return Err(MeshError {
+                line: 0,
+                message: String::from("the file contains no scene"),
+            })
+        }
+    }
+
+    if importer.world.objects.is_empty() {
+        return Err(MeshError {
+            line: 0,
+            message: String::from("the scene contains no triangles"),
+        });
+    }
+    Ok(GltfScene {
+        world: importer.world,
+        cameras: importer.cameras,
+    })
+}
+
+//a whole renderable scene, seen through the file's first camera. Without a camera the
+//view is placed in front of the scene's bounding box.
+pub fn load_scene(path: &Path) -> Result<Scene, MeshError> {
+    let gltf = load(path, None)?;
+
+    let mut camera = gltf.cameras.into_iter().next();
+    let settings = match camera.take() {
+        Some(camera) => camera,
+        None => {
+            let mut bound = crate::aabb::AABB::empty();
+            gltf.world.bounding_box(0.0, 1.0, &mut bound);
+            let center = bound.centroid();
+            let radius = (bound.max() - bound.min()).magnitude() / 2.0;
+            let fov = 40.0;
+            let distance = radius / utilities::degrees_to_radians(fov / 2.0).sin();
+            GltfCamera {
+                settings: CameraSettings::new(center + Vector3::new(0.0, 0.0, distance), center, fov),
+                aspect_ratio: None,
+            }
+        }
+    };
+
+    let mut scene = Scene::new(gltf.world, settings.settings);
+    if let Some(aspect_ratio) = settings.aspect_ratio {
+        scene.image_height = ((scene.image_width as f64 / aspect_ratio) as usize).max(2);
+    }
+    Ok(scene)
+}
+
+impl Importer {
+    fn node(&mut self, node: &gltf::Node, parent: Matrix4<f64>) -> Result<(), MeshError> {
+        let transform = parent * matrix(node.transform().matrix());
+
+        if let Some(mesh) = node.mesh() {
+            if let Some(prototype) = self.mesh(&mesh)? {
+                //a scale of 0 is sometimes used to hide a node
+                if let Ok(instance) = Instance::new(prototype, transform, None) {
+                    self.world.add(Box::new(instance));
+                }
+            }
+        }
+
+        if let Some(camera) = node.camera() {
+            if let gltf::camera::Projection::Perspective(perspective) = camera.projection() {
+                //glTF cameras look down -z with +y up
+                let look_from = Point3::from_homogeneous(transform * Vector4::new(0.0, 0.0, 0.0, 1.0));
+                let forward = (transform * Vector4::new(0.0, 0.0, -1.0, 0.0)).truncate();
+                let vup = (transform * Vector4::new(0.0, 1.0, 0.0, 0.0)).truncate();
+                let fov = perspective.yfov() as f64 * 180.0 / std::f64::consts::PI;
+
+                let mut settings = CameraSettings::new(look_from, look_from + forward, fov);
+                settings.vup = vup;
+                self.cameras.push(GltfCamera {
+                    settings,
+                    aspect_ratio: perspective.aspect_ratio().map(|a| a as f64),
+                });
+            }
+        }
+
+        for child in node.children() {
+            self.node(&child, transform)?;
+        }
+        Ok(())
+    }
+
+    //all primitives of a mesh, shared by every node that uses it
+    fn mesh(&mut self, mesh: &gltf::Mesh) -> Result<Option<Arc<dyn Hittable>>, MeshError> {
+        if let Some(prototype) = self.meshes.get(&mesh.index()) {
+            return Ok(prototype.clone());
+        }
+
+        let mut primitives = HittableList::new();
+        for primitive in mesh.primitives() {
+            if let Some(data) = self.primitive(&primitive)? {
+                let material = self.material(&primitive.material())?;
+                primitives.add(Box::new(TriangleMesh::new(data, material)));
+            }
+        }
+        let prototype: Option<Arc<dyn Hittable>> = match primitives.objects.len() {
+            0 => None,
+            1 => primitives.objects.pop(),
+            _ => Some(Arc::new(BVH::new(primitives, 0.0, 1.0))),
+        };
+
+        self.meshes.insert(mesh.index(), prototype.clone());
+        Ok(prototype)
+    }
+
+    //None for primitives without triangles (points and lines)
+    fn primitive(&self, primitive: &gltf::Primitive) -> Result<Option<MeshData>, MeshError> {
+        let buffers = &self.buffers;
+        let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+
+        let mut data = MeshData::default();
+        match reader.read_positions() {
+            Some(positions) => {
+                data.positions = positions
+                    .map(|p| Point3::new(p[0] as f64, p[1] as f64, p[2] as f64))
+                    .collect()
+            }
+            None => return Ok(None),
+        }
+        let vertex_count = data.positions.len() as u32;
+
+        if let Some(normals) = reader.read_normals() {
+            data.normals = normals
+                .map(|n| Vector3::new(n[0] as f64, n[1] as f64, n[2] as f64))
+                .collect();
+        }
+
+        //the texture coordinate set used by the base color texture
+        let set = primitive
+            .material()
+            .pbr_metallic_roughness()
+            .base_color_texture()
+            .map_or(0, |info| info.tex_coord());
+        if let Some(uvs) = reader.read_tex_coords(set) {
+            //glTF puts v = 0 at the top of the image, ImageTexture at the bottom
+            data.uvs = uvs.into_f32().map(|uv| (uv[0] as f64, 1.0 - uv[1] as f64)).collect();
+        }
+
+        let indices: Vec<u32> = match reader.read_indices() {
+            Some(indices) => indices.into_u32().collect(),
+            None => (0..vertex_count).collect(),
+        };
+        data.indices = match primitive.mode() {
+            Mode::Triangles => indices.chunks_exact(3).map(|t| [t[0], t[1], t[2]]).collect(),
+            Mode::TriangleStrip => (2..indices.len())
+                .map(|i| {
+                    if i % 2 == 0 {
+                        [indices[i - 2], indices[i - 1], indices[i]]
+                    } else {
+                        [indices[i - 1], indices[i - 2], indices[i]]
+                    }
+                })
+                .collect(),
+            Mode::TriangleFan => (2..indices.len())
+                .map(|i| [indices[0], indices[i - 1], indices[i]])
+                .collect(),
+            _ => return Ok(None),
+        };
+        if data.indices.iter().flatten().any(|&i| i >= vertex_count) {
+            return Err(MeshError {
+                line: 0,
+                message: String::from("a primitive refers to a vertex that does not exist"),
+            });
+        }
+
+        if data.indices.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(data))
+    }
+
+    fn material(&mut self, material: &gltf::Material) -> Result<Arc<dyn Material>, MeshError> {
+        if let Some(material) = &self.material_override {
+            return Ok(Arc::clone(material));
+        }
+        if let Some(material) = self.materials.get(&material.index()) {
+            return Ok(Arc::clone(material));
+        }
+
+        let pbr = material.pbr_metallic_roughness();
+        let [r, g, b, _] = pbr.base_color_factor();
+        let base_color = Color::new(r as f64, g as f64, b as f64);
+        let strength = material.emissive_strength().unwrap_or(1.0) as f64;
+        let [er, eg, eb] = material.emissive_factor();
+        let emission = Color::new(er as f64, eg as f64, eb as f64) * strength;
+        let transmission = material.transmission().map_or(0.0, |t| t.transmission_factor());
+
+        let result: Arc<dyn Material> = if emission.x.max(emission.y).max(emission.z) > 0.0 {
+            Arc::new(DiffuseLight::new(Arc::new(SolidColor::new(emission))))
+        } else if transmission > 0.5 {
+            let mut dielectric =
+                Dielectric::new(material.ior().unwrap_or(1.5) as f64).with_roughness(pbr.roughness_factor() as f64);
+            //KHR_materials_volume gives the color that remains after the attenuation distance
+            if let Some(volume) = material.volume() {
+                let [ar, ag, ab] = volume.attenuation_color();
+                let distance = volume.attenuation_distance() as f64;
+                if distance.is_finite() && distance > 0.0 {
+                    let remaining = Color::new(ar as f64, ag as f64, ab as f64);
+                    dielectric = dielectric.with_absorption(remaining.map(|c| -c.max(1e-6).ln() / distance));
+                }
+            }
+            Arc::new(dielectric)
+        } else {
+            let albedo: Arc<dyn Texture> = match pbr.base_color_texture() {
+                Some(info) => Arc::new(self.texture(info.texture().source().index(), base_color)?),
+                None => Arc::new(SolidColor::new(base_color)),
+            };
+            let roughness = pbr.roughness_factor() as f64;
+            let metallic = pbr.metallic_factor() as f64;
+            Arc::new(MetallicRoughness::new(
+                albedo,
+                Arc::new(SolidColor::new(Color::new(roughness, roughness, roughness))),
+                Arc::new(SolidColor::new(Color::new(metallic, metallic, metallic))),
+            ))
+        };
+
+        self.materials.insert(material.index(), Arc::clone(&result));
+        Ok(result)
+    }
+
+    //the image as 8-bit RGB, multiplied by the material's base color factor
+    fn texture(&self, image: usize, factor: Color) -> Result<ImageTexture, MeshError> {
+        let image = &self.images[image];
+        let channels = match image.format {
+            Format::R8 => 1,
+            Format::R8G8 => 2,
+            Format::R8G8B8 => 3,
+            Format::R8G8B8A8 => 4,
+            format => {
+                return Err(MeshError {
+                    line: 0,
+                    message: format!("unsupported texture format {:?}", format),
+                })
+            }
+        };
+
+        let scale = [factor.x, factor.y, factor.z];
+        let mut data = Vec::with_capacity(image.width as usize * image.height as usize * 3);
+        for pixel in image.pixels.chunks_exact(channels) {
+            for (c, s) in scale.iter().enumerate() {
+                //grey images repeat their only channel
+                let value = if channels < 3 { pixel[0] } else { pixel[c] };
+                data.push((value as f64 * s).round().min(255.0) as u8);
+            }
+        }
+        Ok(ImageTexture::from_rgb8(data, image.width as usize, image.height as usize))
+    }
+}
+
+//glTF matrices are column-major arrays of f32
+fn matrix(m: [[f32; 4]; 4]) -> Matrix4<f64> {
+    Matrix4::from(m.map(|column| column.map(|x| x as f64)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::HitRecord;
+    use crate::material::{Lambertian, ScatterKind};
+    use crate::ray::Ray;
+    use crate::sampler::IndependentSampler;
+
+    fn model(name: &str) -> std::path::PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes/models").join(name)
+    }
+
+    //what a ray straight down through (x, z) hits first, starting at height y
+    fn hit_below(world: &HittableList, x: f64, y: f64, z: f64) -> HitRecord {
+        let ray = Ray::new(Point3::new(x, y, z), Vector3::new(0.0, -1.0, 0.0), 0.0);
+        world
+            .hit(&ray, 0.001, f64::INFINITY, &mut IndependentSampler::new(1))
+            .expect("the ray should hit the model")
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-6
+    }
+
+    #[test]
+    fn lantern_nodes_are_placed_through_the_hierarchy() {
+        let gltf = load(&model("lantern.gltf"), None).unwrap();
+        let turn = Matrix4::from_angle_y(Deg(30.0)); //the table's rotation
+        let on_table = |x: f64, z: f64| Point3::new(0.0, 0.5, 0.0) + turn.transform_vector(Vector3::new(x, 0.0, z));
+
+        //the ground sits at the root, untransformed
+        assert!(close(hit_below(&gltf.world, 3.0, 5.0, 3.0).p.y, 0.0));
+        //unit cubes under the table: translated, rotated with it, and scaled by their own node
+        let crate_center = on_table(-1.2, 0.0);
+        assert!(close(hit_below(&gltf.world, crate_center.x, 5.0, crate_center.z).p.y, 1.0));
+        let small_center = on_table(0.2, 1.0);
+        assert!(close(hit_below(&gltf.world, small_center.x, 5.0, small_center.z).p.y, 0.3 + 0.3));
+        let ingot_center = on_table(1.3, -0.3);
+        assert!(close(hit_below(&gltf.world, ingot_center.x, 5.0, ingot_center.z).p.y, 0.25 + 0.25));
+        //the lamp, three levels down: the lantern on the table, the lamp turned inside it,
+        //and the glow scaled to 0.3 inside the lamp
+        let lamp = on_table(0.02, -0.6);
+        assert!(close(hit_below(&gltf.world, lamp.x, 5.0, lamp.z).p.y, 1.6 + 0.6 - 0.02));
+        assert!(close(hit_below(&gltf.world, lamp.x, 1.9, lamp.z).p.y, 1.6 + 0.18 - 0.02));
+    }
+
+    #[test]
+    fn lantern_camera_is_imported() {
+        let gltf = load(&model("lantern.gltf"), None).unwrap();
+        assert_eq!(gltf.cameras.len(), 1);
+        let camera = &gltf.cameras[0];
+        let settings = &camera.settings;
+        assert!((settings.look_from - Point3::new(0.0, 3.0, 7.0)).magnitude() < 1e-6);
+        let forward = utilities::unit_vector(settings.look_at - settings.look_from);
+        assert!((forward - Vector3::new(0.0, -0.3363364, -0.9417419)).magnitude() < 1e-6);
+        assert!((utilities::unit_vector(settings.vup) - Vector3::new(0.0, 0.9417419, -0.3363364)).magnitude() < 1e-6);
+        assert!((settings.fov - 35.0).abs() < 1e-4);
+        assert!(close(camera.aspect_ratio.unwrap(), 1.5));
+
+        let scene = load_scene(&model("lantern.gltf")).unwrap();
+        assert_eq!(scene.image_height, (scene.image_width as f64 / 1.5) as usize);
+    }
+
+    #[test]
+    fn lantern_materials_are_mapped() {
+        let gltf = load(&model("lantern.gltf"), None).unwrap();
+        let down = Ray::new(Point3::new(0.0, 5.0, 0.0), Vector3::new(0.0, -1.0, 0.0), 0.0);
+        let up = |rec: &HitRecord| Ray::new(rec.p, Vector3::new(0.0, 1.0, 0.0), 0.0);
+        let mut sampler = IndependentSampler::new(1);
+
+        //gold: fully metallic, so head-on it reflects the base color and nothing diffuse
+        let turn = Matrix4::from_angle_y(Deg(30.0));
+        let ingot = turn.transform_vector(Vector3::new(1.3, 0.0, -0.3));
+        let lamp = turn.transform_vector(Vector3::new(0.02, 0.0, -0.6));
+        let gold = hit_below(&gltf.world, ingot.x, 5.0, ingot.z);
+        assert!(!gold.mat_ptr.is_emissive());
+        let reflected = gold.mat_ptr.scattering(&down, &gold, Color::new(1.0, 1.0, 1.0), &up(&gold));
+        assert!(close(reflected.y / reflected.x, 0.76) && close(reflected.z / reflected.x, 0.33));
+
+        //glass: transmission becomes a dielectric
+        let glass = hit_below(&gltf.world, lamp.x, 5.0, lamp.z);
+        let scattered = glass.mat_ptr.scatter(&down, &glass, &mut sampler).unwrap();
+        assert!(matches!(scattered.kind, ScatterKind::Specular(_)));
+
+        //glow: emissive factor times KHR_materials_emissive_strength
+        let glow = hit_below(&gltf.world, lamp.x, 1.9, lamp.z);
+        assert!(glow.mat_ptr.is_emissive());
+        let emitted = glow.mat_ptr.emitted(&down, &glow);
+        assert!((emitted - Color::new(8.0, 6.4, 4.0)).magnitude() < 1e-5);
+    }
+
+    #[test]
+    fn crate_glb_loads_with_and_without_an_override() {
+        let gltf = load(&model("crate.glb"), None).unwrap();
+        assert!(gltf.cameras.is_empty());
+        let mut bound = crate::aabb::AABB::empty();
+        assert!(gltf.world.bounding_box(0.0, 1.0, &mut bound));
+        assert!((bound.min() - Point3::new(-0.5, -0.5, -0.5)).magnitude() < 1e-3);
+        assert!((bound.max() - Point3::new(0.5, 0.5, 0.5)).magnitude() < 1e-3);
+        let textured = hit_below(&gltf.world, 0.1, 5.0, 0.1);
+        assert!(close(textured.p.y, 0.5));
+        let down = Ray::new(Point3::new(0.1, 5.0, 0.1), Vector3::new(0.0, -1.0, 0.0), 0.0);
+        let scattered = textured.mat_ptr.scatter(&down, &textured, &mut IndependentSampler::new(1)).unwrap();
+        assert!(matches!(scattered.kind, ScatterKind::Diffuse(_)));
+
+        let steel: Arc<dyn Material> = Arc::new(Lambertian::new(Color::new(0.7, 0.7, 0.75)));
+        let gltf = load(&model("crate.glb"), Some(Arc::clone(&steel))).unwrap();
+        assert!(Arc::ptr_eq(&hit_below(&gltf.world, 0.1, 5.0, 0.1).mat_ptr, &steel));
+
+        //without a camera, the view is placed in front of the box
+        let scene = load_scene(&model("crate.glb")).unwrap();
+        assert!((scene.camera.look_at - Point3::new(0.0, 0.0, 0.0)).magnitude() < 1e-6);
+        assert!(scene.camera.look_from.z > 0.5);
+    }
+}
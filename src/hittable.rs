@@ -1,14 +1,77 @@
 use crate::aabb::AABB;
+use crate::color::Color;
 use crate::material::Material;
 use crate::ray::Ray;
-use std::rc::Rc;
+use crate::sampler::Sampler;
+use std::sync::Arc;
 use cgmath::*;
 //use crate::sphere::Sphere;
 
 //Trait Hittable
-pub trait Hittable {
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord>;
+pub trait Hittable: Send + Sync {
+    //the sampler is only consumed by objects that intersect stochastically, like media
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, sampler: &mut dyn Sampler) -> Option<HitRecord>;
     fn bounding_box(&self, time0: f64, time1: f64, output_box: &mut AABB) -> bool;
+
+    //solid-angle density of `random` choosing `direction` from `origin`
+    fn pdf_value(&self, _origin: Point3<f64>, _direction: Vector3<f64>) -> f64 {
+        0.0
+    }
+
+    //a direction from `origin` toward a random point on the object
+    fn random(&self, _origin: Point3<f64>, _sampler: &mut dyn Sampler) -> Vector3<f64> {
+        Vector3::new(1.0, 0.0, 0.0)
+    }
+
+    //the surface area, for objects that can spread a light's power over it
+    fn area(&self) -> Option<f64> {
+        None
+    }
+
+    //the surface area after the linear map m. Flat surfaces, and objects made of them,
+    //know it for any m; curved ones only for rotations and uniform scales.
+    fn transformed_area(&self, m: &Matrix3<f64>) -> Option<f64> {
+        let s = uniform_scale(m)?;
+        Some(self.area()? * s * s)
+    }
+}
+
+//s if m is a rotation, possibly with a reflection, scaled by s in every direction
+pub fn uniform_scale(m: &Matrix3<f64>) -> Option<f64> {
+    let (a, b, c) = (m.x.magnitude2(), m.y.magnitude2(), m.z.magnitude2());
+    let tolerance = 1e-9 * a.max(b).max(c);
+    let orthogonal = m.x.dot(m.y).abs() <= tolerance && m.y.dot(m.z).abs() <= tolerance && m.z.dot(m.x).abs() <= tolerance;
+    if orthogonal && (a - b).abs() <= tolerance && (b - c).abs() <= tolerance {
+        Some(a.sqrt())
+    } else {
+        None
+    }
+}
+
+impl<T: Hittable + ?Sized> Hittable for Arc<T> {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, sampler: &mut dyn Sampler) -> Option<HitRecord> {
+        (**self).hit(r, t_min, t_max, sampler)
+    }
+
+    fn bounding_box(&self, time0: f64, time1: f64, output_box: &mut AABB) -> bool {
+        (**self).bounding_box(time0, time1, output_box)
+    }
+
+    fn pdf_value(&self, origin: Point3<f64>, direction: Vector3<f64>) -> f64 {
+        (**self).pdf_value(origin, direction)
+    }
+
+    fn random(&self, origin: Point3<f64>, sampler: &mut dyn Sampler) -> Vector3<f64> {
+        (**self).random(origin, sampler)
+    }
+
+    fn area(&self) -> Option<f64> {
+        (**self).area()
+    }
+
+    fn transformed_area(&self, m: &Matrix3<f64>) -> Option<f64> {
+        (**self).transformed_area(m)
+    }
 }
 
 //HitRecord
@@ -19,18 +82,18 @@ pub struct HitRecord {
     pub u: f64,
     pub v: f64, //u and v are the surface coordinates
     pub front_face: bool,
-    pub mat_ptr: Rc<dyn Material>,
+    pub mat_ptr: Arc<dyn Material>,
+    pub color: Option<Color>, //interpolated vertex color, for meshes that carry one
 }
 
 impl HitRecord {
     pub fn new(
         t: f64,
-        p: Point3<f64>,
         r: &Ray,
         outward_normal: Vector3<f64>,
         u: f64,
         v: f64,
-        mat_ptr: Rc<dyn Material>,
+        mat_ptr: Arc<dyn Material>,
     ) -> Self {
 
         let mut rec = HitRecord {
@@ -41,6 +104,7 @@ impl HitRecord {
             mat_ptr,
             u,
             v,
+            color: None,
         };
 
         rec.set_face_normal(r, outward_normal);
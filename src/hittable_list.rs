@@ -1,16 +1,22 @@
 use crate::aabb::AABB;
 use crate::color::Color;
+use crate::bvh::BVH;
 use crate::hittable::{HitRecord, Hittable};
+use crate::instance::Instance;
 use crate::material::{Dielectric, DiffuseLight, Lambertian, Material, Metal};
-use crate::objects::{Cubic, MovingSphere, RotateY, Sphere, XyRect, XzRect, YzRect, Translate, ConstantMedium};
+use crate::objects::{Cubic, MovingSphere, Sphere, XyRect, XzRect, YzRect, ConstantMedium};
 use crate::ray::*;
+use crate::sampler::Sampler;
 use crate::texture::{CheckerTexture, NoiseTexture, SolidColor, ImageTexture};
+use crate::tlas::TLAS;
+use crate::transform::Transform;
 use crate::utilities;
-use std::rc::Rc;
+use std::sync::Arc;
 use cgmath::*;
 
+#[derive(Default)]
 pub struct HittableList {
-    pub objects: Vec<Box<dyn Hittable>>,
+    pub objects: Vec<Arc<dyn Hittable>>,
 }
 
 impl HittableList {
@@ -21,18 +27,23 @@ impl HittableList {
     }
 
     pub fn add(&mut self, object: Box<dyn Hittable>) {
+        self.objects.push(Arc::from(object));
+    }
+
+    //for objects that also belong to another list, such as lights
+    pub fn add_shared(&mut self, object: Arc<dyn Hittable>) {
         self.objects.push(object);
     }
 }
 
 impl Hittable for HittableList {
 
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, sampler: &mut dyn Sampler) -> Option<HitRecord> {
         let mut rec = None;
         let mut closest_so_far = t_max;
 
         for object in self.objects.iter() {
-            if let Some(record) = object.hit(r, t_min, closest_so_far) {
+            if let Some(record) = object.hit(r, t_min, closest_so_far, sampler) {
                 closest_so_far = record.t;
                 rec = Some(record);
             }
@@ -42,7 +53,7 @@ impl Hittable for HittableList {
     }
 
     fn bounding_box(&self, time0: f64, time1: f64, output_box: &mut AABB) -> bool {
-        if self.objects.len() == 0 {
+        if self.objects.is_empty() {
             return false;
         }
 
@@ -50,49 +61,63 @@ impl Hittable for HittableList {
         let mut first_box = true;
 
         for object in self.objects.iter() {
-            if object.bounding_box(time0, time1, &mut temp_box) {
+            if !object.bounding_box(time0, time1, &mut temp_box) {
                 return false;
             }
             *output_box = if first_box {
                 temp_box.clone()
             } else {
-                AABB::surrounding_box(&output_box, &temp_box)
+                AABB::surrounding_box(output_box, &temp_box)
             };
             first_box = false;
         }
 
         true
     }
+
+    //each object is picked with equal probability
+    fn pdf_value(&self, origin: Point3<f64>, direction: Vector3<f64>) -> f64 {
+        let weight = 1.0 / self.objects.len() as f64;
+        self.objects
+            .iter()
+            .map(|object| weight * object.pdf_value(origin, direction))
+            .sum()
+    }
+
+    fn random(&self, origin: Point3<f64>, sampler: &mut dyn Sampler) -> Vector3<f64> {
+        let index = utilities::random_int_with_bounds(sampler, 0, self.objects.len() as i32);
+        self.objects[index as usize].random(origin, sampler)
+    }
 }
 
-pub fn random_scene() -> HittableList {
+pub fn random_scene(sampler: &mut dyn Sampler) -> HittableList {
     let mut world = HittableList::new();
 
     let checker = CheckerTexture::new(Box::new(SolidColor::new(Color::new(0.2, 0.3, 0.1))), Box::new(SolidColor::new(Color::new(0.9, 0.9, 0.9))));
     world.add(Box::new(Sphere::new(
         Point3::new(0.0, -1000.0, 0.0),
         1000.0,
-        Rc::new(Lambertian::new_texture(Rc::new(checker))),
+        Arc::new(Lambertian::new_texture(Arc::new(checker))),
     )));
 
     for a in -11..11 {
         for b in -11..11 {
-            let choose_mat = utilities::random_double();
+            let choose_mat = utilities::random_double(sampler);
             let center = Point3::new(
-                a as f64 + 0.9 * utilities::random_double(),
+                a as f64 + 0.9 * utilities::random_double(sampler),
                 0.2,
-                b as f64 + 0.9 * utilities::random_double(),
+                b as f64 + 0.9 * utilities::random_double(sampler),
             );
 
             if (center - Point3::new(4.0, 0.2, 0.0)).magnitude() > 0.9 {
-                let sphere_material: Rc<dyn Material>;
+                let sphere_material: Arc<dyn Material>;
 
                 if choose_mat < 0.8 {
                     //diffuse
-                    let albedo = utilities::random().mul_element_wise(utilities::random());
-                    sphere_material = Rc::new(Lambertian::new(albedo));
+                    let albedo = utilities::random(sampler).mul_element_wise(utilities::random(sampler));
+                    sphere_material = Arc::new(Lambertian::new(albedo));
                     let center2 = center
-                        + Vector3::new(0.0, utilities::random_double_with_bounds(0.0, 0.5), 0.0);
+                        + Vector3::new(0.0, utilities::random_double_with_bounds(sampler, 0.0, 0.5), 0.0);
                     world.add(Box::new(MovingSphere::new(
                         center,
                         center2,
@@ -102,33 +127,33 @@ pub fn random_scene() -> HittableList {
                         sphere_material,
                     )));
                 } else if choose_mat < 0.95 {
-                    let albedo = utilities::random_vec3(0.5, 1.0);
-                    let fuzz = utilities::random_double_with_bounds(0.0, 0.5);
-                    sphere_material = Rc::new(Metal::new(albedo, fuzz));
+                    let albedo = utilities::random_vec3(sampler, 0.5, 1.0);
+                    let fuzz = utilities::random_double_with_bounds(sampler, 0.0, 0.5);
+                    sphere_material = Arc::new(Metal::new(albedo, fuzz));
                     world.add(Box::new(Sphere::new(center, 0.2, sphere_material)));
                 } else {
-                    sphere_material = Rc::new(Dielectric::new(1.5));
+                    sphere_material = Arc::new(Dielectric::new(1.5));
                     world.add(Box::new(Sphere::new(center, 0.2, sphere_material)));
                 }
             }
         }
     }
 
-    let material_1 = Rc::new(Dielectric::new(1.5));
+    let material_1 = Arc::new(Dielectric::new(1.5));
     world.add(Box::new(Sphere::new(
         Point3::new(0.0, 1.0, 0.0),
         1.0,
         material_1,
     )));
 
-    let material_2 = Rc::new(Lambertian::new(Color::new(0.4, 0.2, 0.1)));
+    let material_2 = Arc::new(Lambertian::new(Color::new(0.4, 0.2, 0.1)));
     world.add(Box::new(Sphere::new(
         Point3::new(-4.0, 1.0, 0.0),
         1.0,
         material_2,
     )));
 
-    let material_3 = Rc::new(Metal::new(Color::new(0.7, 0.6, 0.5), 0.0));
+    let material_3 = Arc::new(Metal::new(Color::new(0.7, 0.6, 0.5), 0.0));
     world.add(Box::new(Sphere::new(
         Point3::new(4.0, 1.0, 0.0),
         1.0,
@@ -146,80 +171,85 @@ pub fn two_spheres() -> HittableList {
     objects.add(Box::new(Sphere::new(
         Point3::new(0.0, -10.0, 0.0),
         10.0,
-        Rc::new(Lambertian::new_texture(Rc::new(checker1))),
+        Arc::new(Lambertian::new_texture(Arc::new(checker1))),
     )));
 
     objects.add(Box::new(Sphere::new(
         Point3::new(0.0, 10.0, 0.0),
         10.0,
-        Rc::new(Lambertian::new_texture(Rc::new(checker2))),
+        Arc::new(Lambertian::new_texture(Arc::new(checker2))),
     )));
 
     objects
 }
 
-pub fn simple_light() -> HittableList {
+//returns the world and the lights in it
+pub fn simple_light(sampler: &mut dyn Sampler) -> (HittableList, HittableList) {
     let mut objects = HittableList::new();
+    let mut lights = HittableList::new();
 
     objects.add(Box::new(Sphere::new(
         Point3::new(0.0, -1000.0, 0.0),
         1000.0,
-        Rc::new(Lambertian::new_texture(Rc::new(NoiseTexture::new(4.0)))),
+        Arc::new(Lambertian::new_texture(Arc::new(NoiseTexture::new(4.0, sampler)))),
     )));
     objects.add(Box::new(Sphere::new(
         Point3::new(0.0, 2.0, 0.0),
         2.0,
-        Rc::new(Lambertian::new_texture(Rc::new(NoiseTexture::new(4.0)))),
+        Arc::new(Lambertian::new_texture(Arc::new(NoiseTexture::new(4.0, sampler)))),
     )));
 
     let light = Color::new(15.0, 15.0, 15.0);
-    let difflight = Rc::new(DiffuseLight::new(Rc::new(SolidColor::new(light))));
-    objects.add(Box::new(XyRect::new(3.0, 5.0, 1.0, 3.0, -2.0, difflight)));
+    let difflight = Arc::new(DiffuseLight::new(Arc::new(SolidColor::new(light))));
+    let light: Arc<dyn Hittable> = Arc::new(XyRect::new(3.0, 5.0, 1.0, 3.0, -2.0, difflight));
+    objects.add_shared(Arc::clone(&light));
+    lights.add_shared(light);
 
-    objects
+    (objects, lights)
 }
 
-pub fn cornell_box() -> HittableList {
+//returns the world and the lights in it
+pub fn cornell_box() -> (HittableList, HittableList) {
     let mut objects = HittableList::new();
+    let mut lights = HittableList::new();
 
     let red = Color::new(0.65, 0.05, 0.05);
     let white = Color::new(0.73, 0.73, 0.73);
     let green = Color::new(0.12, 0.45, 0.15);
     let light = Color::new(15.0, 15.0, 15.0);
 
-    objects.add(Box::new(YzRect::new(0.0, 555.0, 0.0, 555.0, 555.0, Rc::new(Lambertian::new(green)))));
-    objects.add(Box::new(YzRect::new(0.0, 555.0, 0.0, 555.0, 0.0,   Rc::new(Lambertian::new(red)))));
-    objects.add(Box::new(XzRect::new(213.0, 343.0, 227.0, 332.0, 554.0, Rc::new(DiffuseLight::new(Rc::new(SolidColor::new(light)))))));
-    objects.add(Box::new(XzRect::new(0.0, 555.0, 0.0, 555.0, 0.0, Rc::new(Lambertian::new(white)))));
-    objects.add(Box::new(XzRect::new(0.0, 555.0, 0.0, 555.0, 555.0, Rc::new(Lambertian::new(white)))));
-    objects.add(Box::new(XyRect::new(0.0, 555.0, 0.0, 555.0, 555.0, Rc::new(Lambertian::new(white)))));
-
-    let cub1 = Cubic::new(Point3::new(0.0, 0.0, 0.0), Point3::new(165.0, 330.0, 165.0), Rc::new(Lambertian::new(white)));
-    let cub2 = Cubic::new(Point3::new(0.0, 0.0, 0.0), Point3::new(165.0, 165.0, 165.0), Rc::new(Lambertian::new(white)));
+    objects.add(Box::new(YzRect::new(0.0, 555.0, 0.0, 555.0, 555.0, Arc::new(Lambertian::new(green)))));
+    objects.add(Box::new(YzRect::new(0.0, 555.0, 0.0, 555.0, 0.0,   Arc::new(Lambertian::new(red)))));
+    let light: Arc<dyn Hittable> = Arc::new(XzRect::new(213.0, 343.0, 227.0, 332.0, 554.0, Arc::new(DiffuseLight::new(Arc::new(SolidColor::new(light))))));
+    objects.add_shared(Arc::clone(&light));
+    lights.add_shared(light);
+    objects.add(Box::new(XzRect::new(0.0, 555.0, 0.0, 555.0, 0.0, Arc::new(Lambertian::new(white)))));
+    objects.add(Box::new(XzRect::new(0.0, 555.0, 0.0, 555.0, 555.0, Arc::new(Lambertian::new(white)))));
+    objects.add(Box::new(XyRect::new(0.0, 555.0, 0.0, 555.0, 555.0, Arc::new(Lambertian::new(white)))));
 
-    let rotated_cub1 = RotateY::new(Box::new(cub1), 18.0);
-    let rotated_cub2 = RotateY::new(Box::new(cub2), -15.0);
+    let cub1 = Cubic::new(Point3::new(0.0, 0.0, 0.0), Point3::new(165.0, 330.0, 165.0), Arc::new(Lambertian::new(white)));
+    let cub2 = Cubic::new(Point3::new(0.0, 0.0, 0.0), Point3::new(165.0, 165.0, 165.0), Arc::new(Lambertian::new(white)));
 
-    let translated_cub1 = Translate::new(Box::new(rotated_cub1), Vector3::new(265.0, 0.0, 295.0));
-    let translated_cub2 = Translate::new(Box::new(rotated_cub2), Vector3::new(130.0, 0.0, 65.0));
+    let placed_cub1 = Transform::new(Arc::new(cub1), Matrix4::from_translation(Vector3::new(265.0, 0.0, 295.0)) * Matrix4::from_angle_y(Deg(18.0))).unwrap();
+    let placed_cub2 = Transform::new(Arc::new(cub2), Matrix4::from_translation(Vector3::new(130.0, 0.0, 65.0)) * Matrix4::from_angle_y(Deg(-15.0))).unwrap();
 
-    objects.add(Box::new(translated_cub1));
-    objects.add(Box::new(translated_cub2));
-    objects
+    objects.add(Box::new(placed_cub1));
+    objects.add(Box::new(placed_cub2));
+    (objects, lights)
 }
 
-pub fn two_perlin_spheres() -> HittableList {
+pub fn two_perlin_spheres(sampler: &mut dyn Sampler) -> HittableList {
     let mut objects = HittableList::new();
 
     objects.add(Box::new(Sphere::new(
         Point3::new(0.0, -1000.0, 0.0),
         1000.0,
-        Rc::new(Lambertian::new_texture(Rc::new(NoiseTexture::new(4.0)))),
+        Arc::new(Lambertian::new_texture(Arc::new(NoiseTexture::new(4.0, sampler)))),
     )));
     objects.add(Box::new(Sphere::new(
         Point3::new(0.0, 2.0, 0.0),
         2.0,
-        Rc::new(Lambertian::new_texture(Rc::new(NoiseTexture::new(4.0)))),
+        Arc::new(Lambertian::new_texture(Arc::new(NoiseTexture::new(4.0, sampler)))),
     )));
 
     objects
@@ -227,41 +257,89 @@ pub fn two_perlin_spheres() -> HittableList {
 
 pub fn earth() -> HittableList {
     let earth_texture = ImageTexture::new("./images/earthmap.jpg");
-    let earth_surface = Lambertian::new_texture(Rc::new(earth_texture));
-    let globe = Sphere::new(Point3::new(0.0, 0.0, 0.0), 2.0, Rc::new(earth_surface));
+    let earth_surface = Lambertian::new_texture(Arc::new(earth_texture));
+    let globe = Sphere::new(Point3::new(0.0, 0.0, 0.0), 2.0, Arc::new(earth_surface));
 
     HittableList{
-        objects: vec![Box::new(globe)],
+        objects: vec![Arc::new(globe)],
     }
 
 }
 
-pub fn cornell_smoke() -> HittableList {
+//returns the world and the lights in it
+pub fn cornell_smoke() -> (HittableList, HittableList) {
     let mut objects = HittableList::new();
+    let mut lights = HittableList::new();
 
     let red = Color::new(0.65, 0.05, 0.05);
     let white = Color::new(0.73, 0.73, 0.73);
     let green = Color::new(0.12, 0.45, 0.15);
     let light = Color::new(15.0, 15.0, 15.0);
 
-    objects.add(Box::new(YzRect::new(0.0, 555.0, 0.0, 555.0, 555.0, Rc::new(Lambertian::new(green)))));
-    objects.add(Box::new(YzRect::new(0.0, 555.0, 0.0, 555.0, 0.0,   Rc::new(Lambertian::new(red)))));
-    objects.add(Box::new(XzRect::new(113.0, 443.0, 127.0, 432.0, 554.0, Rc::new(DiffuseLight::new(Rc::new(SolidColor::new(light)))))));
-    objects.add(Box::new(XzRect::new(0.0, 555.0, 0.0, 555.0, 0.0, Rc::new(Lambertian::new(white)))));
-    objects.add(Box::new(XzRect::new(0.0, 555.0, 0.0, 555.0, 555.0, Rc::new(Lambertian::new(white)))));
-    objects.add(Box::new(XyRect::new(0.0, 555.0, 0.0, 555.0, 555.0, Rc::new(Lambertian::new(white)))));
+    objects.add(Box::new(YzRect::new(0.0, 555.0, 0.0, 555.0, 555.0, Arc::new(Lambertian::new(green)))));
+    objects.add(Box::new(YzRect::new(0.0, 555.0, 0.0, 555.0, 0.0,   Arc::new(Lambertian::new(red)))));
+    let light: Arc<dyn Hittable> = Arc::new(XzRect::new(113.0, 443.0, 127.0, 432.0, 554.0, Arc::new(DiffuseLight::new(Arc::new(SolidColor::new(light))))));
+    objects.add_shared(Arc::clone(&light));
+    lights.add_shared(light);
+    objects.add(Box::new(XzRect::new(0.0, 555.0, 0.0, 555.0, 0.0, Arc::new(Lambertian::new(white)))));
+    objects.add(Box::new(XzRect::new(0.0, 555.0, 0.0, 555.0, 555.0, Arc::new(Lambertian::new(white)))));
+    objects.add(Box::new(XyRect::new(0.0, 555.0, 0.0, 555.0, 555.0, Arc::new(Lambertian::new(white)))));
 
-    let cub1 = Cubic::new(Point3::new(0.0, 0.0, 0.0), Point3::new(165.0, 330.0, 165.0), Rc::new(Lambertian::new(white)));
-    let cub2 = Cubic::new(Point3::new(0.0, 0.0, 0.0), Point3::new(165.0, 165.0, 165.0), Rc::new(Lambertian::new(white)));
+    let cub1 = Cubic::new(Point3::new(0.0, 0.0, 0.0), Point3::new(165.0, 330.0, 165.0), Arc::new(Lambertian::new(white)));
+    let cub2 = Cubic::new(Point3::new(0.0, 0.0, 0.0), Point3::new(165.0, 165.0, 165.0), Arc::new(Lambertian::new(white)));
 
-    let rotated_cub1 = RotateY::new(Box::new(cub1), 18.0);
-    let rotated_cub2 = RotateY::new(Box::new(cub2), -15.0);
+    let placed_cub1 = Transform::new(Arc::new(cub1), Matrix4::from_translation(Vector3::new(265.0, 0.0, 295.0)) * Matrix4::from_angle_y(Deg(18.0))).unwrap();
+    let placed_cub2 = Transform::new(Arc::new(cub2), Matrix4::from_translation(Vector3::new(130.0, 0.0, 65.0)) * Matrix4::from_angle_y(Deg(-15.0))).unwrap();
 
-    let translated_cub1 = Translate::new(Box::new(rotated_cub1), Vector3::new(265.0, 0.0, 295.0));
-    let translated_cub2 = Translate::new(Box::new(rotated_cub2), Vector3::new(130.0, 0.0, 65.0));
+    objects.add(Box::new(ConstantMedium::new(Box::new(placed_cub1), Box::new(SolidColor::new(Color::new(0.0, 0.0, 0.0))),  0.01)));
+    objects.add(Box::new(ConstantMedium::new(Box::new(placed_cub2), Box::new(SolidColor::new(Color::new(1.0, 1.0, 1.0))),  0.01)));
 
-    objects.add(Box::new(ConstantMedium::new(Box::new(translated_cub1), Box::new(SolidColor::new(Color::new(0.0, 0.0, 0.0))),  0.01)));
-    objects.add(Box::new(ConstantMedium::new(Box::new(translated_cub2), Box::new(SolidColor::new(Color::new(1.0, 1.0, 1.0))),  0.01)));
+    (objects, lights)
+}
+//90,000 instances of two shared prototypes, a tree and a rock; the geometry of each
+//exists only once, whatever the number of copies. The instances sit in their own
+//top-level BVH, which could be refit if they were animated.
+pub fn forest(sampler: &mut dyn Sampler) -> HittableList {
+    let mut world = HittableList::new();
+    world.add(Box::new(Sphere::new(
+        Point3::new(0.0, -10000.0, 0.0),
+        10000.0,
+        Arc::new(Lambertian::new(Color::new(0.35, 0.4, 0.2))),
+    )));
 
-    objects
-}
\ No newline at end of file
+    let bark = Arc::new(Lambertian::new(Color::new(0.3, 0.2, 0.1)));
+    let leaves = Arc::new(Lambertian::new(Color::new(0.1, 0.4, 0.1)));
+    let mut tree = HittableList::new();
+    tree.add(Box::new(Cubic::new(Point3::new(-0.1, 0.0, -0.1), Point3::new(0.1, 1.0, 0.1), bark)));
+    tree.add(Box::new(Sphere::new(Point3::new(0.0, 1.3, 0.0), 0.6, leaves.clone())));
+    tree.add(Box::new(Sphere::new(Point3::new(0.0, 1.9, 0.0), 0.4, leaves)));
+    let tree: Arc<dyn Hittable> = Arc::new(BVH::new(tree, 0.0, 1.0));
+    let rock: Arc<dyn Hittable> = Arc::new(Sphere::new(Point3::new(0.0, 0.0, 0.0), 1.0, Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)))));
+
+    let mut instances = Vec::new();
+
+    for a in -150..150 {
+        for b in -150..150 {
+            let position = Vector3::new(
+                2.0 * (a as f64 + 0.8 * utilities::random_double(sampler)),
+                0.0,
+                2.0 * (b as f64 + 0.8 * utilities::random_double(sampler)),
+            );
+            let placement = Matrix4::from_translation(position) * Matrix4::from_angle_y(Deg(360.0 * utilities::random_double(sampler)));
+            let size = utilities::random_double_with_bounds(sampler, 0.7, 1.3);
+
+            if utilities::random_double(sampler) < 0.8 {
+                instances.push(Instance::new(Arc::clone(&tree), placement * Matrix4::from_scale(size), None).unwrap());
+            } else {
+                //flattened, and each rock in its own shade of grey
+                let grey = utilities::random_double_with_bounds(sampler, 0.3, 0.7);
+                let material: Arc<dyn Material> = Arc::new(Lambertian::new(Color::new(grey, grey, grey)));
+                let shape = Matrix4::from_nonuniform_scale(0.4 * size, 0.2 * size, 0.3 * size);
+                instances.push(Instance::new(Arc::clone(&rock), placement * shape, Some(material)).unwrap());
+            }
+        }
+    }
+
+    world.add(Box::new(TLAS::new(instances, 0.0, 1.0)));
+    world
+}
@@ -0,0 +1,68 @@
+use crate::aabb::AABB;
+use crate::hittable::{HitRecord, Hittable};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::transform::{SingularMatrix, Transform};
+use cgmath::*;
+use std::sync::Arc;
+
+//one placement of a shared prototype, typically a mesh or a group with its own BVH.
+//The prototype is built once and never copied, so an instance only costs its
+//transform and, optionally, a material that replaces every material of the prototype.
+pub struct Instance {
+    transform: Transform,
+    material: Option<Arc<dyn Material>>,
+}
+
+impl Instance {
+    pub fn new(
+        prototype: Arc<dyn Hittable>,
+        matrix: Matrix4<f64>,
+        material: Option<Arc<dyn Material>>,
+    ) -> Result<Self, SingularMatrix> {
+        Ok(Instance {
+            transform: Transform::new(prototype, matrix)?,
+            material,
+        })
+    }
+
+    pub fn matrix(&self) -> Matrix4<f64> {
+        self.transform.matrix()
+    }
+
+    //moves the instance, e.g. for the next frame of an animation; see TLAS::update
+    pub fn set_matrix(&mut self, matrix: Matrix4<f64>) -> Result<(), SingularMatrix> {
+        self.transform.set_matrix(matrix)
+    }
+}
+
+impl Hittable for Instance {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, sampler: &mut dyn Sampler) -> Option<HitRecord> {
+        let mut rec = self.transform.hit(r, t_min, t_max, sampler)?;
+        if let Some(material) = &self.material {
+            rec.mat_ptr = Arc::clone(material);
+        }
+        Some(rec)
+    }
+
+    fn bounding_box(&self, time0: f64, time1: f64, output_box: &mut AABB) -> bool {
+        self.transform.bounding_box(time0, time1, output_box)
+    }
+
+    fn pdf_value(&self, origin: Point3<f64>, direction: Vector3<f64>) -> f64 {
+        self.transform.pdf_value(origin, direction)
+    }
+
+    fn random(&self, origin: Point3<f64>, sampler: &mut dyn Sampler) -> Vector3<f64> {
+        self.transform.random(origin, sampler)
+    }
+
+    fn area(&self) -> Option<f64> {
+        self.transform.area()
+    }
+
+    fn transformed_area(&self, m: &Matrix3<f64>) -> Option<f64> {
+        self.transform.transformed_area(m)
+    }
+}
@@ -0,0 +1,33 @@
+#![allow(clippy::upper_case_acronyms)]
+
+pub mod aabb;
+pub mod bvh;
+pub mod camera;
+pub mod color;
+pub mod environment;
+pub mod framebuffer;
+pub mod gltf_import;
+pub mod hittable;
+pub mod hittable_list;
+pub mod instance;
+pub mod light;
+pub mod material;
+pub mod mesh;
+pub mod microfacet;
+pub mod obj;
+pub mod objects;
+pub mod onb;
+pub mod output;
+pub mod pdf;
+pub mod ply;
+pub mod ray;
+pub mod render;
+pub mod sampler;
+pub mod scene;
+pub mod scene_file;
+pub mod spectrum;
+pub mod stl;
+pub mod texture;
+pub mod tlas;
+pub mod transform;
+pub mod utilities;
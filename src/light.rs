@@ -0,0 +1,238 @@
+//Lights for next-event estimation: at every diffuse bounce one shadow ray goes toward a
+//light picked uniformly from the scene's list. Lights with an area are emissive objects
+//that are also part of the world; the shadow ray takes the emission of whatever it hits,
+//and paths that find them by chance are weighted against light sampling. Point, spot and
+//directional lights are deltas that no path can hit, so they only shine through shadow
+//rays.
+
+use crate::color::Color;
+use crate::hittable::Hittable;
+use crate::hittable_list::HittableList;
+use crate::material::EmissionProfile;
+use crate::pdf::{HittablePdf, Pdf};
+use crate::sampler::Sampler;
+use crate::utilities;
+use cgmath::*;
+use std::sync::Arc;
+
+const PI: f64 = std::f64::consts::PI;
+
+pub enum LightSample {
+    //a direction toward a light with an area; what arrives is the emission of the
+    //surface a ray that way hits first, or the environment if it hits nothing
+    Area(Vector3<f64>),
+    //a delta light `distance` away along the unit vector `direction`, or infinitely far
+    //for distant lights. `radiance` arrives if nothing is in the way, and is already
+    //divided by the density the light was chosen with.
+    Delta {
+        direction: Vector3<f64>,
+        distance: f64,
+        radiance: Color,
+    },
+}
+
+pub trait Light: Send + Sync {
+    fn sample(&self, origin: Point3<f64>, sampler: &mut dyn Sampler) -> LightSample;
+
+    //solid-angle density of `sample` choosing `direction` from `origin`. Delta lights
+    //cannot be hit, so they keep the default.
+    fn pdf_value(&self, _origin: Point3<f64>, _direction: Vector3<f64>) -> f64 {
+        0.0
+    }
+}
+
+//an emissive object, sampled by its own `random` and `pdf_value`
+pub struct AreaLight {
+    shape: Arc<dyn Hittable>,
+}
+
+impl AreaLight {
+    pub fn new(shape: Arc<dyn Hittable>) -> Self {
+        AreaLight { shape }
+    }
+}
+
+impl Light for AreaLight {
+    fn sample(&self, origin: Point3<f64>, sampler: &mut dyn Sampler) -> LightSample {
+        LightSample::Area(HittablePdf::new(&*self.shape, origin).generate(sampler))
+    }
+
+    fn pdf_value(&self, origin: Point3<f64>, direction: Vector3<f64>) -> f64 {
+        HittablePdf::new(&*self.shape, origin).value(direction)
+    }
+}
+
+//toward the point p, which is `distance` away
+fn toward(origin: Point3<f64>, p: Point3<f64>) -> (Vector3<f64>, f64) {
+    let offset = p - origin;
+    let distance = offset.magnitude();
+    (offset / distance, distance)
+}
+
+//shines equally in all directions; intensity is in watts per steradian
+pub struct PointLight {
+    position: Point3<f64>,
+    intensity: Color,
+}
+
+impl PointLight {
+    pub fn new(position: Point3<f64>, intensity: Color) -> Self {
+        PointLight { position, intensity }
+    }
+
+    //the total power; intensity then only gives the color, and should have a luminance of 1
+    pub fn with_power(mut self, watts: f64) -> Self {
+        self.intensity *= watts / (4.0 * PI);
+        self
+    }
+}
+
+impl Light for PointLight {
+    fn sample(&self, origin: Point3<f64>, _sampler: &mut dyn Sampler) -> LightSample {
+        let (direction, distance) = toward(origin, self.position);
+        LightSample::Delta {
+            direction,
+            distance,
+            radiance: self.intensity / (distance * distance),
+        }
+    }
+}
+
+//a point light that only shines into a cone around its axis, fading out toward the
+//cone's edge like EmissionProfile::spot
+pub struct SpotLight {
+    position: Point3<f64>,
+    axis: Vector3<f64>,
+    intensity: Color,
+    profile: EmissionProfile,
+}
+
+impl SpotLight {
+    //intensity is along the axis, from `position` toward `at`
+    pub fn new(position: Point3<f64>, at: Point3<f64>, intensity: Color, cone_degrees: f64, falloff_degrees: f64) -> Self {
+        SpotLight {
+            position,
+            axis: utilities::unit_vector(at - position),
+            intensity,
+            profile: EmissionProfile::spot(cone_degrees, falloff_degrees),
+        }
+    }
+
+    //as for PointLight, but the power only spreads over the cone
+    pub fn with_power(mut self, watts: f64) -> Self {
+        let solid_angle = self.profile.solid_angle();
+        self.intensity *= if solid_angle > 0.0 { watts / solid_angle } else { 0.0 };
+        self
+    }
+}
+
+impl Light for SpotLight {
+    fn sample(&self, origin: Point3<f64>, _sampler: &mut dyn Sampler) -> LightSample {
+        let (direction, distance) = toward(origin, self.position);
+        let factor = self.profile.value(-direction.dot(self.axis));
+        LightSample::Delta {
+            direction,
+            distance,
+            radiance: self.intensity * (factor / (distance * distance)),
+        }
+    }
+}
+
+//light from infinitely far away, such as the sun, arriving along one direction;
+//irradiance is what falls on a surface facing it, in watts per square meter
+pub struct DirectionalLight {
+    direction: Vector3<f64>,
+    irradiance: Color,
+}
+
+impl DirectionalLight {
+    //direction: the way the light travels
+    pub fn new(direction: Vector3<f64>, irradiance: Color) -> Self {
+        DirectionalLight {
+            direction: utilities::unit_vector(direction),
+            irradiance,
+        }
+    }
+}
+
+impl Light for DirectionalLight {
+    fn sample(&self, _origin: Point3<f64>, _sampler: &mut dyn Sampler) -> LightSample {
+        LightSample::Delta {
+            direction: -self.direction,
+            distance: f64::INFINITY,
+            radiance: self.irradiance,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct LightList {
+    pub lights: Vec<Arc<dyn Light>>,
+    shapes: HittableList, //the objects behind the lights added with `add_shape`
+}
+
+impl LightList {
+    pub fn new() -> Self {
+        LightList {
+            lights: Vec::new(),
+            shapes: HittableList::new(),
+        }
+    }
+
+    //every object of the list as an AreaLight
+    pub fn from_shapes(shapes: HittableList) -> Self {
+        let mut list = LightList::new();
+        for shape in shapes.objects {
+            list.add_shape(shape);
+        }
+        list
+    }
+
+    pub fn add(&mut self, light: Arc<dyn Light>) {
+        self.lights.push(light);
+    }
+
+    //an emissive object that is also in the world
+    pub fn add_shape(&mut self, shape: Arc<dyn Hittable>) {
+        self.shapes.add_shared(Arc::clone(&shape));
+        self.lights.push(Arc::new(AreaLight::new(shape)));
+    }
+
+    pub fn shapes(&self) -> &HittableList {
+        &self.shapes
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lights.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.lights.len()
+    }
+
+    //a sample of one light chosen uniformly. Directions toward lights with an area are
+    //weighted by `pdf_value`, the density of the whole list.
+    pub fn sample(&self, origin: Point3<f64>, sampler: &mut dyn Sampler) -> LightSample {
+        let index = utilities::random_int_with_bounds(sampler, 0, self.lights.len() as i32) as usize;
+        match self.lights[index].sample(origin, sampler) {
+            LightSample::Delta {
+                direction,
+                distance,
+                radiance,
+            } => LightSample::Delta {
+                direction,
+                distance,
+                radiance: radiance * self.lights.len() as f64,
+            },
+            sample => sample,
+        }
+    }
+
+    pub fn pdf_value(&self, origin: Point3<f64>, direction: Vector3<f64>) -> f64 {
+        let weight = 1.0 / self.lights.len() as f64;
+        self.lights
+            .iter()
+            .map(|light| weight * light.pdf_value(origin, direction))
+            .sum()
+    }
+}
@@ -1,142 +1,48 @@
-mod aabb;
-mod camera;
-mod color;
-mod hittable;
-mod hittable_list;
-mod material;
-mod objects;
-mod ray;
-mod texture;
-mod utilities;
+mod cli;
 
-use std::fs::File;
-use std::io::Write;
 use std::env;
+use std::path::Path;
 use std::process::exit;
-use cgmath::*;
-use crate::camera::Camera;
-use crate::color::*;
+use ray_tracer::bvh::BVH;
+use ray_tracer::gltf_import;
+use ray_tracer::output;
+use ray_tracer::render::{self, RenderSettings};
+use ray_tracer::sampler::IndependentSampler;
+use ray_tracer::scene::{self, Scene};
+use ray_tracer::scene_file;
+use crate::cli::{Command, Options};
 
 
-pub fn run(arg: i32, path: &str) -> std::io::Result<()>{
-    let mut aspect_ratio: f64 = 3.0 / 2.0;
-    let mut image_height: usize = 400;
-    let mut image_width: usize = (image_height as f64 * aspect_ratio) as usize;
-    let mut samples_per_pixel: usize = 20;
-    let max_depth: usize = 20;
-
+pub fn run(scene: Scene, options: &Options) -> std::io::Result<()>{
     //World
-    let world;
-    let lookfrom;
-    let lookat;
-    let fov;
-    let mut aperture = 0.0;
-    let mut background = SKYBLUE;
-
-    match arg {
-        1 => {
-            world = hittable_list::random_scene();
-            lookfrom = Point3::new(13.0, 2.0, 3.0);
-            lookat = Point3::new(0.0, 0.0, 0.0);
-            fov = 20.0;
-            aperture = 0.1;
-        }
-
-        2 => {
-            world = hittable_list::two_spheres();
-            lookfrom = Point3::new(13.0, 2.0, 3.0);
-            lookat = Point3::new(0.0, 0.0, 0.0);
-            fov = 20.0;
-            aperture = 0.1;
-        }
-
-        3 => {
-            background = BLACK;
-            world = hittable_list::simple_light();
-            lookfrom = Point3::new(26.0, 3.0, 6.0);
-            lookat = Point3::new(0.0, 2.0, 0.0);
-            fov = 20.0;
-        }
-
-        4 => {
-            aspect_ratio = 1.0;
-            image_height = 600;
-            image_width = (image_height as f64 * aspect_ratio) as usize;
-            samples_per_pixel = 500;
-            world = hittable_list::cornell_box();
-            background = Color::new(0.0, 0.0, 0.0);
-            lookfrom = Point3::new(278.0, 278.0, -800.0);
-            lookat = Point3::new(278.0, 278.0, 0.0);
-            fov = 40.0;
-        }
-        5 => {
-            world = hittable_list::two_perlin_spheres();
-            lookfrom = Point3::new(13.0, 2.0, 3.0);
-            lookat = Point3::new(0.0, 0.0, 0.0);
-            fov = 20.0;
-        }
-
-        6 => {
-            world = hittable_list::earth();
-            lookfrom = Point3::new(13.0, 2.0, 3.0);
-            lookat = Point3::new(0.0, 0.0, 0.0);
-            fov = 20.0;
-        }
-
-        7 => {
-            aspect_ratio = 1.0;
-            image_height = 600;
-            image_width = (image_height as f64 * aspect_ratio) as usize;
-            samples_per_pixel = 20;
-            world = hittable_list::cornell_smoke();
-            background = Color::new(0.0, 0.0, 0.0);
-            lookfrom = Point3::new(278.0, 278.0, -800.0);
-            lookat = Point3::new(278.0, 278.0, 0.0);
-            fov = 40.0;
-        }
-        _ => {
-            panic!("no such mode");
-        }
-    }
-    //Camera
-
-    let vup = Vector3::new(0.0, 1.0, 0.0);
-    let dist_to_focus = 10.0;
+    let cam = scene.camera();
+    let world = BVH::new(scene.world, scene.camera.time0, scene.camera.time1);
+    let lights = if scene.lights.is_empty() {
+        None
+    } else {
+        Some(&scene.lights)
+    };
 
-    let cam = Camera::new(
-        lookfrom,
-        lookat,
-        vup,
-        fov,
-        aspect_ratio,
-        aperture,
-        dist_to_focus,
-        0.0,
-        1.0,
-    );    
-    
     //Render
 
-    let mut file = File::create(path)?;
-    let mut output = format!("P3\n{} {}\n255\n", image_width, image_height);
-
-    for j in (0..image_height).rev() {
-        eprintln!("Scanlines remaining: {} \r", j);
-        
-        for i in 0..image_width {
-            let mut pixel_color = Color::new(0.0, 0.0, 0.0);
-
-            for _ in 0..samples_per_pixel {
-                let u = (i as f64 + utilities::random_double()) / (image_width - 1) as f64;
-                let v = (j as f64 + utilities::random_double()) / (image_height - 1) as f64;
-                let r = cam.cast_ray(u, v);
-                pixel_color += color::ray_color(&r, background, &world, max_depth);
-            }
-            output += &color::write_color(pixel_color, samples_per_pixel);
-        }
-    }
-
-    file.write(output.as_bytes())?;
+    let settings = RenderSettings {
+        image_width: scene.image_width,
+        image_height: scene.image_height,
+        samples_per_pixel: scene.samples_per_pixel,
+        limits: scene.limits,
+        sampler: scene.sampler,
+        color_mode: scene.color_mode,
+        seed: options.seed,
+        threads: options.threads,
+        tile_size: 16,
+    };
+    eprintln!(
+        "Rendering {}x{} at {} spp on {} thread(s)",
+        settings.image_width, settings.image_height, settings.samples_per_pixel, settings.threads
+    );
+    let framebuffer = render::render(&world, lights, &cam, scene.background.as_ref(), &settings);
+
+    output::write_image(&framebuffer, Path::new(&options.output), options.format)?;
 
     eprintln!("Done");
 
@@ -144,18 +50,48 @@ pub fn run(arg: i32, path: &str) -> std::io::Result<()>{
 }
 
 fn main() -> std::io::Result<()>{
-    let args: Vec<String> = env::args().collect();
-
-    if args.len() != 2 {
-        eprintln!("Usage: [executable] [image-name]");
-        drop(args);
-        exit(1);
-    }
+    let options = match cli::parse(env::args().skip(1)) {
+        Ok(Command::Render(options)) => options,
+        Ok(Command::Help) => {
+            println!("{}", cli::usage());
+            return Ok(());
+        }
+        Err(message) => {
+            eprintln!("error: {}\nrun with --help for usage", message);
+            exit(2);
+        }
+    };
 
     let now = std::time::Instant::now();
-    let mode = 1;
-    let path = String::from("./images/") + &args[1];
-    run(mode, &path)?;
+    //scene construction gets its own stream, separate from the per-pixel ones
+    let mut sampler = IndependentSampler::new(options.seed);
+
+    let mut scene = match scene::builtin(&options.scene, &mut sampler) {
+        Some(scene) => scene,
+        None if !Path::new(&options.scene).is_file() => {
+            eprintln!("error: `{}` is neither a built-in scene nor a scene file", options.scene);
+            exit(2);
+        }
+        None => {
+            let path = Path::new(&options.scene);
+            let extension = path.extension().map(|e| e.to_string_lossy().to_lowercase());
+            //glTF files are complete scenes with their own cameras
+            let result = match extension.as_deref() {
+                Some("gltf") | Some("glb") => gltf_import::load_scene(path).map_err(|e| e.to_string()),
+                _ => scene_file::load(&options.scene, &mut sampler).map_err(|e| e.to_string()),
+            };
+            match result {
+                Ok(scene) => scene,
+                Err(e) => {
+                    eprintln!("{}: {}", options.scene, e);
+                    exit(1);
+                }
+            }
+        }
+    };
+    options.apply(&mut scene);
+
+    run(scene, &options)?;
 
     let cost = now.elapsed().as_millis();
 
@@ -1,24 +1,77 @@
-use crate::color::Color;
+use crate::color::{luminance, Color, BLACK, WHITE};
 use crate::hittable::HitRecord;
+use crate::microfacet::{self, dielectric_reflectance, schlick, Ggx};
+use crate::onb::Onb;
+use crate::pdf::{CosinePdf, MicrofacetPdf, Pdf, RoughDielectricPdf, SpherePdf};
 use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::spectrum::Ior;
 use crate::texture::{SolidColor, Texture};
 use crate::utilities::*;
-use std::rc::Rc;
+use std::sync::Arc;
 use cgmath::*;
 
+const PI: f64 = std::f64::consts::PI;
+
+//how a material continues a path
+pub enum ScatterKind {
+    //a single deterministic direction (mirror, glass); no pdf is involved
+    Specular(Ray),
+    //a direction to be drawn from the pdf and weighted by scattering_pdf / pdf
+    Diffuse(Box<dyn Pdf>),
+    //like Diffuse, but scattering inside a participating medium
+    Volume(Box<dyn Pdf>),
+}
+
+pub struct ScatterRecord {
+    pub attenuation: Color,
+    pub kind: ScatterKind,
+}
+
 //Definition of Material
-pub trait Material {
-    fn scatter(
-        &self,
-        r_in: &Ray,
-        rec: &HitRecord,
-        attenuation: &mut Color,
-        scattered: &mut Ray,
-    ) -> bool;
-    
-    fn emitted(&self, _u: f64, _v: f64, _p: Point3<f64>) -> Color {
+pub trait Material: Send + Sync {
+    //None means the path is absorbed
+    fn scatter(&self, _r_in: &Ray, _rec: &HitRecord, _sampler: &mut dyn Sampler) -> Option<ScatterRecord> {
+        None
+    }
+
+    //density of the material scattering r_in into `scattered`, only used for diffuse scattering
+    fn scattering_pdf(&self, _r_in: &Ray, _rec: &HitRecord, _scattered: &Ray) -> f64 {
+        0.0
+    }
+
+    //the BSDF times the cosine toward `scattered`, for diffuse scattering. The default
+    //suits materials whose color does not depend on the directions.
+    fn scattering(&self, r_in: &Ray, rec: &HitRecord, attenuation: Color, scattered: &Ray) -> Color {
+        attenuation * self.scattering_pdf(r_in, rec, scattered)
+    }
+
+    //radiance leaving the surface at rec back along r_in
+    fn emitted(&self, _r_in: &Ray, _rec: &HitRecord) -> Color {
         Color::new(0.0, 0.0, 0.0)
     }
+
+    //emissive surfaces are collected into the scene's light list for direct sampling
+    fn is_emissive(&self) -> bool {
+        false
+    }
+
+    //a light whose output is given as a power returns the copy that spreads it over a
+    //surface of this area; None for everything else
+    fn fit_to_area(&self, _area: f64) -> Option<Arc<dyn Material>> {
+        None
+    }
+
+    //whether scattering depends on the wavelength, see spectrum.rs
+    fn is_dispersive(&self) -> bool {
+        false
+    }
+
+    //a material that stands for one of several others picks the one to use for this hit.
+    //The integrator asks before anything else, and again of the answer.
+    fn select(&self, _r_in: &Ray, _rec: &HitRecord, _sampler: &mut dyn Sampler) -> Option<Arc<dyn Material>> {
+        None
+    }
 }
 
 //Metal
@@ -28,20 +81,17 @@ pub struct Metal {
 }
 
 impl Material for Metal {
-    fn scatter(
-        &self,
-        r_in: &Ray,
-        rec: &HitRecord,
-        attenuation: &mut Color,
-        scattered: &mut Ray,
-    ) -> bool {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, sampler: &mut dyn Sampler) -> Option<ScatterRecord> {
         let reflected = reflect(unit_vector(r_in.direction), rec);
-        scattered.origin = rec.p;
-        scattered.direction = reflected + random_in_unit_sphere() * self.fuzz;
-        scattered.time = r_in.time;
+        let scattered = Ray::new(rec.p, reflected + random_in_unit_sphere(sampler) * self.fuzz, r_in.time);
 
-        *attenuation = self.albedo.clone();
-        scattered.direction.dot(rec.normal) > 0.0
+        if scattered.direction.dot(rec.normal) <= 0.0 {
+            return None;
+        }
+        Some(ScatterRecord {
+            attenuation: self.albedo,
+            kind: ScatterKind::Specular(scattered),
+        })
     }
 }
 
@@ -51,116 +101,387 @@ impl Metal {
     }
 }
 
+//a microfacet surface with the parameters of glTF and most PBR tools: a base color, a
+//perceptual roughness and a metalness, each read from a texture (the scalars from its
+//red channel). Metals reflect tinted by the base color; everything else is a diffuse
+//base under a clear specular lobe with a reflectance of 4% at normal incidence.
+pub struct MetallicRoughness {
+    base_color: Arc<dyn Texture>,
+    roughness: Arc<dyn Texture>,
+    metallic: Arc<dyn Texture>,
+}
+
+impl MetallicRoughness {
+    pub fn new(base_color: Arc<dyn Texture>, roughness: Arc<dyn Texture>, metallic: Arc<dyn Texture>) -> Self {
+        MetallicRoughness {
+            base_color,
+            roughness,
+            metallic,
+        }
+    }
+
+    //diffuse albedo, specular reflectance at normal incidence, and the distribution
+    fn parameters(&self, rec: &HitRecord) -> (Color, Color, Ggx) {
+        let base_color = self.base_color.value_at(rec);
+        let metallic = self.metallic.value_at(rec).x.clamp(0.0, 1.0);
+        let f0 = Color::new(0.04, 0.04, 0.04) * (1.0 - metallic) + base_color * metallic;
+        let ggx = Ggx::from_roughness(self.roughness.value_at(rec).x);
+        (base_color * (1.0 - metallic), f0, ggx)
+    }
+}
+
+impl Material for MetallicRoughness {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, _sampler: &mut dyn Sampler) -> Option<ScatterRecord> {
+        let uvw = Onb::build_from_w(rec.normal);
+        let wo = uvw.to_local(-unit_vector(r_in.direction));
+        if wo.z <= 0.0 {
+            return None;
+        }
+
+        //pick the specular lobe about as often as it reflects
+        let (diffuse, f0, ggx) = self.parameters(rec);
+        let specular = luminance(schlick(f0, wo.z));
+        let total = specular + luminance(diffuse);
+        let specular_probability = if total > 0.0 { specular / total } else { 1.0 };
+
+        Some(ScatterRecord {
+            attenuation: WHITE,
+            kind: ScatterKind::Diffuse(Box::new(MicrofacetPdf::new(uvw, wo, ggx, specular_probability))),
+        })
+    }
+
+    fn scattering(&self, r_in: &Ray, rec: &HitRecord, _attenuation: Color, scattered: &Ray) -> Color {
+        let uvw = Onb::build_from_w(rec.normal);
+        let wo = uvw.to_local(-unit_vector(r_in.direction));
+        let wi = uvw.to_local(unit_vector(scattered.direction));
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return BLACK;
+        }
+
+        let (diffuse, f0, ggx) = self.parameters(rec);
+        let h = unit_vector(wo + wi);
+        let fresnel = schlick(f0, wo.dot(h));
+        let specular = fresnel * (ggx.d(h) * ggx.g2(wo, wi) / (4.0 * wo.z * wi.z));
+        let diffuse = (WHITE - fresnel).mul_element_wise(diffuse) / PI;
+        (specular + diffuse) * wi.z
+    }
+}
+
 //Lambertian
 pub struct Lambertian {
-    albedo: Rc<dyn Texture>,
+    albedo: Arc<dyn Texture>,
 }
 
 impl Lambertian {
     pub fn new(a: Color) -> Self {
         Lambertian {
-            albedo: Rc::new(SolidColor::new(a)),
+            albedo: Arc::new(SolidColor::new(a)),
         }
     }
 
-    pub fn new_texture(a: Rc<dyn Texture>) -> Self {
+    pub fn new_texture(a: Arc<dyn Texture>) -> Self {
         Lambertian { albedo: a }
     }
 }
 
 impl Material for Lambertian {
-    fn scatter(
-        &self,
-        r_in: &Ray,
-        rec: &HitRecord,
-        attenuation: &mut Color,
-        scattered: &mut Ray,
-    ) -> bool {
-        let mut scatter_direction = rec.normal + random_unit_vector();
+    fn scatter(&self, _r_in: &Ray, rec: &HitRecord, _sampler: &mut dyn Sampler) -> Option<ScatterRecord> {
+        Some(ScatterRecord {
+            attenuation: self.albedo.value_at(rec),
+            kind: ScatterKind::Diffuse(Box::new(CosinePdf::new(rec.normal))),
+        })
+    }
 
-        if near_zero(scatter_direction) {
-            scatter_direction = rec.normal;
+    fn scattering_pdf(&self, _r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+        let cosine = rec.normal.dot(unit_vector(scattered.direction));
+        if cosine < 0.0 {
+            0.0
+        } else {
+            cosine / PI
         }
-
-        scattered.origin = rec.p;
-        scattered.direction = scatter_direction;
-        scattered.time = r_in.time;
-
-        *attenuation = self.albedo.value(rec.u, rec.v, rec.p);
-
-        true
     }
 }
 
 //Dielectric
+//glass, water and the like. Light is reflected or refracted as the Fresnel reflectance
+//decides, and is absorbed inside following the Beer-Lambert law, so the object must be
+//closed. A rough dielectric scatters through GGX microfacets instead of a mirror. An
+//index of refraction that varies with wavelength disperses light in spectral mode.
+//Radiance is not rescaled by eta^2 where it crosses the surface: for an object seen
+//from outside the two crossings cancel, and the rescaling only adds variance.
 pub struct Dielectric {
-    index_of_refraction: f64,
+    ior: Ior,
+    absorption: Color, //per unit length
+    roughness: Option<Ggx>,
 }
 
 impl Dielectric {
     pub fn new(index_of_refraction: f64) -> Self {
+        Dielectric::new_dispersive(Ior::Constant(index_of_refraction))
+    }
+
+    pub fn new_dispersive(ior: Ior) -> Self {
         Dielectric {
-            index_of_refraction,
+            ior,
+            absorption: BLACK,
+            roughness: None,
+        }
+    }
+
+    //the fraction absorbed per unit length traveled inside, per channel; light passing
+    //through a thickness d keeps exp(-absorption * d)
+    pub fn with_absorption(mut self, absorption: Color) -> Self {
+        self.absorption = absorption;
+        self
+    }
+
+    //perceptual roughness as for MetallicRoughness; 0 keeps the surface smooth
+    pub fn with_roughness(mut self, roughness: f64) -> Self {
+        self.roughness = if roughness > 0.0 {
+            Some(Ggx::from_roughness(roughness))
+        } else {
+            None
+        };
+        self
+    }
+
+    //index of refraction beyond the surface over the one on the ray's side
+    fn eta(&self, r_in: &Ray, rec: &HitRecord) -> f64 {
+        let index_of_refraction = self.ior.at(r_in.wavelength);
+        if rec.front_face {
+            index_of_refraction
+        } else {
+            1.0 / index_of_refraction
         }
     }
 }
 
 impl Material for Dielectric {
-    fn scatter(
-        &self,
-        r_in: &Ray,
-        rec: &HitRecord,
-        attenuation: &mut Color,
-        scattered: &mut Ray,
-    ) -> bool {
-        *attenuation = Color::new(1.0, 1.0, 1.0);
-        let refraction_ratio = if rec.front_face {
-            1.0 / self.index_of_refraction
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, sampler: &mut dyn Sampler) -> Option<ScatterRecord> {
+        //leaving the object, so the ray has just crossed its interior
+        let attenuation = if rec.front_face || self.absorption == BLACK {
+            WHITE
         } else {
-            self.index_of_refraction
+            let distance = rec.t * r_in.direction.magnitude();
+            (-self.absorption * distance).map(f64::exp)
         };
-
+        let eta = self.eta(r_in, rec);
         let unit_direction = unit_vector(r_in.direction);
-        let cos_theta = min(-r_in.direction.dot(rec.normal), 1.0);
-        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
 
-        let cannot_refract = sin_theta * refraction_ratio > 1.0;
+        if let Some(ggx) = self.roughness {
+            let uvw = Onb::build_from_w(rec.normal);
+            let wo = uvw.to_local(-unit_direction);
+            if wo.z <= 0.0 {
+                return None;
+            }
+            return Some(ScatterRecord {
+                attenuation,
+                kind: ScatterKind::Diffuse(Box::new(RoughDielectricPdf::new(uvw, wo, ggx, eta))),
+            });
+        }
 
-        let direction = if cannot_refract {
+        let cos_theta = min(-unit_direction.dot(rec.normal), 1.0);
+        let direction = if random_double(sampler) < dielectric_reflectance(cos_theta, eta) {
             reflect(unit_direction, rec)
         } else {
-            refract(unit_direction, rec.normal, refraction_ratio)
+            refract(unit_direction, rec.normal, 1.0 / eta)
         };
-        scattered.origin = rec.p;
-        scattered.direction = direction;
-        scattered.time = r_in.time;
-        true
+        Some(ScatterRecord {
+            attenuation,
+            kind: ScatterKind::Specular(Ray::new(rec.p, direction, r_in.time)),
+        })
+    }
+
+    fn scattering(&self, r_in: &Ray, rec: &HitRecord, attenuation: Color, scattered: &Ray) -> Color {
+        let ggx = match self.roughness {
+            Some(ggx) => ggx,
+            None => return BLACK,
+        };
+        let uvw = Onb::build_from_w(rec.normal);
+        let wo = uvw.to_local(-unit_vector(r_in.direction));
+        let wi = uvw.to_local(unit_vector(scattered.direction));
+        let eta = self.eta(r_in, rec);
+        let h = match microfacet::half_vector(wo, wi, eta) {
+            Some(h) if wo.z > 0.0 => h,
+            _ => return BLACK,
+        };
+
+        let fresnel = dielectric_reflectance(wo.dot(h), eta);
+        let value = if wi.z > 0.0 {
+            fresnel * ggx.d(h) * ggx.g2(wo, wi) / (4.0 * wo.z)
+        } else {
+            let denom = wo.dot(h) + eta * wi.dot(h);
+            (1.0 - fresnel) * ggx.d(h) * ggx.g2(wo, wi) * wo.dot(h) * wi.dot(h).abs() * eta * eta / (wo.z * denom * denom)
+        };
+        attenuation * value
+    }
+
+    fn is_dispersive(&self) -> bool {
+        !matches!(self.ior, Ior::Constant(_))
+    }
+}
+
+//how the radiance of an emitter falls off with the angle from its normal, as a factor
+//of the radiance along the normal
+#[derive(Clone, Debug)]
+pub enum EmissionProfile {
+    //the same radiance in every direction
+    Lambertian,
+    //cos^exponent, a lobe that narrows as the exponent grows
+    Cosine { exponent: f64 },
+    //full inside cos_falloff, fading smoothly to nothing at cos_cone
+    Spot { cos_cone: f64, cos_falloff: f64 },
+    //measured factors at angles evenly spaced from 0 to 90 degrees and interpolated in
+    //between, like the vertical angles of an IES photometric file
+    Table(Vec<f64>),
+}
+
+impl EmissionProfile {
+    pub fn spot(cone_degrees: f64, falloff_degrees: f64) -> Self {
+        let cone = cone_degrees.clamp(0.0, 90.0);
+        EmissionProfile::Spot {
+            cos_cone: cone.to_radians().cos(),
+            cos_falloff: falloff_degrees.clamp(0.0, cone).to_radians().cos(),
+        }
+    }
+
+    //cos: the cosine of the angle between the normal and the emitted direction
+    pub fn value(&self, cos: f64) -> f64 {
+        match self {
+            EmissionProfile::Lambertian => 1.0,
+            EmissionProfile::Cosine { exponent } => cos.max(0.0).powf(*exponent),
+            EmissionProfile::Spot { cos_cone, cos_falloff } => {
+                if cos < *cos_cone {
+                    0.0
+                } else if cos >= *cos_falloff {
+                    1.0
+                } else {
+                    let t = (cos - cos_cone) / (cos_falloff - cos_cone);
+                    t * t * (3.0 - 2.0 * t)
+                }
+            }
+            EmissionProfile::Table(values) => {
+                if values.len() < 2 {
+                    return values.first().copied().unwrap_or(0.0);
+                }
+                let x = cos.clamp(0.0, 1.0).acos() / (0.5 * PI) * (values.len() - 1) as f64;
+                let i = (x as usize).min(values.len() - 2);
+                let t = x - i as f64;
+                values[i] * (1.0 - t) + values[i + 1] * t
+            }
+        }
+    }
+
+    //the profile integrated over the hemisphere: the power of a point light shining like
+    //this, for an intensity of 1 along the normal
+    pub fn solid_angle(&self) -> f64 {
+        if let EmissionProfile::Lambertian = self {
+            return 2.0 * PI;
+        }
+        const STEPS: usize = 1024;
+        let sum: f64 = (0..STEPS).map(|i| self.value((i as f64 + 0.5) / STEPS as f64)).sum();
+        2.0 * PI * sum / STEPS as f64
+    }
+
+    //the power one side of a unit area emits for a radiance of 1 along the normal: the
+    //profile times the cosine integrated over the hemisphere, which is pi if it is flat
+    pub fn projected_solid_angle(&self) -> f64 {
+        if let EmissionProfile::Lambertian = self {
+            return PI;
+        }
+        const STEPS: usize = 1024;
+        let sum: f64 = (0..STEPS)
+            .map(|i| {
+                let cos = (i as f64 + 0.5) / STEPS as f64;
+                self.value(cos) * cos
+            })
+            .sum();
+        2.0 * PI * sum / STEPS as f64
     }
 }
 
+//luminous efficacy at 555 nm, the peak of the eye's sensitivity, in lumens per watt
+pub const LUMENS_PER_WATT: f64 = 683.0;
+
 //struct DiffuseLight
 pub struct DiffuseLight {
-    emit: Rc<dyn Texture>,
+    emit: Arc<dyn Texture>,
+    two_sided: bool,
+    profile: EmissionProfile,
+    power: Option<f64>, //in watts, until fit_to_area turns it into a scale of emit
+    scale: f64,
 }
 
 impl DiffuseLight {
-    pub fn new(emit: Rc<dyn Texture>) -> Self {
-        Self { emit }
+    //emit is the radiance, the same from both sides and in every direction
+    pub fn new(emit: Arc<dyn Texture>) -> Self {
+        Self {
+            emit,
+            two_sided: true,
+            profile: EmissionProfile::Lambertian,
+            power: None,
+            scale: 1.0,
+        }
+    }
+
+    //emits only on the side the surface normal points to
+    pub fn one_sided(mut self) -> Self {
+        self.two_sided = false;
+        self
+    }
+
+    //scales the radiance given by emit
+    pub fn with_intensity(mut self, intensity: f64) -> Self {
+        self.scale = intensity;
+        self
+    }
+
+    pub fn with_profile(mut self, profile: EmissionProfile) -> Self {
+        self.profile = profile;
+        self
+    }
+
+    //the total power leaving the surface. emit then only gives the color, and should have
+    //a luminance of 1; the scene is expected to place the light with fit_to_area.
+    pub fn with_power(mut self, watts: f64) -> Self {
+        self.power = Some(watts.max(0.0));
+        self
+    }
+
+    pub fn with_lumens(self, lumens: f64) -> Self {
+        self.with_power(lumens / LUMENS_PER_WATT)
     }
 }
 
 impl Material for DiffuseLight {
-    fn scatter(
-        &self,
-        _r_in: &Ray,
-        _rec: &HitRecord,
-        _attenuation: &mut Color,
-        _scattered: &mut Ray,
-    ) -> bool {
-        false
+    fn emitted(&self, r_in: &Ray, rec: &HitRecord) -> Color {
+        if !self.two_sided && !rec.front_face {
+            return BLACK;
+        }
+        //rec.normal faces the ray, so this is the cosine on the side that emits
+        let cos = -r_in.direction.dot(rec.normal) / r_in.direction.magnitude();
+        let factor = self.scale * self.profile.value(cos);
+        if factor == 0.0 {
+            return BLACK;
+        }
+        self.emit.value_at(rec) * factor
     }
-    fn emitted(&self, u: f64, v: f64, p: Point3<f64>) -> Color {
-        self.emit.value(u, v, p)
+
+    fn is_emissive(&self) -> bool {
+        true
+    }
+
+    fn fit_to_area(&self, area: f64) -> Option<Arc<dyn Material>> {
+        let watts = self.power?;
+        let sides = if self.two_sided { 2.0 } else { 1.0 };
+        let denom = area * sides * self.profile.projected_solid_angle();
+        Some(Arc::new(DiffuseLight {
+            emit: Arc::clone(&self.emit),
+            two_sided: self.two_sided,
+            profile: self.profile.clone(),
+            power: None,
+            scale: if denom > 0.0 { watts / denom } else { 0.0 },
+        }))
     }
 }
 
@@ -205,9 +526,158 @@ impl Isotropic {
     }
 }
 impl Material for Isotropic {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord, attenuation: &mut Color, scattered: &mut Ray) -> bool {
-        *scattered = Ray::new(rec.p, random_in_unit_sphere(), r_in.time);
-        *attenuation = self.albedo.value(rec.u, rec.v, rec.p);
-        true
-    }   
+    fn scatter(&self, _r_in: &Ray, rec: &HitRecord, _sampler: &mut dyn Sampler) -> Option<ScatterRecord> {
+        Some(ScatterRecord {
+            attenuation: self.albedo.value_at(rec),
+            kind: ScatterKind::Volume(Box::new(SpherePdf)),
+        })
+    }
+
+    fn scattering_pdf(&self, _r_in: &Ray, _rec: &HitRecord, _scattered: &Ray) -> f64 {
+        1.0 / (4.0 * PI)
+    }
+}
+
+//one of two materials, picked anew at every hit with the weight texture's red channel as
+//the probability of `b`. A weight that varies over the surface paints one material over
+//the other, e.g. rust over steel; a constant one blends them.
+pub struct MixMaterial {
+    a: Arc<dyn Material>,
+    b: Arc<dyn Material>,
+    weight: Arc<dyn Texture>,
+}
+
+impl MixMaterial {
+    pub fn new(a: Arc<dyn Material>, b: Arc<dyn Material>, weight: Arc<dyn Texture>) -> Self {
+        MixMaterial { a, b, weight }
+    }
+}
+
+impl Material for MixMaterial {
+    fn select(&self, _r_in: &Ray, rec: &HitRecord, sampler: &mut dyn Sampler) -> Option<Arc<dyn Material>> {
+        let weight = self.weight.value_at(rec).x;
+        if random_double(sampler) < weight {
+            Some(Arc::clone(&self.b))
+        } else {
+            Some(Arc::clone(&self.a))
+        }
+    }
+
+    //lights are sampled without a hit to select with, so they get the average
+    fn emitted(&self, r_in: &Ray, rec: &HitRecord) -> Color {
+        let weight = self.weight.value_at(rec).x.clamp(0.0, 1.0);
+        self.a.emitted(r_in, rec) * (1.0 - weight) + self.b.emitted(r_in, rec) * weight
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.a.is_emissive() || self.b.is_emissive()
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.a.is_dispersive() || self.b.is_dispersive()
+    }
+}
+
+//a smooth dielectric layer, such as varnish or lacquer, over any base material. The coat
+//reflects as the Fresnel reflectance decides; the rest reaches the base, and what the
+//base sends back out is filtered by the coat's color and reflected back in by the coat
+//at the other end. Light reflected between the base and the coat is not followed, and
+//the base sees directions as they are outside the coat.
+pub struct Coated {
+    base: Arc<dyn Material>,
+    index_of_refraction: f64,
+    color: Color, //transmittance of one pass straight through the coat
+}
+
+impl Coated {
+    pub fn new(base: Arc<dyn Material>, index_of_refraction: f64) -> Self {
+        Coated {
+            base,
+            index_of_refraction,
+            color: WHITE,
+        }
+    }
+
+    pub fn with_color(mut self, color: Color) -> Self {
+        self.color = color;
+        self
+    }
+
+    //what is left of light crossing the coat at cos to the normal (outside the coat)
+    fn transmittance(&self, cos: f64, reflected_back: bool) -> Color {
+        let cos = cos.clamp(0.0, 1.0);
+        let sin2_inside = (1.0 - cos * cos) / (self.index_of_refraction * self.index_of_refraction);
+        let cos_inside = (1.0 - sin2_inside).sqrt();
+        let absorbed = self.color.map(|c| c.powf(1.0 / cos_inside));
+        if reflected_back {
+            absorbed * (1.0 - dielectric_reflectance(cos, self.index_of_refraction))
+        } else {
+            absorbed
+        }
+    }
+
+    //the coat's filtering of a path that reached the base from r_in and left toward `out`
+    fn through_coat(&self, r_in: &Ray, rec: &HitRecord, out: Vector3<f64>) -> Color {
+        let cos_in = -unit_vector(r_in.direction).dot(rec.normal);
+        let cos_out = unit_vector(out).dot(rec.normal);
+        let inward = self.transmittance(cos_in, false);
+        if cos_out <= 0.0 {
+            //the base let it through, as glass would
+            return inward;
+        }
+        inward.mul_element_wise(self.transmittance(cos_out, true))
+    }
+}
+
+impl Material for Coated {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, sampler: &mut dyn Sampler) -> Option<ScatterRecord> {
+        let unit_direction = unit_vector(r_in.direction);
+        let cos_theta = min(-unit_direction.dot(rec.normal), 1.0);
+        if random_double(sampler) < dielectric_reflectance(cos_theta, self.index_of_refraction) {
+            return Some(ScatterRecord {
+                attenuation: WHITE,
+                kind: ScatterKind::Specular(Ray::new(rec.p, reflect(unit_direction, rec), r_in.time)),
+            });
+        }
+
+        //the coat's share of diffuse scattering is applied in `scattering`
+        let mut srec = self.base.scatter(r_in, rec, sampler)?;
+        if let ScatterKind::Specular(ref ray) = srec.kind {
+            srec.attenuation = srec
+                .attenuation
+                .mul_element_wise(self.through_coat(r_in, rec, ray.direction));
+        }
+        Some(srec)
+    }
+
+    fn scattering_pdf(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+        self.base.scattering_pdf(r_in, rec, scattered)
+    }
+
+    fn scattering(&self, r_in: &Ray, rec: &HitRecord, attenuation: Color, scattered: &Ray) -> Color {
+        self.base
+            .scattering(r_in, rec, attenuation, scattered)
+            .mul_element_wise(self.through_coat(r_in, rec, scattered.direction))
+    }
+
+    fn emitted(&self, r_in: &Ray, rec: &HitRecord) -> Color {
+        self.base.emitted(r_in, rec)
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.base.is_emissive()
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.base.is_dispersive()
+    }
+
+    fn select(&self, r_in: &Ray, rec: &HitRecord, sampler: &mut dyn Sampler) -> Option<Arc<dyn Material>> {
+        let base = self.base.select(r_in, rec, sampler)?;
+        Some(Arc::new(Coated {
+            base,
+            index_of_refraction: self.index_of_refraction,
+            color: self.color,
+        }))
+    }
 }
@@ -0,0 +1,170 @@
+use crate::aabb::AABB;
+use crate::bvh::BVH;
+use crate::color::Color;
+use crate::hittable::{HitRecord, Hittable};
+use crate::material::Material;
+use crate::objects::{intersect_triangle, triangle_bounding_box};
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::utilities;
+use cgmath::*;
+use std::fmt;
+use std::sync::Arc;
+
+//a failure while reading a mesh file
+#[derive(Debug)]
+pub struct MeshError {
+    pub line: usize, //0 when the error is not tied to a line, e.g. in binary files
+    pub message: String,
+}
+
+impl fmt::Display for MeshError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.line == 0 {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "line {}: {}", self.line, self.message)
+        }
+    }
+}
+
+impl std::error::Error for MeshError {}
+
+//vertex buffers shared by every triangle of a mesh. `normals`, `uvs` and `colors` are
+//either empty or hold one entry per position; each entry of `indices` is one triangle.
+#[derive(Default)]
+pub struct MeshData {
+    pub positions: Vec<Point3<f64>>,
+    pub normals: Vec<Vector3<f64>>,
+    pub uvs: Vec<(f64, f64)>,
+    pub colors: Vec<Color>,
+    pub indices: Vec<[u32; 3]>,
+}
+
+struct Mesh {
+    data: MeshData,
+    mat_ptr: Arc<dyn Material>,
+}
+
+//one triangle of a mesh; only an index into the shared buffers
+pub struct MeshTriangle {
+    mesh: Arc<Mesh>,
+    index: u32,
+}
+
+impl MeshTriangle {
+    fn vertices(&self) -> ([u32; 3], [Point3<f64>; 3]) {
+        let indices = self.mesh.data.indices[self.index as usize];
+        let positions = &self.mesh.data.positions;
+        (
+            indices,
+            [
+                positions[indices[0] as usize],
+                positions[indices[1] as usize],
+                positions[indices[2] as usize],
+            ],
+        )
+    }
+}
+
+impl Hittable for MeshTriangle {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, _sampler: &mut dyn Sampler) -> Option<HitRecord> {
+        let ([i0, i1, i2], [p0, p1, p2]) = self.vertices();
+        let (t, b1, b2) = intersect_triangle(r, p0, p1, p2, t_min, t_max)?;
+        let b0 = 1.0 - b1 - b2;
+        let data = &self.mesh.data;
+
+        //without texture coordinates the barycentrics stand in for them
+        let (u, v) = if data.uvs.is_empty() {
+            (b1, b2)
+        } else {
+            let (uv0, uv1, uv2) = (data.uvs[i0 as usize], data.uvs[i1 as usize], data.uvs[i2 as usize]);
+            (
+                b0 * uv0.0 + b1 * uv1.0 + b2 * uv2.0,
+                b0 * uv0.1 + b1 * uv1.1 + b2 * uv2.1,
+            )
+        };
+
+        let geometric_normal = utilities::unit_vector((p1 - p0).cross(p2 - p0));
+        let mut rec = HitRecord::new(t, r, geometric_normal, u, v, Arc::clone(&self.mesh.mat_ptr));
+
+        if !data.colors.is_empty() {
+            rec.color = Some(data.colors[i0 as usize] * b0 + data.colors[i1 as usize] * b1 + data.colors[i2 as usize] * b2);
+        }
+
+        //smooth shading: the interpolated normal, flipped onto the side the ray hit
+        if !data.normals.is_empty() {
+            let n = data.normals[i0 as usize] * b0 + data.normals[i1 as usize] * b1 + data.normals[i2 as usize] * b2;
+            if n.magnitude2() > 0.0 {
+                let mut shading_normal = utilities::unit_vector(n);
+                if shading_normal.dot(geometric_normal) < 0.0 {
+                    shading_normal = -shading_normal;
+                }
+                rec.normal = if rec.front_face { shading_normal } else { -shading_normal };
+            }
+        }
+
+        Some(rec)
+    }
+
+    fn bounding_box(&self, _time0: f64, _time1: f64, output_box: &mut AABB) -> bool {
+        let (_, [p0, p1, p2]) = self.vertices();
+        *output_box = triangle_bounding_box(p0, p1, p2);
+        true
+    }
+}
+
+//an indexed triangle mesh with its own BVH over the triangles, so a large mesh is a
+//single object in the scene's top-level list
+pub struct TriangleMesh {
+    bvh: BVH<MeshTriangle>,
+}
+
+impl TriangleMesh {
+    pub fn new(data: MeshData, mat_ptr: Arc<dyn Material>) -> Self {
+        let vertex_count = data.positions.len();
+        assert!(
+            data.normals.is_empty() || data.normals.len() == vertex_count,
+            "mesh needs one normal per vertex"
+        );
+        assert!(
+            data.uvs.is_empty() || data.uvs.len() == vertex_count,
+            "mesh needs one texture coordinate per vertex"
+        );
+        assert!(
+            data.colors.is_empty() || data.colors.len() == vertex_count,
+            "mesh needs one color per vertex"
+        );
+        assert!(
+            data.indices.iter().flatten().all(|&i| (i as usize) < vertex_count),
+            "mesh index out of range"
+        );
+
+        let triangle_count = data.indices.len() as u32;
+        let mesh = Arc::new(Mesh { data, mat_ptr });
+        let triangles = (0..triangle_count)
+            .map(|index| MeshTriangle {
+                mesh: Arc::clone(&mesh),
+                index,
+            })
+            .collect();
+
+        TriangleMesh {
+            bvh: BVH::from_primitives(triangles, 0.0, 1.0),
+        }
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.bvh.primitives().len()
+    }
+}
+
+impl Hittable for TriangleMesh {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, sampler: &mut dyn Sampler) -> Option<HitRecord> {
+        self.bvh.hit(r, t_min, t_max, sampler)
+    }
+
+    fn bounding_box(&self, time0: f64, time1: f64, output_box: &mut AABB) -> bool {
+        self.bvh.bounding_box(time0, time1, output_box)
+    }
+}
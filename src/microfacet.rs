@@ -0,0 +1,167 @@
+//The GGX (Trowbridge-Reitz) microfacet distribution with Smith masking-shadowing, and
+//sampling of the normals visible from a direction (Heitz 2018, "Sampling the GGX
+//Distribution of Visible Normals"). Directions are in a local frame where the
+//macro-surface normal is +z.
+
+use crate::sampler::Sampler;
+use crate::utilities;
+use cgmath::*;
+
+const PI: f64 = std::f64::consts::PI;
+
+//below this the distribution is too peaked to evaluate reliably
+const MIN_ALPHA: f64 = 1e-3;
+
+#[derive(Clone, Copy)]
+pub struct Ggx {
+    alpha: f64,
+}
+
+impl Ggx {
+    //perceptual roughness in [0, 1]; alpha = roughness^2
+    pub fn from_roughness(roughness: f64) -> Self {
+        let roughness = roughness.clamp(0.0, 1.0);
+        Ggx {
+            alpha: (roughness * roughness).max(MIN_ALPHA),
+        }
+    }
+
+    //density of micro-normals h, per unit projected area
+    pub fn d(&self, h: Vector3<f64>) -> f64 {
+        if h.z <= 0.0 {
+            return 0.0;
+        }
+        let a2 = self.alpha * self.alpha;
+        let cos2 = h.z * h.z;
+        let t = cos2 * (a2 - 1.0) + 1.0;
+        a2 / (PI * t * t)
+    }
+
+    fn lambda(&self, v: Vector3<f64>) -> f64 {
+        let cos2 = v.z * v.z;
+        if cos2 <= 0.0 {
+            return f64::INFINITY;
+        }
+        let tan2 = (1.0 - cos2).max(0.0) / cos2;
+        0.5 * (-1.0 + (1.0 + self.alpha * self.alpha * tan2).sqrt())
+    }
+
+    //fraction of the micro-surface seen from v
+    pub fn g1(&self, v: Vector3<f64>) -> f64 {
+        1.0 / (1.0 + self.lambda(v))
+    }
+
+    //height-correlated masking-shadowing for the pair of directions
+    pub fn g2(&self, wo: Vector3<f64>, wi: Vector3<f64>) -> f64 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    //density of the visible normal h as seen from wo
+    pub fn visible_d(&self, wo: Vector3<f64>, h: Vector3<f64>) -> f64 {
+        if wo.z <= 0.0 {
+            return 0.0;
+        }
+        self.g1(wo) * wo.dot(h).max(0.0) * self.d(h) / wo.z
+    }
+
+    //a micro-normal distributed as visible_d
+    pub fn sample_visible_normal(&self, wo: Vector3<f64>, sampler: &mut dyn Sampler) -> Vector3<f64> {
+        //stretch the view so the distribution becomes a hemisphere of radius 1
+        let vh = utilities::unit_vector(Vector3::new(self.alpha * wo.x, self.alpha * wo.y, wo.z));
+        let len2 = vh.x * vh.x + vh.y * vh.y;
+        let t1 = if len2 > 0.0 {
+            Vector3::new(-vh.y, vh.x, 0.0) / len2.sqrt()
+        } else {
+            Vector3::new(1.0, 0.0, 0.0)
+        };
+        let t2 = vh.cross(t1);
+
+        //a point on the projected disk, squeezed onto the visible half
+        let (u1, u2) = sampler.get_2d();
+        let r = u1.sqrt();
+        let phi = 2.0 * PI * u2;
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1.0 + vh.z);
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * r * phi.sin();
+
+        let nh = t1 * p1 + t2 * p2 + vh * (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt();
+        utilities::unit_vector(Vector3::new(self.alpha * nh.x, self.alpha * nh.y, nh.z.max(0.0)))
+    }
+
+    //solid-angle density of refracting wo into wi through the visible normal h, where eta
+    //is the index of refraction on wi's side over the one on wo's
+    pub fn transmission_pdf(&self, wo: Vector3<f64>, wi: Vector3<f64>, h: Vector3<f64>, eta: f64) -> f64 {
+        let denom = wo.dot(h) + eta * wi.dot(h);
+        if denom == 0.0 {
+            return 0.0;
+        }
+        self.visible_d(wo, h) * eta * eta * wi.dot(h).abs() / (denom * denom)
+    }
+
+    //solid-angle density of reflecting wo into wi through a visible normal
+    pub fn reflection_pdf(&self, wo: Vector3<f64>, wi: Vector3<f64>) -> f64 {
+        let h = wo + wi;
+        if h.magnitude2() == 0.0 {
+            return 0.0;
+        }
+        let h = utilities::unit_vector(h);
+        let cos = wo.dot(h);
+        if cos <= 0.0 {
+            return 0.0;
+        }
+        self.visible_d(wo, h) / (4.0 * cos)
+    }
+}
+
+//Schlick's approximation of the Fresnel reflectance, per channel
+pub fn schlick(f0: Vector3<f64>, cos: f64) -> Vector3<f64> {
+    let m = (1.0 - cos.clamp(0.0, 1.0)).powi(5);
+    f0 + (Vector3::new(1.0, 1.0, 1.0) - f0) * m
+}
+
+//Schlick's approximation for the boundary of a dielectric, where eta is the index of
+//refraction beyond it over the one on the side of the incoming light. The angle is
+//taken in the denser medium; beyond the critical angle everything is reflected.
+pub fn dielectric_reflectance(cos_i: f64, eta: f64) -> f64 {
+    let cos_i = cos_i.clamp(0.0, 1.0);
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return 1.0;
+    }
+    let cos = if eta < 1.0 { (1.0 - sin2_t).sqrt() } else { cos_i };
+    let r0 = ((eta - 1.0) / (eta + 1.0)).powi(2);
+    r0 + (1.0 - r0) * (1.0 - cos).powi(5)
+}
+
+//the micro-normal, on wo's side, that reflects (wi above the surface) or refracts (wi
+//below it) wo into wi; None if no microfacet facing wo can do so
+pub fn half_vector(wo: Vector3<f64>, wi: Vector3<f64>, eta: f64) -> Option<Vector3<f64>> {
+    let reflected = wi.z > 0.0;
+    let h = if reflected { wo + wi } else { wo + wi * eta };
+    if h.magnitude2() == 0.0 {
+        return None;
+    }
+    let h = utilities::unit_vector(h);
+    let h = if h.z < 0.0 { -h } else { h };
+    if wo.dot(h) <= 0.0 || (wi.dot(h) > 0.0) != reflected {
+        return None;
+    }
+    Some(h)
+}
+
+//v refracted through n into the side where the index of refraction is eta times that
+//on v's; None on total internal reflection. v points away from the surface.
+pub fn refract(v: Vector3<f64>, n: Vector3<f64>, eta: f64) -> Option<Vector3<f64>> {
+    let cos_i = v.dot(n);
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return None;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    Some(n * (cos_i / eta - cos_t) - v / eta)
+}
+
+//mirrors v about n; unlike material::reflect, v points away from the surface
+pub fn reflect(v: Vector3<f64>, n: Vector3<f64>) -> Vector3<f64> {
+    n * (2.0 * v.dot(n)) - v
+}
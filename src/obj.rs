@@ -0,0 +1,545 @@
+//Wavefront OBJ import. Supports positions, texture coordinates and normals, polygons of
+//any size (triangulated by ear clipping), negative (relative) indices, `g`/`o` groups
+//and `usemtl` with materials from `mtllib` files. Every (group, material) pair becomes
+//one mesh. Lines, points, smoothing groups and free-form geometry are ignored.
+
+use crate::color::Color;
+use crate::material::{Dielectric, DiffuseLight, Lambertian, Material, Metal};
+use crate::mesh::{MeshData, MeshError};
+use crate::texture::{ImageTexture, SolidColor, Texture};
+use cgmath::*;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+pub struct ObjGroup {
+    pub name: String,
+    pub material_name: Option<String>,
+    pub material: Arc<dyn Material>,
+    pub data: MeshData,
+}
+
+//one corner of a face: indices into the position, texture coordinate and normal lists
+type Corner = (usize, Option<usize>, Option<usize>);
+
+struct GroupBuilder {
+    name: String,
+    material_name: Option<String>,
+    triangles: Vec<[Corner; 3]>,
+}
+
+pub fn load(path: &Path) -> Result<Vec<ObjGroup>, MeshError> {
+    let source = read(path)?;
+    let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+    parse(&source, base_dir)
+}
+
+//relative file names (material libraries, textures) are resolved against base_dir
+pub fn parse(source: &str, base_dir: &Path) -> Result<Vec<ObjGroup>, MeshError> {
+    let mut positions: Vec<Point3<f64>> = Vec::new();
+    let mut uvs: Vec<(f64, f64)> = Vec::new();
+    let mut normals: Vec<Vector3<f64>> = Vec::new();
+    let mut materials: HashMap<String, Arc<dyn Material>> = HashMap::new();
+    let mut textures: HashMap<PathBuf, Arc<dyn Texture>> = HashMap::new();
+
+    let mut groups: Vec<GroupBuilder> = Vec::new();
+    let mut current: Option<usize> = None;
+    let mut group_name = String::from("default");
+    let mut material_name: Option<String> = None;
+
+    for (line, text) in logical_lines(source) {
+        let error = |message: String| MeshError { line, message };
+        let mut tokens = text.split_whitespace();
+        let keyword = match tokens.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+        let args: Vec<&str> = tokens.collect();
+
+        match keyword {
+            "v" => {
+                let v = numbers(&args, 3, 4).map_err(error)?;
+                positions.push(Point3::new(v[0], v[1], v[2]));
+            }
+            "vt" => {
+                let v = numbers(&args, 1, 3).map_err(error)?;
+                uvs.push((v[0], v.get(1).copied().unwrap_or(0.0)));
+            }
+            "vn" => {
+                let v = numbers(&args, 3, 3).map_err(error)?;
+                normals.push(Vector3::new(v[0], v[1], v[2]));
+            }
+            "f" => {
+                if args.len() < 3 {
+                    return Err(error(String::from("a face needs at least 3 vertices")));
+                }
+                let corners = args
+                    .iter()
+                    .map(|arg| corner(arg, positions.len(), uvs.len(), normals.len()))
+                    .collect::<Result<Vec<Corner>, String>>()
+                    .map_err(error)?;
+
+                let index = match current {
+                    Some(index) => index,
+                    None => {
+                        let index = group_index(&mut groups, &group_name, &material_name);
+                        current = Some(index);
+                        index
+                    }
+                };
+                let points: Vec<Point3<f64>> = corners.iter().map(|c| positions[c.0]).collect();
+                for [a, b, c] in triangulate(&points) {
+                    groups[index].triangles.push([corners[a], corners[b], corners[c]]);
+                }
+            }
+            "g" | "o" => {
+                group_name = if args.is_empty() {
+                    String::from("default")
+                } else {
+                    args.join(" ")
+                };
+                current = None;
+            }
+            "usemtl" => {
+                material_name = args.first().map(|name| name.to_string());
+                current = None;
+            }
+            "mtllib" => {
+                for file in args {
+                    let mtl_path = base_dir.join(file.replace('\\', "/"));
+                    let source = read(&mtl_path)?;
+                    let mtl_dir = mtl_path.parent().unwrap_or(base_dir);
+                    parse_mtl(&source, mtl_dir, &mut materials, &mut textures).map_err(|e| MeshError {
+                        line,
+                        message: format!("{}: {}", mtl_path.display(), e),
+                    })?;
+                }
+            }
+            _ => {} //s, l, p, curves and surfaces
+        }
+    }
+
+    //faces without usemtl, or naming a material that is missing from the libraries
+    //(a common exporter quirk), fall back to a neutral grey
+    let default_material: Arc<dyn Material> = Arc::new(Lambertian::new(Color::new(0.8, 0.8, 0.8)));
+    let result: Vec<ObjGroup> = groups
+        .into_iter()
+        .filter(|group| !group.triangles.is_empty())
+        .map(|group| ObjGroup {
+            material: group
+                .material_name
+                .as_ref()
+                .and_then(|name| materials.get(name))
+                .cloned()
+                .unwrap_or_else(|| Arc::clone(&default_material)),
+            name: group.name,
+            material_name: group.material_name,
+            data: build_mesh(&group.triangles, &positions, &uvs, &normals),
+        })
+        .collect();
+
+    if result.is_empty() {
+        return Err(MeshError {
+            line: 0,
+            message: String::from("the file contains no faces"),
+        });
+    }
+    Ok(result)
+}
+
+fn read(path: &Path) -> Result<String, MeshError> {
+    fs::read_to_string(path).map_err(|e| MeshError {
+        line: 0,
+        message: format!("cannot read {}: {}", path.display(), e),
+    })
+}
+
+//joins lines ending in a backslash and strips comments; yields (first line number, text)
+fn logical_lines(source: &str) -> Vec<(usize, String)> {
+    let mut lines = Vec::new();
+    let mut pending: Option<(usize, String)> = None;
+
+    for (index, raw) in source.lines().enumerate() {
+        let text = raw.split('#').next().unwrap_or("");
+        let (line, mut joined) = pending.take().unwrap_or((index + 1, String::new()));
+        match text.trim_end().strip_suffix('\\') {
+            Some(head) => {
+                joined.push_str(head);
+                joined.push(' ');
+                pending = Some((line, joined));
+            }
+            None => {
+                joined.push_str(text);
+                lines.push((line, joined));
+            }
+        }
+    }
+    if let Some(last) = pending {
+        lines.push(last);
+    }
+    lines
+}
+
+fn numbers(args: &[&str], min: usize, max: usize) -> Result<Vec<f64>, String> {
+    if args.len() < min || args.len() > max {
+        return Err(format!("expected {} to {} numbers, found {}", min, max, args.len()));
+    }
+    args.iter()
+        .map(|a| a.parse::<f64>().map_err(|_| format!("`{}` is not a number", a)))
+        .collect()
+}
+
+//resolves a 1-based or negative (counted back from the end) index into a list of `count`
+fn index(text: &str, count: usize, what: &str) -> Result<usize, String> {
+    let i: i64 = text
+        .parse()
+        .map_err(|_| format!("`{}` is not a valid {} index", text, what))?;
+    let resolved = if i > 0 { i - 1 } else { count as i64 + i };
+    if i == 0 || resolved < 0 || resolved >= count as i64 {
+        return Err(format!("{} index {} is out of range (have {})", what, i, count));
+    }
+    Ok(resolved as usize)
+}
+
+//`v`, `v/vt`, `v//vn` or `v/vt/vn`
+fn corner(text: &str, positions: usize, uvs: usize, normals: usize) -> Result<Corner, String> {
+    let mut parts = text.split('/');
+    let v = index(parts.next().unwrap_or(""), positions, "vertex")?;
+    let vt = match parts.next() {
+        Some(t) if !t.is_empty() => Some(index(t, uvs, "texture coordinate")?),
+        _ => None,
+    };
+    let vn = match parts.next() {
+        Some(n) if !n.is_empty() => Some(index(n, normals, "normal")?),
+        _ => None,
+    };
+    Ok((v, vt, vn))
+}
+
+fn group_index(groups: &mut Vec<GroupBuilder>, name: &str, material_name: &Option<String>) -> usize {
+    if let Some(index) = groups
+        .iter()
+        .position(|g| g.name == name && g.material_name == *material_name)
+    {
+        return index;
+    }
+    groups.push(GroupBuilder {
+        name: name.to_string(),
+        material_name: material_name.clone(),
+        triangles: Vec::new(),
+    });
+    groups.len() - 1
+}
+
+//ear clipping in the plane of the polygon, so concave faces are split correctly.
+//Degenerate or self-intersecting polygons fall back to a fan.
+pub(crate) fn triangulate(points: &[Point3<f64>]) -> Vec<[usize; 3]> {
+    let n = points.len();
+    let fan = |corners: &[usize]| -> Vec<[usize; 3]> {
+        (1..corners.len() - 1)
+            .map(|i| [corners[0], corners[i], corners[i + 1]])
+            .collect()
+    };
+    if n == 3 {
+        return vec![[0, 1, 2]];
+    }
+
+    //Newell's method gives a normal that is robust for non-planar polygons
+    let mut normal = Vector3::new(0.0, 0.0, 0.0);
+    for i in 0..n {
+        let (a, b) = (points[i], points[(i + 1) % n]);
+        normal.x += (a.y - b.y) * (a.z + b.z);
+        normal.y += (a.z - b.z) * (a.x + b.x);
+        normal.z += (a.x - b.x) * (a.y + b.y);
+    }
+    let mut remaining: Vec<usize> = (0..n).collect();
+    if normal.magnitude2() == 0.0 {
+        return fan(&remaining);
+    }
+
+    let mut triangles = Vec::with_capacity(n - 2);
+    while remaining.len() > 3 {
+        let m = remaining.len();
+        let ear = (0..m).find(|&i| {
+            let (ia, ib, ic) = (remaining[(i + m - 1) % m], remaining[i], remaining[(i + 1) % m]);
+            let (a, b, c) = (points[ia], points[ib], points[ic]);
+            let convex = (b - a).cross(c - b).dot(normal) > 0.0;
+            convex
+                && !remaining
+                    .iter()
+                    .any(|&j| j != ia && j != ib && j != ic && inside_triangle(points[j], a, b, c, normal))
+        });
+        match ear {
+            Some(i) => {
+                triangles.push([remaining[(i + m - 1) % m], remaining[i], remaining[(i + 1) % m]]);
+                remaining.remove(i);
+            }
+            None => {
+                triangles.extend(fan(&remaining));
+                return triangles;
+            }
+        }
+    }
+    triangles.push([remaining[0], remaining[1], remaining[2]]);
+    triangles
+}
+
+fn inside_triangle(p: Point3<f64>, a: Point3<f64>, b: Point3<f64>, c: Point3<f64>, normal: Vector3<f64>) -> bool {
+    (b - a).cross(p - a).dot(normal) >= 0.0
+        && (c - b).cross(p - b).dot(normal) >= 0.0
+        && (a - c).cross(p - c).dot(normal) >= 0.0
+}
+
+//turns the face corners into one vertex per distinct (position, uv, normal) triple.
+//Normals are only kept if every corner has one; missing texture coordinates become 0.
+fn build_mesh(
+    triangles: &[[Corner; 3]],
+    positions: &[Point3<f64>],
+    uvs: &[(f64, f64)],
+    normals: &[Vector3<f64>],
+) -> MeshData {
+    let corners = || triangles.iter().flatten();
+    let use_normals = corners().all(|c| c.2.is_some());
+    let use_uvs = corners().any(|c| c.1.is_some());
+
+    let mut data = MeshData::default();
+    let mut vertices: HashMap<Corner, u32> = HashMap::new();
+    for triangle in triangles {
+        let mut face = [0u32; 3];
+        for (k, &(v, vt, vn)) in triangle.iter().enumerate() {
+            let key = (v, if use_uvs { vt } else { None }, if use_normals { vn } else { None });
+            face[k] = *vertices.entry(key).or_insert_with(|| {
+                data.positions.push(positions[v]);
+                if use_uvs {
+                    data.uvs.push(vt.map_or((0.0, 0.0), |t| uvs[t]));
+                }
+                if let (true, Some(n)) = (use_normals, vn) {
+                    data.normals.push(normals[n]);
+                }
+                (data.positions.len() - 1) as u32
+            });
+        }
+        data.indices.push(face);
+    }
+    data
+}
+
+//the subset of MTL that maps onto the materials we have
+struct MtlMaterial {
+    kd: Color,
+    ks: Color,
+    ke: Color,
+    ns: f64,
+    ni: f64,
+    dissolve: f64,
+    illum: u32,
+    map_kd: Option<PathBuf>,
+}
+
+impl MtlMaterial {
+    fn new() -> Self {
+        MtlMaterial {
+            kd: Color::new(0.8, 0.8, 0.8),
+            ks: Color::new(0.0, 0.0, 0.0),
+            ke: Color::new(0.0, 0.0, 0.0),
+            ns: 0.0,
+            ni: 1.5,
+            dissolve: 1.0,
+            illum: 2,
+            map_kd: None,
+        }
+    }
+
+    //emissive beats transparent beats mirror-like beats diffuse
+    fn build(&self, textures: &mut HashMap<PathBuf, Arc<dyn Texture>>) -> Result<Arc<dyn Material>, String> {
+        let max = |c: Color| c.x.max(c.y).max(c.z);
+
+        if max(self.ke) > 0.0 {
+            return Ok(Arc::new(DiffuseLight::new(Arc::new(SolidColor::new(self.ke)))));
+        }
+        if self.dissolve < 1.0 || matches!(self.illum, 4 | 6 | 7 | 9) {
+            let ior = if self.ni > 1.0 { self.ni } else { 1.5 };
+            return Ok(Arc::new(Dielectric::new(ior)));
+        }
+        if self.illum == 3 || (max(self.ks) > 0.0 && max(self.kd) == 0.0 && self.map_kd.is_none()) {
+            //Phong exponent to roughness, as in Walter et al. 2007
+            let fuzz = (2.0 / (self.ns + 2.0)).sqrt();
+            return Ok(Arc::new(Metal::new(self.ks, fuzz)));
+        }
+
+        let albedo: Arc<dyn Texture> = match &self.map_kd {
+            Some(file) => match textures.get(file) {
+                Some(texture) => Arc::clone(texture),
+                None => {
+                    if !file.is_file() {
+                        return Err(format!("texture {} does not exist", file.display()));
+                    }
+                    let texture = ImageTexture::load(file)
+                        .map_err(|e| format!("cannot load texture {}: {}", file.display(), e))?;
+                    let texture: Arc<dyn Texture> = Arc::new(texture);
+                    textures.insert(file.clone(), Arc::clone(&texture));
+                    texture
+                }
+            },
+            None => Arc::new(SolidColor::new(self.kd)),
+        };
+        Ok(Arc::new(Lambertian::new_texture(albedo)))
+    }
+}
+
+fn parse_mtl(
+    source: &str,
+    base_dir: &Path,
+    materials: &mut HashMap<String, Arc<dyn Material>>,
+    textures: &mut HashMap<PathBuf, Arc<dyn Texture>>,
+) -> Result<(), String> {
+    let mut current: Option<(String, MtlMaterial)> = None;
+    let mut finish = |current: Option<(String, MtlMaterial)>,
+                      textures: &mut HashMap<PathBuf, Arc<dyn Texture>>|
+     -> Result<(), String> {
+        if let Some((name, mtl)) = current {
+            let material = mtl.build(textures).map_err(|e| format!("material `{}`: {}", name, e))?;
+            materials.insert(name, material);
+        }
+        Ok(())
+    };
+
+    for (line, text) in logical_lines(source) {
+        let error = |message: String| format!("line {}: {}", line, message);
+        let mut tokens = text.split_whitespace();
+        let keyword = match tokens.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+        let args: Vec<&str> = tokens.collect();
+
+        if keyword == "newmtl" {
+            finish(current.take(), textures)?;
+            current = Some((args.join(" "), MtlMaterial::new()));
+            continue;
+        }
+        let mtl = match current.as_mut() {
+            Some((_, mtl)) => mtl,
+            None => continue, //statements before the first newmtl
+        };
+        let color = |args: &[&str]| -> Result<Color, String> {
+            //a single value means grey; `spectral` and `xyz` forms are not supported
+            let v = numbers(args, 1, 3).map_err(error)?;
+            Ok(match v.len() {
+                1 => Color::new(v[0], v[0], v[0]),
+                3 => Color::new(v[0], v[1], v[2]),
+                _ => return Err(error(String::from("expected 1 or 3 color components"))),
+            })
+        };
+        let number = |args: &[&str]| -> Result<f64, String> { Ok(numbers(args, 1, 1).map_err(error)?[0]) };
+
+        match keyword {
+            "Kd" => mtl.kd = color(&args)?,
+            "Ks" => mtl.ks = color(&args)?,
+            "Ke" => mtl.ke = color(&args)?,
+            "Ns" => mtl.ns = number(&args)?,
+            "Ni" => mtl.ni = number(&args)?,
+            "d" => mtl.dissolve = number(&args)?,
+            "Tr" => mtl.dissolve = 1.0 - number(&args)?,
+            "illum" => mtl.illum = number(&args)? as u32,
+            //options such as `-s 1 1 1` come first, the file name last
+            "map_Kd" => match args.last() {
+                Some(file) => mtl.map_kd = Some(base_dir.join(file.replace('\\', "/"))),
+                None => return Err(error(String::from("map_Kd needs a file name"))),
+            },
+            _ => {}
+        }
+    }
+    finish(current, textures)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_str(source: &str) -> Result<Vec<ObjGroup>, MeshError> {
+        parse(source, Path::new(""))
+    }
+
+    #[test]
+    fn polygons_are_triangulated_with_relative_indices() {
+        //a concave L-shaped hexagon, then a quad given with negative indices
+        let source = "v 0 0 0\nv 2 0 0\nv 2 1 0\nv 1 1 0\nv 1 2 0\nv 0 2 0\nf 1 2 3 4 5 6\n\
+                      v 0 0 1\nv 1 0 1\nv 1 1 1\nv 0 1 1\nf -4 -3 -2 -1\n";
+        let groups = parse_str(source).unwrap();
+        assert_eq!(groups.len(), 1);
+        let data = &groups[0].data;
+        assert_eq!(data.indices.len(), 4 + 2);
+        assert_eq!(data.positions.len(), 10);
+
+        //the triangles of the hexagon cover exactly its area of 3
+        let area: f64 = data.indices[..4]
+            .iter()
+            .map(|t| {
+                let [a, b, c] = t.map(|i| data.positions[i as usize]);
+                (b - a).cross(c - a).magnitude() / 2.0
+            })
+            .sum();
+        assert!((area - 3.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn groups_materials_and_attributes() {
+        let source = "v 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0 0\nvt 1 0\nvt 0 1\nvn 0 0 1\n\
+                      o first\nf 1/1/1 2/2/1 3/3/1\n\
+                      o second\nusemtl missing\nf 1 2 3\n";
+        let groups = parse_str(source).unwrap();
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].name, "first");
+        assert_eq!(groups[0].data.uvs, vec![(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)]);
+        assert_eq!(groups[0].data.normals.len(), 3);
+        assert_eq!(groups[1].name, "second");
+        assert_eq!(groups[1].material_name.as_deref(), Some("missing"));
+        assert!(groups[1].data.normals.is_empty());
+    }
+
+    #[test]
+    fn errors_name_their_line() {
+        let cases = [
+            ("v 0 0 0\nv 1 0 0\nf 1 2 3\n", 3, "out of range"),
+            ("v 0 0 0\nv 1 0 x\n", 2, "not a number"),
+            ("v 0 0 0\nf 1 1\n", 2, "at least 3"),
+            ("v 0 0 0\n", 0, "no faces"),
+        ];
+        for (source, line, message) in cases {
+            let error = match parse_str(source) {
+                Ok(_) => panic!("{:?} should not parse", source),
+                Err(e) => e,
+            };
+            assert_eq!(error.line, line, "{}", error.message);
+            assert!(error.message.contains(message), "{}", error.message);
+        }
+    }
+
+    #[test]
+    fn bundled_model_loads_with_its_materials() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes/models/shapes.obj");
+        let groups = load(&path).unwrap();
+        assert!(groups.iter().all(|g| !g.data.indices.is_empty()));
+        assert!(groups.iter().any(|g| g.material_name.is_some()));
+    }
+
+    #[test]
+    fn undecodable_map_kd_names_the_library_and_the_texture() {
+        let dir = std::env::temp_dir().join(format!("ray_tracer_obj_bad_texture_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("bad.png"), b"not a png").unwrap();
+        fs::write(dir.join("bad.mtl"), "newmtl painted\nKd 1 1 1\nmap_Kd bad.png\n").unwrap();
+        let source = "mtllib bad.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\nusemtl painted\nf 1 2 3\n";
+        let error = match parse(source, &dir) {
+            Ok(_) => panic!("the texture should not load"),
+            Err(e) => e,
+        };
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(error.line, 1);
+        assert!(error.message.contains("bad.mtl"), "{}", error.message);
+        assert!(error.message.contains("cannot load texture"), "{}", error.message);
+        assert!(error.message.contains("bad.png"), "{}", error.message);
+    }
+}
@@ -1,26 +1,30 @@
 use crate::aabb::AABB;
-use crate::color::Color;
 use crate::hittable::{HitRecord, Hittable};
 use crate::hittable_list::HittableList;
 use crate::material::{Material, Isotropic};
+use crate::onb::Onb;
 use crate::ray::Ray;
+use crate::sampler::Sampler;
 use crate::texture::Texture;
 use crate::utilities;
-use std::rc::Rc;
+use std::sync::Arc;
 use cgmath::*;
 
 const PI: f64 = std::f64::consts::PI;
-//use crate::ray::Ray;
-//use crate::color::Color;
+
+//the area of the parallelogram spanned by u and v once both are mapped by m
+fn parallelogram_area(m: &Matrix3<f64>, u: Vector3<f64>, v: Vector3<f64>) -> f64 {
+    (m * u).cross(m * v).magnitude()
+}
 
 pub struct Sphere {
     pub center: Point3<f64>,
     pub radius: f64,
-    pub mat_ptr: Rc<dyn Material>,
+    pub mat_ptr: Arc<dyn Material>,
 }
 
 impl Sphere {
-    pub fn new(center: Point3<f64>, radius: f64, mat_ptr: Rc<dyn Material>) -> Self {
+    pub fn new(center: Point3<f64>, radius: f64, mat_ptr: Arc<dyn Material>) -> Self {
         Sphere {
             center,
             radius,
@@ -34,12 +38,9 @@ impl Sphere {
 
         (phi / (2.0 * PI), theta / PI)
     }
-}
-
-impl Hittable for Sphere {
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-        let rec: HitRecord;
 
+    //the deterministic intersection, also used to evaluate pdf_value
+    fn intersect(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
         let oc = r.origin - self.center;
         let a = r.direction.magnitude2();
         let half_b = oc.dot(r.direction);
@@ -62,17 +63,22 @@ impl Hittable for Sphere {
         let outward_normal :Vector3<f64> = (r.at(root) - self.center) / self.radius;
         let (u, v) = Self::get_sphere_uv(outward_normal);
 
-        rec = HitRecord::new(
+        let rec = HitRecord::new(
             root,
-            r.at(root),
             r,
             outward_normal,
             u,
             v,
-            Rc::clone(&self.mat_ptr),
+            Arc::clone(&self.mat_ptr),
         );
         Some(rec)
     }
+}
+
+impl Hittable for Sphere {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, _sampler: &mut dyn Sampler) -> Option<HitRecord> {
+        self.intersect(r, t_min, t_max)
+    }
 
     fn bounding_box(&self, _time0: f64, _time1: f64, output_box: &mut AABB) -> bool {
         *output_box = AABB::new(
@@ -82,6 +88,36 @@ impl Hittable for Sphere {
 
         true
     }
+
+    //uniform over the cone of directions subtended by the sphere
+    fn pdf_value(&self, origin: Point3<f64>, direction: Vector3<f64>) -> f64 {
+        if self.intersect(&Ray::new(origin, direction, 0.0), 0.001, f64::INFINITY).is_none() {
+            return 0.0;
+        }
+
+        let distance_squared = (self.center - origin).magnitude2();
+        if distance_squared <= self.radius * self.radius {
+            return 1.0 / (4.0 * PI);
+        }
+        let cos_theta_max = (1.0 - self.radius * self.radius / distance_squared).sqrt();
+        let solid_angle = 2.0 * PI * (1.0 - cos_theta_max);
+
+        1.0 / solid_angle
+    }
+
+    fn random(&self, origin: Point3<f64>, sampler: &mut dyn Sampler) -> Vector3<f64> {
+        let direction = self.center - origin;
+        let distance_squared = direction.magnitude2();
+        if distance_squared <= self.radius * self.radius {
+            return utilities::random_unit_vector(sampler);
+        }
+        let uvw = Onb::build_from_w(direction);
+        uvw.local(utilities::random_to_sphere(sampler, self.radius, distance_squared))
+    }
+
+    fn area(&self) -> Option<f64> {
+        Some(4.0 * PI * self.radius * self.radius)
+    }
 }
 
 //moving Sphere
@@ -91,7 +127,7 @@ pub struct MovingSphere {
     pub time0: f64,
     pub time1: f64,
     pub radius: f64,
-    pub mat_ptr: Rc<dyn Material>,
+    pub mat_ptr: Arc<dyn Material>,
 }
 
 impl MovingSphere {
@@ -101,7 +137,7 @@ impl MovingSphere {
         time0: f64,
         time1: f64,
         radius: f64,
-        mat_ptr: Rc<dyn Material>,
+        mat_ptr: Arc<dyn Material>,
     ) -> Self {
         MovingSphere {
             center0,
@@ -119,20 +155,8 @@ impl MovingSphere {
     }
 }
 
-impl Material for MovingSphere {
-    fn scatter(
-        &self,
-        _r_in: &Ray,
-        _rec: &HitRecord,
-        _attenuation: &mut Color,
-        _scattered: &mut Ray,
-    ) -> bool {
-        true
-    }
-}
-
 impl Hittable for MovingSphere {
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, _sampler: &mut dyn Sampler) -> Option<HitRecord> {
         let oc = r.origin - self.center(r.time);
         let a = r.direction.magnitude2();
         let half_b = oc.dot(r.direction);
@@ -156,12 +180,11 @@ impl Hittable for MovingSphere {
 
         let rec = HitRecord::new(
             root,
-            r.at(root),
             r,
             outward_normal,
             0.0,
             0.0,
-            Rc::clone(&self.mat_ptr),
+            Arc::clone(&self.mat_ptr),
         );
         Some(rec)
     }
@@ -180,6 +203,10 @@ impl Hittable for MovingSphere {
         *output_box = AABB::surrounding_box(&box0, &box1);
         true
     }
+
+    fn area(&self) -> Option<f64> {
+        Some(4.0 * PI * self.radius * self.radius)
+    }
 }
 
 //struct XyRect
@@ -189,11 +216,11 @@ pub struct XyRect {
     y0: f64,
     y1: f64,
     k: f64,
-    mp: Rc<dyn Material>,
+    mp: Arc<dyn Material>,
 }
 
 impl XyRect {
-    pub fn new(x0: f64, x1: f64, y0: f64, y1: f64, k: f64, mp: Rc<dyn Material>) -> Self {
+    pub fn new(x0: f64, x1: f64, y0: f64, y1: f64, k: f64, mp: Arc<dyn Material>) -> Self {
         Self {
             x0,
             x1,
@@ -203,10 +230,8 @@ impl XyRect {
             mp,
         }
     }
-}
 
-impl Hittable for XyRect {
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    fn intersect(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
         let t = (self.k - r.origin.z) / (r.direction.z);
         if t < t_min || t > t_max {
             return None;
@@ -222,10 +247,16 @@ impl Hittable for XyRect {
         let v = (y - self.y0) / (self.y1 - self.y0);
         let outward_normal = Vector3::new(0.0, 0.0, 1.0);
         
-        let rec = HitRecord::new(t, r.at(t), r, outward_normal, u, v, Rc::clone(&self.mp));
+        let rec = HitRecord::new(t, r, outward_normal, u, v, Arc::clone(&self.mp));
 
         Some(rec)
     }
+}
+
+impl Hittable for XyRect {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, _sampler: &mut dyn Sampler) -> Option<HitRecord> {
+        self.intersect(r, t_min, t_max)
+    }
 
     fn bounding_box(&self, _time0: f64, _time1: f64, output_box: &mut AABB) -> bool {
         *output_box = AABB::new(
@@ -234,6 +265,34 @@ impl Hittable for XyRect {
         );
         true
     }
+
+    fn pdf_value(&self, origin: Point3<f64>, direction: Vector3<f64>) -> f64 {
+        let rec = match self.intersect(&Ray::new(origin, direction, 0.0), 0.001, f64::INFINITY) {
+            Some(rec) => rec,
+            None => return 0.0,
+        };
+
+        let area = (self.x1 - self.x0) * (self.y1 - self.y0);
+        let distance_squared = rec.t * rec.t * direction.magnitude2();
+        let cosine = (direction.z / direction.magnitude()).abs();
+
+        distance_squared / (cosine * area)
+    }
+
+    fn random(&self, origin: Point3<f64>, sampler: &mut dyn Sampler) -> Vector3<f64> {
+        let (s, t) = sampler.get_2d();
+        let x = self.x0 + s * (self.x1 - self.x0);
+        let y = self.y0 + t * (self.y1 - self.y0);
+        Point3::new(x, y, self.k) - origin
+    }
+
+    fn area(&self) -> Option<f64> {
+        Some((self.x1 - self.x0) * (self.y1 - self.y0))
+    }
+
+    fn transformed_area(&self, m: &Matrix3<f64>) -> Option<f64> {
+        Some(parallelogram_area(m, Vector3::new(self.x1 - self.x0, 0.0, 0.0), Vector3::new(0.0, self.y1 - self.y0, 0.0)))
+    }
 }
 
 //struct YzRect
@@ -243,11 +302,11 @@ pub struct YzRect {
     z0: f64,
     z1: f64,
     k: f64,
-    mp: Rc<dyn Material>,
+    mp: Arc<dyn Material>,
 }
 
 impl YzRect {
-    pub fn new(y0: f64, y1: f64, z0: f64, z1: f64, k: f64, mp: Rc<dyn Material>) -> Self {
+    pub fn new(y0: f64, y1: f64, z0: f64, z1: f64, k: f64, mp: Arc<dyn Material>) -> Self {
         YzRect {
             y0,
             y1,
@@ -257,10 +316,8 @@ impl YzRect {
             mp,
         }
     }
-}
 
-impl Hittable for YzRect {
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    fn intersect(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
         let t = (self.k - r.origin.x) / (r.direction.x);
         if t < t_min || t > t_max {
             return None;
@@ -276,10 +333,16 @@ impl Hittable for YzRect {
         let v = (z - self.z0) / (self.z1 - self.z0);
         let outward_normal = Vector3::new(1.0, 0.0, 0.0);
 
-        let rec = HitRecord::new(t, r.at(t), r, outward_normal, u, v, Rc::clone(&self.mp));
+        let rec = HitRecord::new(t, r, outward_normal, u, v, Arc::clone(&self.mp));
 
         Some(rec)
     }
+}
+
+impl Hittable for YzRect {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, _sampler: &mut dyn Sampler) -> Option<HitRecord> {
+        self.intersect(r, t_min, t_max)
+    }
 
     fn bounding_box(&self, _time0: f64, _time1: f64, output_box: &mut AABB) -> bool {
         *output_box = AABB::new(
@@ -288,6 +351,34 @@ impl Hittable for YzRect {
         );
         true
     }
+
+    fn pdf_value(&self, origin: Point3<f64>, direction: Vector3<f64>) -> f64 {
+        let rec = match self.intersect(&Ray::new(origin, direction, 0.0), 0.001, f64::INFINITY) {
+            Some(rec) => rec,
+            None => return 0.0,
+        };
+
+        let area = (self.y1 - self.y0) * (self.z1 - self.z0);
+        let distance_squared = rec.t * rec.t * direction.magnitude2();
+        let cosine = (direction.x / direction.magnitude()).abs();
+
+        distance_squared / (cosine * area)
+    }
+
+    fn random(&self, origin: Point3<f64>, sampler: &mut dyn Sampler) -> Vector3<f64> {
+        let (s, t) = sampler.get_2d();
+        let y = self.y0 + s * (self.y1 - self.y0);
+        let z = self.z0 + t * (self.z1 - self.z0);
+        Point3::new(self.k, y, z) - origin
+    }
+
+    fn area(&self) -> Option<f64> {
+        Some((self.y1 - self.y0) * (self.z1 - self.z0))
+    }
+
+    fn transformed_area(&self, m: &Matrix3<f64>) -> Option<f64> {
+        Some(parallelogram_area(m, Vector3::new(0.0, self.y1 - self.y0, 0.0), Vector3::new(0.0, 0.0, self.z1 - self.z0)))
+    }
 }
 
 //struct XzRect
@@ -297,11 +388,11 @@ pub struct XzRect {
     z0: f64,
     z1: f64,
     k: f64,
-    mp: Rc<dyn Material>,
+    mp: Arc<dyn Material>,
 }
 
 impl XzRect {
-    pub fn new(x0: f64, x1: f64, z0: f64, z1: f64, k: f64, mp: Rc<dyn Material>) -> Self {
+    pub fn new(x0: f64, x1: f64, z0: f64, z1: f64, k: f64, mp: Arc<dyn Material>) -> Self {
         XzRect {
             x0,
             x1,
@@ -311,9 +402,8 @@ impl XzRect {
             mp,
         }
     }
-}
-impl Hittable for XzRect {
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+
+    fn intersect(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
         let t = (self.k - r.origin.y) / (r.direction.y);
         if t < t_min || t > t_max {
             return None;
@@ -329,17 +419,50 @@ impl Hittable for XzRect {
         let v = (z - self.z0) / (self.z1 - self.z0);
         let outward_normal = Vector3::new(0.0, 1.0, 0.0);
 
-        let rec = HitRecord::new(t, r.at(t), r, outward_normal, u, v, Rc::clone(&self.mp));
+        let rec = HitRecord::new(t, r, outward_normal, u, v, Arc::clone(&self.mp));
         Some(rec)
     }
+}
+impl Hittable for XzRect {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, _sampler: &mut dyn Sampler) -> Option<HitRecord> {
+        self.intersect(r, t_min, t_max)
+    }
 
     fn bounding_box(&self, _time0: f64, _time1: f64, output_box: &mut AABB) -> bool {
         *output_box = AABB::new(
             Point3::new(self.x0, self.k - 0.0001, self.z0),
-            Point3::new(self.x1, self.k + 0.0001, self.z0),
+            Point3::new(self.x1, self.k + 0.0001, self.z1),
         );
         true
     }
+
+    fn pdf_value(&self, origin: Point3<f64>, direction: Vector3<f64>) -> f64 {
+        let rec = match self.intersect(&Ray::new(origin, direction, 0.0), 0.001, f64::INFINITY) {
+            Some(rec) => rec,
+            None => return 0.0,
+        };
+
+        let area = (self.x1 - self.x0) * (self.z1 - self.z0);
+        let distance_squared = rec.t * rec.t * direction.magnitude2();
+        let cosine = (direction.y / direction.magnitude()).abs();
+
+        distance_squared / (cosine * area)
+    }
+
+    fn random(&self, origin: Point3<f64>, sampler: &mut dyn Sampler) -> Vector3<f64> {
+        let (s, t) = sampler.get_2d();
+        let x = self.x0 + s * (self.x1 - self.x0);
+        let z = self.z0 + t * (self.z1 - self.z0);
+        Point3::new(x, self.k, z) - origin
+    }
+
+    fn area(&self) -> Option<f64> {
+        Some((self.x1 - self.x0) * (self.z1 - self.z0))
+    }
+
+    fn transformed_area(&self, m: &Matrix3<f64>) -> Option<f64> {
+        Some(parallelogram_area(m, Vector3::new(self.x1 - self.x0, 0.0, 0.0), Vector3::new(0.0, 0.0, self.z1 - self.z0)))
+    }
 }
 
 //struct Cubic
@@ -350,7 +473,7 @@ pub struct Cubic {
 }
 
 impl Cubic {
-    pub fn new(p1: Point3<f64>, p2: Point3<f64>, mat_ptr: Rc<dyn Material>) -> Self {
+    pub fn new(p1: Point3<f64>, p2: Point3<f64>, mat_ptr: Arc<dyn Material>) -> Self {
         let mut sides = HittableList::new();
 
         //front and back
@@ -360,7 +483,7 @@ impl Cubic {
             p1.y,
             p2.y,
             p1.z,
-            Rc::clone(&mat_ptr),
+            Arc::clone(&mat_ptr),
         )));
         sides.add(Box::new(XyRect::new(
             p1.x,
@@ -368,7 +491,7 @@ impl Cubic {
             p1.y,
             p2.y,
             p2.z,
-            Rc::clone(&mat_ptr),
+            Arc::clone(&mat_ptr),
         )));
         //up and down
         sides.add(Box::new(XzRect::new(
@@ -377,7 +500,7 @@ impl Cubic {
             p1.z,
             p2.z,
             p1.y,
-            Rc::clone(&mat_ptr),
+            Arc::clone(&mat_ptr),
         )));
         sides.add(Box::new(XzRect::new(
             p1.x,
@@ -385,7 +508,7 @@ impl Cubic {
             p1.z,
             p2.z,
             p2.y,
-            Rc::clone(&mat_ptr),
+            Arc::clone(&mat_ptr),
         )));
         //left and right
         sides.add(Box::new(YzRect::new(
@@ -394,7 +517,7 @@ impl Cubic {
             p1.z,
             p2.z,
             p1.x,
-            Rc::clone(&mat_ptr),
+            Arc::clone(&mat_ptr),
         )));
         sides.add(Box::new(YzRect::new(
             p1.y,
@@ -402,7 +525,7 @@ impl Cubic {
             p1.z,
             p2.z,
             p2.x,
-            Rc::clone(&mat_ptr),
+            Arc::clone(&mat_ptr),
         )));
         Cubic {
             sides,
@@ -413,149 +536,223 @@ impl Cubic {
 }
 
 impl Hittable for Cubic {
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-        self.sides.hit(r, t_min, t_max)
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, sampler: &mut dyn Sampler) -> Option<HitRecord> {
+        self.sides.hit(r, t_min, t_max, sampler)
     }
 
     fn bounding_box(&self, _time0: f64, _time1: f64, output_box: &mut AABB) -> bool {
         *output_box = AABB::new(self.p1, self.p2);
         true
     }
-}
-
-//struct rotate_y
-pub struct RotateY {
-    ptr: Box<dyn Hittable>,
-    sin_theta: f64,
-    cos_theta: f64,
-    has_box: bool,
-    bbox: AABB,
-}
 
-impl RotateY {
-    pub fn new(ptr: Box<dyn Hittable>, angle: f64) -> Self {
-        let angle = utilities::degrees_to_radians(angle);
-        let sin_theta = angle.sin();
-        let cos_theta = angle.cos();
+    fn pdf_value(&self, origin: Point3<f64>, direction: Vector3<f64>) -> f64 {
+        self.sides.pdf_value(origin, direction)
+    }
 
-        let mut bbox = AABB::new(Point3::new(0.0, 0.0, 0.0), Point3::new(0.0, 0.0, 0.0));
-        let has_box = ptr.bounding_box(0.0, 1.0, &mut bbox);
+    fn random(&self, origin: Point3<f64>, sampler: &mut dyn Sampler) -> Vector3<f64> {
+        self.sides.random(origin, sampler)
+    }
 
-        let mut minimum = Point3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY);
-        let mut maximum = Point3::new(-f64::INFINITY, -f64::INFINITY, -f64::INFINITY);
+    fn area(&self) -> Option<f64> {
+        let d = self.p2 - self.p1;
+        Some(2.0 * (d.x * d.y + d.y * d.z + d.z * d.x))
+    }
 
-        for i in 0..2 {
-            for j in 0..2 {
-                for k in 0..2 {
-                    let x = (i as f64) * bbox.max().x + (1.0 - i as f64) * bbox.min().x;
-                    let y = (j as f64) * bbox.max().y + (1.0 - j as f64) * bbox.min().y;
-                    let z = (k as f64) * bbox.max().z + (1.0 - k as f64) * bbox.min().z;
+    //opposite faces stay parallel and equal under a linear map
+    fn transformed_area(&self, m: &Matrix3<f64>) -> Option<f64> {
+        let d = self.p2 - self.p1;
+        let (x, y, z) = (Vector3::new(d.x, 0.0, 0.0), Vector3::new(0.0, d.y, 0.0), Vector3::new(0.0, 0.0, d.z));
+        Some(2.0 * (parallelogram_area(m, x, y) + parallelogram_area(m, y, z) + parallelogram_area(m, z, x)))
+    }
+}
 
-                    let newx = cos_theta * x + sin_theta * z;
-                    let newz = -sin_theta * x + cos_theta * z;
+//struct Triangle
+pub struct Triangle {
+    p0: Point3<f64>,
+    p1: Point3<f64>,
+    p2: Point3<f64>,
+    mat_ptr: Arc<dyn Material>,
+}
 
-                    let tester = Point3::new(newx, y, newz);
+impl Triangle {
+    pub fn new(p0: Point3<f64>, p1: Point3<f64>, p2: Point3<f64>, mat_ptr: Arc<dyn Material>) -> Self {
+        Triangle { p0, p1, p2, mat_ptr }
+    }
+}
 
-                    for c in 0..3 {
-                        minimum[c] = tester[c].min(minimum[c]);
-                        maximum[c] = tester[c].min(maximum[c]);
-                    }
-                }
-            }
-        }
+//Moller-Trumbore; returns t and the barycentric coordinates of p1 and p2 at the hit point
+pub fn intersect_triangle(
+    r: &Ray,
+    p0: Point3<f64>,
+    p1: Point3<f64>,
+    p2: Point3<f64>,
+    t_min: f64,
+    t_max: f64,
+) -> Option<(f64, f64, f64)> {
+    let e1 = p1 - p0;
+    let e2 = p2 - p0;
+    let pvec = r.direction.cross(e2);
+    let det = e1.dot(pvec);
+    if det.abs() < 1e-12 {
+        return None; //the ray is parallel to the triangle
+    }
+    let inv_det = 1.0 / det;
 
-        bbox = AABB::new(minimum, maximum);
+    let tvec = r.origin - p0;
+    let b1 = tvec.dot(pvec) * inv_det;
+    if !(0.0..=1.0).contains(&b1) {
+        return None;
+    }
+    let qvec = tvec.cross(e1);
+    let b2 = r.direction.dot(qvec) * inv_det;
+    if b2 < 0.0 || b1 + b2 > 1.0 {
+        return None;
+    }
 
-        RotateY {
-            ptr,
-            sin_theta,
-            cos_theta,
-            bbox,
-            has_box,
-        }
+    let t = e2.dot(qvec) * inv_det;
+    if t < t_min || t > t_max {
+        return None;
     }
+    Some((t, b1, b2))
 }
 
-impl Hittable for RotateY {
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-
-        let mut origin = r.origin;
-        let mut direction = r.direction;
+//bounds of a triangle, padded like the rects so that axis-aligned triangles are not flat
+pub fn triangle_bounding_box(p0: Point3<f64>, p1: Point3<f64>, p2: Point3<f64>) -> AABB {
+    let pad = Vector3::new(0.0001, 0.0001, 0.0001);
+    let min = Point3::new(p0.x.min(p1.x).min(p2.x), p0.y.min(p1.y).min(p2.y), p0.z.min(p1.z).min(p2.z));
+    let max = Point3::new(p0.x.max(p1.x).max(p2.x), p0.y.max(p1.y).max(p2.y), p0.z.max(p1.z).max(p2.z));
+    AABB::new(min - pad, max + pad)
+}
 
-        origin[0] = origin[0] * self.cos_theta - origin[2] * self.sin_theta;
-        origin[2] = origin[0] * self.sin_theta + origin[2] * self.cos_theta;
+impl Hittable for Triangle {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, _sampler: &mut dyn Sampler) -> Option<HitRecord> {
+        let (t, u, v) = intersect_triangle(r, self.p0, self.p1, self.p2, t_min, t_max)?;
+        let outward_normal = utilities::unit_vector((self.p1 - self.p0).cross(self.p2 - self.p0));
 
-        direction[0] = self.cos_theta * direction[0] - self.sin_theta * direction[2];
-        direction[2] = self.sin_theta * direction[0] + self.cos_theta * direction[2];
+        Some(HitRecord::new(t, r, outward_normal, u, v, Arc::clone(&self.mat_ptr)))
+    }
 
-        let rotated_r = Ray::new(origin, direction, r.time);
+    fn bounding_box(&self, _time0: f64, _time1: f64, output_box: &mut AABB) -> bool {
+        *output_box = triangle_bounding_box(self.p0, self.p1, self.p2);
+        true
+    }
 
-        match self.ptr.hit(&rotated_r, t_min, t_max) {
-            None => None,
-            Some(mut rec) => {
-                let mut p = rec.p;
-                let mut normal = rec.normal;
+    fn pdf_value(&self, origin: Point3<f64>, direction: Vector3<f64>) -> f64 {
+        let t = match intersect_triangle(&Ray::new(origin, direction, 0.0), self.p0, self.p1, self.p2, 0.001, f64::INFINITY) {
+            Some((t, _, _)) => t,
+            None => return 0.0,
+        };
 
-                p[0] =  self.cos_theta * rec.p[0] + self.sin_theta * rec.p[2];
-                p[2] = -self.sin_theta * rec.p[0] + self.cos_theta * rec.p[2];
+        let cross = (self.p1 - self.p0).cross(self.p2 - self.p0);
+        let area = 0.5 * cross.magnitude();
+        let distance_squared = t * t * direction.magnitude2();
+        let cosine = (direction.dot(cross) / (direction.magnitude() * cross.magnitude())).abs();
 
-                normal[0] =  self.cos_theta * rec.normal[0] + self.sin_theta * rec.normal[2];
-                normal[2] = -self.cos_theta * rec.normal[0] + self.sin_theta * rec.normal[2];
+        distance_squared / (cosine * area)
+    }
 
-                rec.p = p;
-                rec.set_face_normal(&rotated_r, normal);
+    //uniform over the triangle's area
+    fn random(&self, origin: Point3<f64>, sampler: &mut dyn Sampler) -> Vector3<f64> {
+        let (s, t) = sampler.get_2d();
+        let su = s.sqrt();
+        let (b0, b1) = (1.0 - su, t * su);
+        let p = self.p0 + (self.p1 - self.p0) * b1 + (self.p2 - self.p0) * (1.0 - b0 - b1);
+        p - origin
+    }
 
-                Some(rec)
-            }
-        }
+    fn area(&self) -> Option<f64> {
+        Some(0.5 * (self.p1 - self.p0).cross(self.p2 - self.p0).magnitude())
     }
 
-    fn bounding_box(&self, _time0: f64, _time1: f64, output_box: &mut AABB) -> bool {
-        *output_box = self.bbox.clone();
-        true
+    fn transformed_area(&self, m: &Matrix3<f64>) -> Option<f64> {
+        Some(0.5 * parallelogram_area(m, self.p1 - self.p0, self.p2 - self.p0))
     }
 }
 
-pub struct Translate{
-    obj: Box<dyn Hittable>,
-    offset: Vector3<f64>,
+//struct Quad: the parallelogram q + a u + b v for a, b in [0, 1], facing u x v
+pub struct Quad {
+    q: Point3<f64>,
+    u: Vector3<f64>,
+    v: Vector3<f64>,
+    mat_ptr: Arc<dyn Material>,
 }
 
-impl Translate {
-    pub fn new(obj: Box<dyn Hittable>, offset: Vector3<f64>) -> Self {
-        Translate {
-            obj,
-            offset,
-        }
+impl Quad {
+    pub fn new(q: Point3<f64>, u: Vector3<f64>, v: Vector3<f64>, mat_ptr: Arc<dyn Material>) -> Self {
+        Quad { q, u, v, mat_ptr }
     }
-}
 
-impl Hittable for Translate {
-    fn hit(&self ,r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-        let moved_ray = Ray::new(r.origin-self.offset, r.direction, r.time);
-        match self.obj.hit(&moved_ray, t_min, t_max) {
-            Some(mut rec) => {
-                rec.p = rec.p + self.offset;
-                Some(rec)
-            },
-            None => None,
+    //t and the coordinates along u and v of the hit point
+    fn intersect(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<(f64, f64, f64)> {
+        let n = self.u.cross(self.v);
+        let denom = n.dot(r.direction);
+        if denom.abs() < 1e-12 {
+            return None; //the ray is parallel to the quad
+        }
+        let t = n.dot(self.q - r.origin) / denom;
+        if t < t_min || t > t_max {
+            return None;
         }
 
+        //coordinates of the hit point in the (u, v) frame
+        let w = n / n.magnitude2();
+        let planar = r.at(t) - self.q;
+        let a = w.dot(planar.cross(self.v));
+        let b = w.dot(self.u.cross(planar));
+        if !(0.0..=1.0).contains(&a) || !(0.0..=1.0).contains(&b) {
+            return None;
+        }
+        Some((t, a, b))
     }
+}
 
-    fn bounding_box(&self, time0: f64, time1: f64, output_box: &mut AABB) -> bool {
-        if !self.obj.bounding_box(time0, time1, output_box) {
-            return false;
-        } 
+impl Hittable for Quad {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, _sampler: &mut dyn Sampler) -> Option<HitRecord> {
+        let (t, a, b) = self.intersect(r, t_min, t_max)?;
+        let outward_normal = utilities::unit_vector(self.u.cross(self.v));
 
-        *output_box  = AABB::new(output_box.min()+self.offset, output_box.max()+self.offset);
+        Some(HitRecord::new(t, r, outward_normal, a, b, Arc::clone(&self.mat_ptr)))
+    }
+
+    fn bounding_box(&self, _time0: f64, _time1: f64, output_box: &mut AABB) -> bool {
+        let far = self.q + self.u + self.v;
+        *output_box = AABB::surrounding_box(
+            &triangle_bounding_box(self.q, self.q + self.u, far),
+            &triangle_bounding_box(self.q, self.q + self.v, far),
+        );
         true
     }
+
+    fn pdf_value(&self, origin: Point3<f64>, direction: Vector3<f64>) -> f64 {
+        let t = match self.intersect(&Ray::new(origin, direction, 0.0), 0.001, f64::INFINITY) {
+            Some((t, _, _)) => t,
+            None => return 0.0,
+        };
+
+        let cross = self.u.cross(self.v);
+        let distance_squared = t * t * direction.magnitude2();
+        let cosine = (direction.dot(cross) / (direction.magnitude() * cross.magnitude())).abs();
+
+        distance_squared / (cosine * cross.magnitude())
+    }
+
+    fn random(&self, origin: Point3<f64>, sampler: &mut dyn Sampler) -> Vector3<f64> {
+        let (s, t) = sampler.get_2d();
+        self.q + self.u * s + self.v * t - origin
+    }
+
+    fn area(&self) -> Option<f64> {
+        Some(self.u.cross(self.v).magnitude())
+    }
+
+    fn transformed_area(&self, m: &Matrix3<f64>) -> Option<f64> {
+        Some(parallelogram_area(m, self.u, self.v))
+    }
 }
 
 pub struct ConstantMedium {
     boundary: Box<dyn Hittable>,
-    phase_function: Rc<dyn Material>,
+    phase_function: Arc<dyn Material>,
     neg_inv_density: f64,
 }
 
@@ -563,30 +760,38 @@ impl ConstantMedium {
     pub fn new(boundary: Box<dyn Hittable>, text: Box<dyn Texture> , distance: f64) -> Self {
         ConstantMedium {
             boundary,
-            phase_function: Rc::new(Isotropic::new(text)),
+            phase_function: Arc::new(Isotropic::new(text)),
             neg_inv_density: -1.0 / distance, 
         }
     }
 }
 
 impl Hittable for ConstantMedium {
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-        let (t1, t2);
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, sampler: &mut dyn Sampler) -> Option<HitRecord> {
+        let (mut t1, mut t2);
 
-        match self.boundary.hit(r, t_min, t_max) {
+        //find the whole span of the line inside the boundary, so that a ray which
+        //starts inside the medium (after scattering in it) can scatter again
+        match self.boundary.hit(r, -f64::INFINITY, f64::INFINITY, sampler) {
             None => return None,
             Some(rec) => {
                 t1 = rec.t;
-                match self.boundary.hit(r, t1+0.0001, t_max) {
+                match self.boundary.hit(r, t1+0.0001, f64::INFINITY, sampler) {
                     None => return None,
                     Some(rec2) => { t2 = rec2.t; }
                 }
             }
         }
 
+        t1 = t1.max(t_min);
+        t2 = t2.min(t_max);
+        if t1 >= t2 {
+            return None
+        }
+
         let ray_length = r.direction.magnitude();
         let distance_inside_boundary = (t2 - t1) * ray_length;
-        let hit_distance = self.neg_inv_density * utilities::random_double().log10();
+        let hit_distance = self.neg_inv_density * utilities::random_double(sampler).ln();
 
         if hit_distance > distance_inside_boundary {
             return None
@@ -599,11 +804,12 @@ impl Hittable for ConstantMedium {
             HitRecord {
                 t,
                 p,
-                mat_ptr: Rc::clone(&self.phase_function),
+                mat_ptr: Arc::clone(&self.phase_function),
                 normal: Vector3::new(1.0, 0.0, 0.0),
                 front_face: true,
                 u: 0.0, 
                 v: 0.0,
+                color: None,
             }
         )
     }
@@ -611,4 +817,59 @@ impl Hittable for ConstantMedium {
     fn bounding_box(&self, time0: f64, time1: f64, output_box: &mut AABB) -> bool {
         self.boundary.bounding_box(time0, time1, output_box)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::material::Lambertian;
+    use crate::sampler::IndependentSampler;
+    use crate::texture::SolidColor;
+
+    #[test]
+    fn medium_transmittance_follows_beer_lambert() {
+        let boundary = Cubic::new(
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(1.0, 1.0, 1.0),
+            Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
+        );
+        let density = 1.5;
+        let medium = ConstantMedium::new(Box::new(boundary), Box::new(SolidColor::new(Color::new(1.0, 1.0, 1.0))), density);
+
+        //straight through a unit of thickness
+        let r = Ray::new(Point3::new(0.5, 0.5, -1.0), Vector3::new(0.0, 0.0, 2.0), 0.0);
+        let mut sampler = IndependentSampler::new(4);
+        let trials = 20000;
+        let passed = (0..trials)
+            .filter(|_| medium.hit(&r, 0.001, f64::INFINITY, &mut sampler).is_none())
+            .count();
+        let transmittance = passed as f64 / trials as f64;
+        assert!((transmittance - (-density).exp()).abs() < 0.01, "{}", transmittance);
+    }
+
+    #[test]
+    fn ray_starting_inside_the_medium_can_scatter() {
+        let boundary = Cubic::new(
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(1.0, 1.0, 1.0),
+            Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
+        );
+        let density = 1.5;
+        let medium = ConstantMedium::new(Box::new(boundary), Box::new(SolidColor::new(Color::new(1.0, 1.0, 1.0))), density);
+
+        //from the centre, as after a scattering event, half a unit from the exit
+        let r = Ray::new(Point3::new(0.5, 0.5, 0.5), Vector3::new(0.0, 0.0, 1.0), 0.0);
+        let mut sampler = IndependentSampler::new(5);
+        let trials = 20000;
+        let mut scattered = 0;
+        for _ in 0..trials {
+            if let Some(rec) = medium.hit(&r, 0.001, f64::INFINITY, &mut sampler) {
+                assert!(rec.t >= 0.001 && rec.t <= 0.5, "{}", rec.t);
+                scattered += 1;
+            }
+        }
+        let transmittance = 1.0 - scattered as f64 / trials as f64;
+        assert!((transmittance - (-0.5 * density).exp()).abs() < 0.01, "{}", transmittance);
+    }
+}
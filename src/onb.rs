@@ -0,0 +1,33 @@
+use crate::utilities;
+use cgmath::*;
+
+//orthonormal basis, used to turn directions sampled around +z into world space
+pub struct Onb {
+    pub u: Vector3<f64>,
+    pub v: Vector3<f64>,
+    pub w: Vector3<f64>,
+}
+
+impl Onb {
+    pub fn build_from_w(n: Vector3<f64>) -> Self {
+        let w = utilities::unit_vector(n);
+        let a = if w.x.abs() > 0.9 {
+            Vector3::new(0.0, 1.0, 0.0)
+        } else {
+            Vector3::new(1.0, 0.0, 0.0)
+        };
+        let v = utilities::unit_vector(w.cross(a));
+        let u = w.cross(v);
+
+        Onb { u, v, w }
+    }
+
+    pub fn local(&self, a: Vector3<f64>) -> Vector3<f64> {
+        self.u * a.x + self.v * a.y + self.w * a.z
+    }
+
+    //the inverse of `local`
+    pub fn to_local(&self, a: Vector3<f64>) -> Vector3<f64> {
+        Vector3::new(a.dot(self.u), a.dot(self.v), a.dot(self.w))
+    }
+}
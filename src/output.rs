@@ -0,0 +1,96 @@
+use crate::color;
+use crate::framebuffer::Framebuffer;
+use image::codecs::hdr::HdrEncoder;
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::png::PngEncoder;
+use image::{ColorType, Rgb};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ImageFormat {
+    Png,
+    Jpeg,
+    PpmAscii,
+    PpmBinary,
+    Hdr, //Radiance RGBE, keeps the linear values unclamped
+}
+
+impl ImageFormat {
+    pub const NAMES: [&'static str; 6] = ["png", "jpg", "jpeg", "ppm", "ppm-ascii", "hdr"];
+
+    //accepts both file extensions and the names listed in NAMES
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "png" => Some(ImageFormat::Png),
+            "jpg" | "jpeg" => Some(ImageFormat::Jpeg),
+            "ppm" => Some(ImageFormat::PpmBinary),
+            "ppm-ascii" => Some(ImageFormat::PpmAscii),
+            "hdr" => Some(ImageFormat::Hdr),
+            _ => None,
+        }
+    }
+
+    pub fn from_path(path: &Path) -> Option<Self> {
+        path.extension().and_then(|ext| Self::from_name(&ext.to_string_lossy()))
+    }
+}
+
+pub fn write_image(framebuffer: &Framebuffer, path: &Path, format: ImageFormat) -> io::Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+
+    match format {
+        ImageFormat::PpmAscii => {
+            write!(file, "P3\n{} {}\n255\n", framebuffer.width, framebuffer.height)?;
+            for &pixel in framebuffer.pixels.iter() {
+                let [r, g, b] = color::to_rgb8(pixel);
+                writeln!(file, "{} {} {}", r, g, b)?;
+            }
+        }
+        ImageFormat::PpmBinary => {
+            write!(file, "P6\n{} {}\n255\n", framebuffer.width, framebuffer.height)?;
+            file.write_all(&to_rgb8_buffer(framebuffer))?;
+        }
+        ImageFormat::Png => {
+            PngEncoder::new(&mut file)
+                .encode(
+                    &to_rgb8_buffer(framebuffer),
+                    framebuffer.width as u32,
+                    framebuffer.height as u32,
+                    ColorType::Rgb8,
+                )
+                .map_err(into_io_error)?;
+        }
+        ImageFormat::Jpeg => {
+            JpegEncoder::new_with_quality(&mut file, 95)
+                .encode(
+                    &to_rgb8_buffer(framebuffer),
+                    framebuffer.width as u32,
+                    framebuffer.height as u32,
+                    ColorType::Rgb8,
+                )
+                .map_err(into_io_error)?;
+        }
+        ImageFormat::Hdr => {
+            let data: Vec<Rgb<f32>> = framebuffer
+                .pixels
+                .iter()
+                .map(|c| Rgb([c.x as f32, c.y as f32, c.z as f32]))
+                .collect();
+            HdrEncoder::new(&mut file)
+                .encode(&data, framebuffer.width, framebuffer.height)
+                .map_err(into_io_error)?;
+        }
+    }
+
+    file.flush()
+}
+
+fn to_rgb8_buffer(framebuffer: &Framebuffer) -> Vec<u8> {
+    framebuffer.pixels.iter().flat_map(|&c| color::to_rgb8(c)).collect()
+}
+
+fn into_io_error(e: image::ImageError) -> io::Error {
+    io::Error::other(e)
+}
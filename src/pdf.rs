@@ -0,0 +1,182 @@
+use crate::hittable::Hittable;
+use crate::microfacet::{self, Ggx};
+use crate::onb::Onb;
+use crate::sampler::Sampler;
+use crate::utilities;
+use cgmath::*;
+
+const PI: f64 = std::f64::consts::PI;
+
+//a probability density over directions, with respect to solid angle
+pub trait Pdf {
+    fn value(&self, direction: Vector3<f64>) -> f64;
+    fn generate(&self, sampler: &mut dyn Sampler) -> Vector3<f64>;
+}
+
+//cos(theta) / pi around a normal, the ideal density for Lambertian surfaces
+pub struct CosinePdf {
+    uvw: Onb,
+}
+
+impl CosinePdf {
+    pub fn new(w: Vector3<f64>) -> Self {
+        CosinePdf {
+            uvw: Onb::build_from_w(w),
+        }
+    }
+}
+
+impl Pdf for CosinePdf {
+    fn value(&self, direction: Vector3<f64>) -> f64 {
+        let cosine = utilities::unit_vector(direction).dot(self.uvw.w);
+        if cosine <= 0.0 {
+            0.0
+        } else {
+            cosine / PI
+        }
+    }
+
+    fn generate(&self, sampler: &mut dyn Sampler) -> Vector3<f64> {
+        self.uvw.local(utilities::random_cosine_direction(sampler))
+    }
+}
+
+//the visible normals of a GGX lobe, mixed with a cosine lobe for a diffuse base.
+//`wo` is the direction toward the viewer in the frame `uvw`.
+pub struct MicrofacetPdf {
+    uvw: Onb,
+    wo: Vector3<f64>,
+    ggx: Ggx,
+    specular_probability: f64,
+}
+
+impl MicrofacetPdf {
+    pub fn new(uvw: Onb, wo: Vector3<f64>, ggx: Ggx, specular_probability: f64) -> Self {
+        MicrofacetPdf {
+            uvw,
+            wo,
+            ggx,
+            specular_probability,
+        }
+    }
+}
+
+impl Pdf for MicrofacetPdf {
+    fn value(&self, direction: Vector3<f64>) -> f64 {
+        let wi = self.uvw.to_local(utilities::unit_vector(direction));
+        let diffuse = if wi.z > 0.0 { wi.z / PI } else { 0.0 };
+        self.specular_probability * self.ggx.reflection_pdf(self.wo, wi) + (1.0 - self.specular_probability) * diffuse
+    }
+
+    fn generate(&self, sampler: &mut dyn Sampler) -> Vector3<f64> {
+        if utilities::random_double(sampler) < self.specular_probability {
+            let h = self.ggx.sample_visible_normal(self.wo, sampler);
+            self.uvw.local(microfacet::reflect(self.wo, h))
+        } else {
+            self.uvw.local(utilities::random_cosine_direction(sampler))
+        }
+    }
+}
+
+//the visible normals of a GGX lobe on a dielectric boundary, each reflecting or
+//refracting `wo` as the Fresnel reflectance decides. eta is the index of refraction
+//below the surface over the one on wo's side.
+pub struct RoughDielectricPdf {
+    uvw: Onb,
+    wo: Vector3<f64>,
+    ggx: Ggx,
+    eta: f64,
+}
+
+impl RoughDielectricPdf {
+    pub fn new(uvw: Onb, wo: Vector3<f64>, ggx: Ggx, eta: f64) -> Self {
+        RoughDielectricPdf { uvw, wo, ggx, eta }
+    }
+}
+
+impl Pdf for RoughDielectricPdf {
+    fn value(&self, direction: Vector3<f64>) -> f64 {
+        let wi = self.uvw.to_local(utilities::unit_vector(direction));
+        let h = match microfacet::half_vector(self.wo, wi, self.eta) {
+            Some(h) => h,
+            None => return 0.0,
+        };
+        let fresnel = microfacet::dielectric_reflectance(self.wo.dot(h), self.eta);
+        if wi.z > 0.0 {
+            fresnel * self.ggx.reflection_pdf(self.wo, wi)
+        } else {
+            (1.0 - fresnel) * self.ggx.transmission_pdf(self.wo, wi, h, self.eta)
+        }
+    }
+
+    fn generate(&self, sampler: &mut dyn Sampler) -> Vector3<f64> {
+        let h = self.ggx.sample_visible_normal(self.wo, sampler);
+        let fresnel = microfacet::dielectric_reflectance(self.wo.dot(h), self.eta);
+        let wi = if utilities::random_double(sampler) < fresnel {
+            microfacet::reflect(self.wo, h)
+        } else {
+            microfacet::refract(self.wo, h, self.eta).unwrap_or_else(|| microfacet::reflect(self.wo, h))
+        };
+        self.uvw.local(wi)
+    }
+}
+
+//uniform over all directions
+pub struct SpherePdf;
+
+impl Pdf for SpherePdf {
+    fn value(&self, _direction: Vector3<f64>) -> f64 {
+        1.0 / (4.0 * PI)
+    }
+
+    fn generate(&self, sampler: &mut dyn Sampler) -> Vector3<f64> {
+        utilities::random_unit_vector(sampler)
+    }
+}
+
+//directions from origin toward the surface of a hittable
+pub struct HittablePdf<'a> {
+    objects: &'a dyn Hittable,
+    origin: Point3<f64>,
+}
+
+impl<'a> HittablePdf<'a> {
+    pub fn new(objects: &'a dyn Hittable, origin: Point3<f64>) -> Self {
+        HittablePdf { objects, origin }
+    }
+}
+
+impl<'a> Pdf for HittablePdf<'a> {
+    fn value(&self, direction: Vector3<f64>) -> f64 {
+        self.objects.pdf_value(self.origin, direction)
+    }
+
+    fn generate(&self, sampler: &mut dyn Sampler) -> Vector3<f64> {
+        self.objects.random(self.origin, sampler)
+    }
+}
+
+//an equal-weight blend of two densities
+pub struct MixturePdf<'a> {
+    p: [&'a dyn Pdf; 2],
+}
+
+impl<'a> MixturePdf<'a> {
+    pub fn new(p0: &'a dyn Pdf, p1: &'a dyn Pdf) -> Self {
+        MixturePdf { p: [p0, p1] }
+    }
+}
+
+impl<'a> Pdf for MixturePdf<'a> {
+    fn value(&self, direction: Vector3<f64>) -> f64 {
+        0.5 * self.p[0].value(direction) + 0.5 * self.p[1].value(direction)
+    }
+
+    fn generate(&self, sampler: &mut dyn Sampler) -> Vector3<f64> {
+        if utilities::random_double(sampler) < 0.5 {
+            self.p[0].generate(sampler)
+        } else {
+            self.p[1].generate(sampler)
+        }
+    }
+}
@@ -0,0 +1,476 @@
+//Stanford PLY import, ASCII and binary of either byte order, as written by scanners and
+//tools like MeshLab. The `vertex` element gives positions plus optional normals
+//(nx, ny, nz), texture coordinates (u, v or s, t) and colors (red, green, blue);
+//`face` gives polygons as a `vertex_indices` list. Other elements are skipped.
+
+use crate::color::Color;
+use crate::mesh::{MeshData, MeshError};
+use crate::obj;
+use cgmath::*;
+use std::fs;
+use std::path::Path;
+
+pub fn load(path: &Path) -> Result<MeshData, MeshError> {
+    let bytes = fs::read(path).map_err(|e| MeshError {
+        line: 0,
+        message: format!("cannot read {}: {}", path.display(), e),
+    })?;
+    parse(&bytes)
+}
+
+pub fn parse(bytes: &[u8]) -> Result<MeshData, MeshError> {
+    let (header, body) = parse_header(bytes)?;
+    let mut values: Box<dyn Values> = match header.format {
+        Format::Ascii => Box::new(AsciiValues::new(body, header.lines)?),
+        Format::BinaryLittleEndian => Box::new(BinaryValues { data: body, pos: 0, big_endian: false }),
+        Format::BinaryBigEndian => Box::new(BinaryValues { data: body, pos: 0, big_endian: true }),
+    };
+
+    let mut data = MeshData::default();
+    let mut polygons: Vec<Vec<u32>> = Vec::new();
+    for element in &header.elements {
+        match element.name.as_str() {
+            "vertex" => read_vertices(element, values.as_mut(), &mut data)?,
+            "face" => polygons = read_faces(element, values.as_mut())?,
+            _ => {
+                for _ in 0..element.count {
+                    values.start_element()?;
+                    for property in &element.properties {
+                        read_property(property, values.as_mut())?;
+                    }
+                }
+            }
+        }
+    }
+
+    let vertex_count = data.positions.len();
+    for polygon in polygons.iter().filter(|p| p.len() >= 3) {
+        if let Some(&i) = polygon.iter().find(|&&i| i as usize >= vertex_count) {
+            return Err(MeshError {
+                line: 0,
+                message: format!("face refers to vertex {} but there are only {}", i, vertex_count),
+            });
+        }
+        let points: Vec<Point3<f64>> = polygon.iter().map(|&i| data.positions[i as usize]).collect();
+        for [a, b, c] in obj::triangulate(&points) {
+            data.indices.push([polygon[a], polygon[b], polygon[c]]);
+        }
+    }
+    if data.indices.is_empty() {
+        return Err(MeshError {
+            line: 0,
+            message: String::from("the file contains no faces"),
+        });
+    }
+    Ok(data)
+}
+
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Clone, Copy)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl Scalar {
+    fn from_name(name: &str) -> Option<Scalar> {
+        match name {
+            "char" | "int8" => Some(Scalar::I8),
+            "uchar" | "uint8" => Some(Scalar::U8),
+            "short" | "int16" => Some(Scalar::I16),
+            "ushort" | "uint16" => Some(Scalar::U16),
+            "int" | "int32" => Some(Scalar::I32),
+            "uint" | "uint32" => Some(Scalar::U32),
+            "float" | "float32" => Some(Scalar::F32),
+            "double" | "float64" => Some(Scalar::F64),
+            _ => None,
+        }
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Scalar::I8 | Scalar::U8 => 1,
+            Scalar::I16 | Scalar::U16 => 2,
+            Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+            Scalar::F64 => 8,
+        }
+    }
+
+    fn is_float(self) -> bool {
+        matches!(self, Scalar::F32 | Scalar::F64)
+    }
+}
+
+enum Property {
+    Scalar(Scalar, String),
+    List(Scalar, Scalar, String), //count type, item type, name
+}
+
+impl Property {
+    fn name(&self) -> &str {
+        match self {
+            Property::Scalar(_, name) | Property::List(_, _, name) => name,
+        }
+    }
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+struct Header {
+    format: Format,
+    elements: Vec<Element>,
+    lines: usize, //number of header lines, so ASCII bodies can report line numbers
+}
+
+//splits the file after `end_header`
+fn parse_header(bytes: &[u8]) -> Result<(Header, &[u8]), MeshError> {
+    let error = |line: usize, message: String| MeshError { line, message };
+    if !bytes.starts_with(b"ply") {
+        return Err(error(1, String::from("not a PLY file")));
+    }
+
+    let mut format = None;
+    let mut elements: Vec<Element> = Vec::new();
+    let mut pos = 0;
+    let mut line = 0;
+    loop {
+        let end = match bytes[pos..].iter().position(|&b| b == b'\n') {
+            Some(end) => pos + end,
+            None => return Err(error(line + 1, String::from("missing end_header"))),
+        };
+        line += 1;
+        let text = String::from_utf8_lossy(&bytes[pos..end]);
+        pos = end + 1;
+        let words: Vec<&str> = text.split_whitespace().collect();
+
+        match words.as_slice() {
+            ["end_header"] => break,
+            ["ply"] | ["comment", ..] | ["obj_info", ..] | [] => {}
+            ["format", kind, _] => {
+                format = Some(match *kind {
+                    "ascii" => Format::Ascii,
+                    "binary_little_endian" => Format::BinaryLittleEndian,
+                    "binary_big_endian" => Format::BinaryBigEndian,
+                    _ => return Err(error(line, format!("unknown format `{}`", kind))),
+                })
+            }
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count
+                    .parse()
+                    .map_err(|_| error(line, format!("invalid element count `{}`", count)))?,
+                properties: Vec::new(),
+            }),
+            ["property", rest @ ..] => {
+                let scalar = |name: &str| {
+                    Scalar::from_name(name).ok_or_else(|| error(line, format!("unknown property type `{}`", name)))
+                };
+                let property = match rest {
+                    ["list", count, item, name] => Property::List(scalar(count)?, scalar(item)?, name.to_string()),
+                    [kind, name] => Property::Scalar(scalar(kind)?, name.to_string()),
+                    _ => return Err(error(line, String::from("malformed property"))),
+                };
+                match elements.last_mut() {
+                    Some(element) => element.properties.push(property),
+                    None => return Err(error(line, String::from("property before any element"))),
+                }
+            }
+            _ => return Err(error(line, format!("unexpected header line `{}`", text.trim()))),
+        }
+    }
+
+    let format = format.ok_or_else(|| error(line, String::from("missing format line")))?;
+    Ok((Header { format, elements, lines: line }, &bytes[pos..]))
+}
+
+//the element data, read one property value at a time
+trait Values {
+    //moves to the next element instance; one line per instance in ASCII files
+    fn start_element(&mut self) -> Result<(), MeshError>;
+    fn next(&mut self, scalar: Scalar) -> Result<f64, MeshError>;
+}
+
+struct AsciiValues<'a> {
+    lines: std::iter::Enumerate<std::str::Lines<'a>>,
+    first_line: usize,
+    line: usize,
+    words: std::str::SplitWhitespace<'a>,
+}
+
+impl<'a> AsciiValues<'a> {
+    fn new(body: &'a [u8], header_lines: usize) -> Result<Self, MeshError> {
+        let text = std::str::from_utf8(body).map_err(|_| MeshError {
+            line: header_lines + 1,
+            message: String::from("ASCII data is not valid text"),
+        })?;
+        Ok(AsciiValues {
+            lines: text.lines().enumerate(),
+            first_line: header_lines + 1,
+            line: header_lines,
+            words: "".split_whitespace(),
+        })
+    }
+}
+
+impl<'a> Values for AsciiValues<'a> {
+    fn start_element(&mut self) -> Result<(), MeshError> {
+        for (i, text) in &mut self.lines {
+            if !text.trim().is_empty() {
+                self.line = self.first_line + i;
+                self.words = text.split_whitespace();
+                return Ok(());
+            }
+        }
+        Err(MeshError {
+            line: 0,
+            message: String::from("unexpected end of file"),
+        })
+    }
+
+    fn next(&mut self, _scalar: Scalar) -> Result<f64, MeshError> {
+        let word = self.words.next().ok_or_else(|| MeshError {
+            line: self.line,
+            message: String::from("too few values"),
+        })?;
+        word.parse().map_err(|_| MeshError {
+            line: self.line,
+            message: format!("invalid number `{}`", word),
+        })
+    }
+}
+
+struct BinaryValues<'a> {
+    data: &'a [u8],
+    pos: usize,
+    big_endian: bool,
+}
+
+impl<'a> Values for BinaryValues<'a> {
+    fn start_element(&mut self) -> Result<(), MeshError> {
+        Ok(())
+    }
+
+    fn next(&mut self, scalar: Scalar) -> Result<f64, MeshError> {
+        let size = scalar.size();
+        let bytes = self.data.get(self.pos..self.pos + size).ok_or_else(|| MeshError {
+            line: 0,
+            message: String::from("unexpected end of file"),
+        })?;
+        self.pos += size;
+
+        let mut raw = [0u8; 8];
+        raw[..size].copy_from_slice(bytes);
+        if self.big_endian {
+            raw[..size].reverse();
+        }
+        Ok(match scalar {
+            Scalar::I8 => raw[0] as i8 as f64,
+            Scalar::U8 => raw[0] as f64,
+            Scalar::I16 => i16::from_le_bytes([raw[0], raw[1]]) as f64,
+            Scalar::U16 => u16::from_le_bytes([raw[0], raw[1]]) as f64,
+            Scalar::I32 => i32::from_le_bytes([raw[0], raw[1], raw[2], raw[3]]) as f64,
+            Scalar::U32 => u32::from_le_bytes([raw[0], raw[1], raw[2], raw[3]]) as f64,
+            Scalar::F32 => f32::from_le_bytes([raw[0], raw[1], raw[2], raw[3]]) as f64,
+            Scalar::F64 => f64::from_le_bytes(raw),
+        })
+    }
+}
+
+//a scalar property, or a list whose items are discarded
+fn read_property(property: &Property, values: &mut dyn Values) -> Result<f64, MeshError> {
+    match property {
+        Property::Scalar(scalar, _) => values.next(*scalar),
+        Property::List(count, item, _) => {
+            let n = values.next(*count)?;
+            for _ in 0..n as usize {
+                values.next(*item)?;
+            }
+            Ok(0.0)
+        }
+    }
+}
+
+fn read_vertices(element: &Element, values: &mut dyn Values, data: &mut MeshData) -> Result<(), MeshError> {
+    let find = |names: &[&str]| {
+        element
+            .properties
+            .iter()
+            .position(|p| matches!(p, Property::Scalar(..)) && names.contains(&p.name()))
+    };
+    let all = |indices: &[Option<usize>]| indices.iter().copied().collect::<Option<Vec<usize>>>();
+    let position = all(&[find(&["x"]), find(&["y"]), find(&["z"])]).ok_or_else(|| MeshError {
+        line: 0,
+        message: String::from("vertices have no x, y and z"),
+    })?;
+    let normal = all(&[find(&["nx"]), find(&["ny"]), find(&["nz"])]);
+    let uv = all(&[
+        find(&["u", "s", "texture_u", "texture_s"]),
+        find(&["v", "t", "texture_v", "texture_t"]),
+    ]);
+    let color = all(&[
+        find(&["red", "diffuse_red"]),
+        find(&["green", "diffuse_green"]),
+        find(&["blue", "diffuse_blue"]),
+    ]);
+    //integer colors are 0-255, float colors 0-1
+    let color_scale = match color.as_ref().map(|c| &element.properties[c[0]]) {
+        Some(Property::Scalar(scalar, _)) if !scalar.is_float() => 1.0 / 255.0,
+        _ => 1.0,
+    };
+
+    let mut row = vec![0.0; element.properties.len()];
+    for _ in 0..element.count {
+        values.start_element()?;
+        for (value, property) in row.iter_mut().zip(&element.properties) {
+            *value = read_property(property, values)?;
+        }
+        data.positions.push(Point3::new(row[position[0]], row[position[1]], row[position[2]]));
+        if let Some(n) = &normal {
+            data.normals.push(Vector3::new(row[n[0]], row[n[1]], row[n[2]]));
+        }
+        if let Some(t) = &uv {
+            data.uvs.push((row[t[0]], row[t[1]]));
+        }
+        if let Some(c) = &color {
+            data.colors.push(Color::new(row[c[0]], row[c[1]], row[c[2]]) * color_scale);
+        }
+    }
+    Ok(())
+}
+
+fn read_faces(element: &Element, values: &mut dyn Values) -> Result<Vec<Vec<u32>>, MeshError> {
+    let list = element
+        .properties
+        .iter()
+        .position(|p| matches!(p, Property::List(..)) && (p.name() == "vertex_indices" || p.name() == "vertex_index"))
+        .ok_or_else(|| MeshError {
+            line: 0,
+            message: String::from("faces have no vertex_indices list"),
+        })?;
+
+    let mut polygons = Vec::with_capacity(element.count);
+    for _ in 0..element.count {
+        values.start_element()?;
+        for (i, property) in element.properties.iter().enumerate() {
+            match property {
+                Property::List(count, item, _) if i == list => {
+                    let n = values.next(*count)? as usize;
+                    let mut polygon = Vec::with_capacity(n);
+                    for _ in 0..n {
+                        polygon.push(values.next(*item)? as u32);
+                    }
+                    polygons.push(polygon);
+                }
+                _ => {
+                    read_property(property, values)?;
+                }
+            }
+        }
+    }
+    Ok(polygons)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ASCII: &str = "ply
+format ascii 1.0
+comment a unit square, colored
+element vertex 4
+property float x
+property float y
+property float z
+property uchar red
+property uchar green
+property uchar blue
+element face 1
+property list uchar int vertex_indices
+element edge 1
+property int vertex1
+property int vertex2
+end_header
+0 0 0 255 0 0
+1 0 0 0 255 0
+1 1 0 0 0 255
+0 1 0 255 255 255
+4 0 1 2 3
+0 2
+";
+
+    //the same square, with float positions and an int index list
+    fn binary(big_endian: bool) -> Vec<u8> {
+        let format = if big_endian { "binary_big_endian" } else { "binary_little_endian" };
+        let mut bytes = format!(
+            "ply\nformat {} 1.0\nelement vertex 4\nproperty float x\nproperty float y\nproperty float z\n\
+             element face 1\nproperty list uchar int vertex_indices\nend_header\n",
+            format
+        )
+        .into_bytes();
+        for [x, y] in [[0.0f32, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]] {
+            for c in [x, y, 0.0] {
+                bytes.extend(if big_endian { c.to_be_bytes() } else { c.to_le_bytes() });
+            }
+        }
+        bytes.push(4);
+        for i in 0..4i32 {
+            bytes.extend(if big_endian { i.to_be_bytes() } else { i.to_le_bytes() });
+        }
+        bytes
+    }
+
+    #[test]
+    fn ascii_with_colors_and_extra_elements() {
+        let data = parse(ASCII.as_bytes()).unwrap();
+        assert_eq!(data.positions.len(), 4);
+        assert_eq!(data.indices.len(), 2);
+        assert_eq!(data.colors[1], Color::new(0.0, 1.0, 0.0));
+        assert_eq!(data.colors[3], Color::new(1.0, 1.0, 1.0));
+        assert!(data.normals.is_empty() && data.uvs.is_empty());
+    }
+
+    #[test]
+    fn both_byte_orders_match_ascii() {
+        let expected = parse(ASCII.as_bytes()).unwrap();
+        for big_endian in [false, true] {
+            let data = parse(&binary(big_endian)).unwrap();
+            assert_eq!(data.positions, expected.positions);
+            assert_eq!(data.indices, expected.indices);
+        }
+    }
+
+    #[test]
+    fn errors_are_reported() {
+        let error = parse(ASCII.replace("4 0 1 2 3", "4 0 1 2 7").as_bytes()).err().unwrap();
+        assert!(error.message.contains("vertex 7"), "{}", error.message);
+
+        let mut truncated = binary(false);
+        truncated.truncate(truncated.len() - 3);
+        assert!(parse(&truncated).is_err());
+
+        assert!(parse(b"not a ply file\n").is_err());
+    }
+
+    #[test]
+    fn bundled_model_loads() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes/models/torus.ply");
+        let data = load(&path).unwrap();
+        assert!(!data.indices.is_empty());
+        assert_eq!(data.normals.len(), data.positions.len());
+    }
+}
@@ -4,6 +4,7 @@ pub struct Ray {
     pub origin: Point3<f64>,
     pub direction: Vector3<f64>,
     pub time: f64, //added for motion_blur
+    pub wavelength: Option<f64>, //in nanometers, the hero wavelength of a spectral path
 }
 
 impl Ray {
@@ -12,6 +13,7 @@ impl Ray {
             origin,
             direction,
             time, // the time the single ray exists.
+            wavelength: None,
         }
     }
 
@@ -0,0 +1,182 @@
+use crate::camera::Camera;
+use crate::color::{self, Color, ColorMode, PathLimits};
+use crate::environment::Environment;
+use crate::framebuffer::Framebuffer;
+use crate::hittable::Hittable;
+use crate::light::LightList;
+use crate::sampler::SamplerKind;
+use crate::spectrum::Wavelengths;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+
+pub struct RenderSettings {
+    pub image_width: usize,
+    pub image_height: usize,
+    pub samples_per_pixel: usize,
+    pub limits: PathLimits,
+    pub sampler: SamplerKind,
+    pub color_mode: ColorMode,
+    pub seed: u64,
+    pub threads: usize,
+    pub tile_size: usize,
+}
+
+//a rectangular block of pixels, rendered by one worker at a time
+struct Tile {
+    x0: usize,
+    y0: usize,
+    x1: usize,
+    y1: usize,
+}
+
+fn split_into_tiles(settings: &RenderSettings) -> Vec<Tile> {
+    let mut tiles = Vec::new();
+    for y0 in (0..settings.image_height).step_by(settings.tile_size) {
+        for x0 in (0..settings.image_width).step_by(settings.tile_size) {
+            tiles.push(Tile {
+                x0,
+                y0,
+                x1: (x0 + settings.tile_size).min(settings.image_width),
+                y1: (y0 + settings.tile_size).min(settings.image_height),
+            });
+        }
+    }
+    tiles
+}
+
+//returns the average of all samples for every pixel.
+//every sample restarts the worker's sampler at (pixel index, sample index), so the result
+//does not depend on the number of threads or on the order tiles are picked up.
+pub fn render(
+    world: &dyn Hittable,
+    lights: Option<&LightList>,
+    cam: &Camera,
+    background: &dyn Environment,
+    settings: &RenderSettings,
+) -> Framebuffer {
+    let (width, height) = (settings.image_width, settings.image_height);
+    assert!(width >= 2 && height >= 2, "the image must be at least 2 pixels wide and high");
+    let tiles = split_into_tiles(settings);
+    let next_tile = AtomicUsize::new(0);
+    let mut framebuffer = Framebuffer::new(width, height);
+
+    thread::scope(|scope| {
+        let (sender, receiver) = mpsc::channel();
+
+        for _ in 0..settings.threads.max(1) {
+            let sender = sender.clone();
+            let (tiles, next_tile) = (&tiles, &next_tile);
+
+            scope.spawn(move || {
+                let mut sampler = settings.sampler.create(settings.seed, settings.samples_per_pixel);
+                loop {
+                    let index = next_tile.fetch_add(1, Ordering::Relaxed);
+                    if index >= tiles.len() {
+                        break;
+                    }
+                    let tile = &tiles[index];
+                    let mut pixels = Vec::with_capacity((tile.x1 - tile.x0) * (tile.y1 - tile.y0));
+
+                    for y in tile.y0..tile.y1 {
+                        let j = height - 1 - y;
+                        for i in tile.x0..tile.x1 {
+                            let pixel_index = (y * width + i) as u64;
+                            let mut pixel_color = Color::new(0.0, 0.0, 0.0);
+
+                            for s in 0..settings.samples_per_pixel {
+                                sampler.start_pixel_sample(pixel_index, s as u64);
+                                let (du, dv) = sampler.get_2d();
+                                let u = (i as f64 + du) / (width - 1) as f64;
+                                let v = (j as f64 + dv) / (height - 1) as f64;
+                                let r = cam.cast_ray(u, v, &mut *sampler);
+                                let wavelengths = match settings.color_mode {
+                                    ColorMode::Spectral => Some(Wavelengths::sample(sampler.get_1d())),
+                                    ColorMode::Rgb => None,
+                                };
+                                pixel_color += color::ray_color(
+                                    &r,
+                                    background,
+                                    world,
+                                    lights,
+                                    &settings.limits,
+                                    wavelengths.as_ref(),
+                                    &mut *sampler,
+                                );
+                            }
+                            pixels.push(pixel_color / settings.samples_per_pixel as f64);
+                        }
+                    }
+
+                    if sender.send((index, pixels)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(sender);
+
+        let mut remaining = tiles.len();
+        for (index, pixels) in receiver {
+            let tile = &tiles[index];
+            let tile_width = tile.x1 - tile.x0;
+            for (row, y) in (tile.y0..tile.y1).enumerate() {
+                framebuffer.pixels[y * width + tile.x0..y * width + tile.x1]
+                    .copy_from_slice(&pixels[row * tile_width..(row + 1) * tile_width]);
+            }
+            remaining -= 1;
+            eprint!("\rTiles remaining: {:>5}", remaining);
+        }
+        eprintln!();
+    });
+
+    framebuffer
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bvh::BVH;
+    use crate::sampler::IndependentSampler;
+    use crate::scene_file;
+    use std::path::Path;
+
+    const SCENE: &str = "camera from=0,1,6 at=0,1,0 fov=40
+background color=0.1,0.1,0.2
+material white lambertian albedo=0.7,0.7,0.7
+material glass dielectric ior=1.5
+material lamp diffuse_light emit=6,6,6
+sphere center=0,-100,0 radius=100 material=white
+sphere center=-0.8,1,0 radius=1 material=glass
+box min=0.4,0,-0.5 max=1.4,1.5,0.5 material=white
+xz_rect x=-1,1 z=-1,1 y=3 material=lamp
+";
+
+    fn render_with(threads: usize, sampler: SamplerKind) -> Framebuffer {
+        let scene = scene_file::parse(SCENE, Path::new(""), &mut IndependentSampler::new(1)).unwrap();
+        let cam = scene.camera();
+        let world = BVH::new(scene.world, 0.0, 1.0);
+        let settings = RenderSettings {
+            image_width: 24,
+            image_height: 16,
+            samples_per_pixel: 4,
+            limits: PathLimits::default(),
+            sampler,
+            color_mode: ColorMode::Rgb,
+            seed: 7,
+            threads,
+            tile_size: 5, //tiles of different sizes, finished in varying order
+        };
+        render(&world, Some(&scene.lights), &cam, scene.background.as_ref(), &settings)
+    }
+
+    #[test]
+    fn framebuffer_does_not_depend_on_the_thread_count() {
+        for sampler in [SamplerKind::Independent, SamplerKind::Sobol] {
+            let one = render_with(1, sampler);
+            let four = render_with(4, sampler);
+            assert!(one.pixels.iter().any(|c| c.x > 0.0));
+            assert!(one.pixels == four.pixels);
+        }
+    }
+}
@@ -0,0 +1,474 @@
+use crate::utilities;
+
+//dimensions read for every camera sample before the first bounce: pixel offset (2),
+//lens position (2) and time (1)
+pub const CAMERA_DIMENSIONS: u32 = 5;
+//dimensions reserved for each bounce; values a bounce reads past its share come from
+//an independent stream so they can never repeat a value of another dimension
+pub const BOUNCE_DIMENSIONS: u32 = 8;
+
+//source of the random numbers used while rendering. Renderers call start_pixel_sample
+//before tracing each camera sample, so the values that follow depend only on the
+//seed, the pixel and the sample index.
+pub trait Sampler {
+    fn start_pixel_sample(&mut self, pixel_index: u64, sample_index: u64);
+
+    //the integrator calls this at the start of every bounce so that a given bounce reads
+    //the same dimensions in every sample, which is what keeps them well distributed
+    fn start_bounce(&mut self, _depth: usize) {}
+
+    //the next value in [0, 1)
+    fn get_1d(&mut self) -> f64;
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        (self.get_1d(), self.get_1d())
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SamplerKind {
+    Independent,
+    Stratified,
+    Halton,
+    Sobol,
+}
+
+impl SamplerKind {
+    pub const NAMES: [&'static str; 4] = ["independent", "stratified", "halton", "sobol"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "independent" => Some(SamplerKind::Independent),
+            "stratified" => Some(SamplerKind::Stratified),
+            "halton" => Some(SamplerKind::Halton),
+            "sobol" => Some(SamplerKind::Sobol),
+            _ => None,
+        }
+    }
+
+    //stratified sampling needs to know how many samples every pixel will take
+    pub fn create(self, seed: u64, samples_per_pixel: usize) -> Box<dyn Sampler> {
+        match self {
+            SamplerKind::Independent => Box::new(IndependentSampler::new(seed)),
+            SamplerKind::Stratified => Box::new(SequenceSampler::new(
+                Stratified {
+                    samples: samples_per_pixel.max(1) as u32,
+                },
+                seed,
+            )),
+            SamplerKind::Halton => Box::new(SequenceSampler::new(Halton, seed)),
+            SamplerKind::Sobol => Box::new(SequenceSampler::new(Sobol, seed)),
+        }
+    }
+}
+
+//PCG32 (XSH RR) by M.E. O'Neill; every stream is an independent sequence
+pub struct Pcg32 {
+    state: u64,
+    inc: u64,
+}
+
+impl Pcg32 {
+    const MULTIPLIER: u64 = 0x5851_f42d_4c95_7f2d;
+
+    pub fn new(seed: u64, stream: u64) -> Self {
+        let mut rng = Pcg32 {
+            state: 0,
+            inc: (stream << 1) | 1,
+        };
+        rng.next_u32();
+        rng.state = rng.state.wrapping_add(seed);
+        rng.next_u32();
+        rng
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let old = self.state;
+        self.state = old.wrapping_mul(Self::MULTIPLIER).wrapping_add(self.inc);
+        let xorshifted = (((old >> 18) ^ old) >> 27) as u32;
+        xorshifted.rotate_right((old >> 59) as u32)
+    }
+
+    //53 random bits, so every representable value in [0, 1) with that spacing is reachable
+    pub fn next_f64(&mut self) -> f64 {
+        let bits = ((self.next_u32() as u64) << 32 | self.next_u32() as u64) >> 11;
+        bits as f64 * (1.0 / (1u64 << 53) as f64)
+    }
+}
+
+//plain uniform random numbers, one PCG stream per camera sample
+pub struct IndependentSampler {
+    seed: u64,
+    rng: Pcg32,
+}
+
+impl IndependentSampler {
+    pub fn new(seed: u64) -> Self {
+        IndependentSampler {
+            seed,
+            rng: Pcg32::new(seed, 0),
+        }
+    }
+}
+
+impl Sampler for IndependentSampler {
+    fn start_pixel_sample(&mut self, pixel_index: u64, sample_index: u64) {
+        self.rng = Pcg32::new(utilities::pixel_seed(self.seed, pixel_index), sample_index);
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        self.rng.next_f64()
+    }
+}
+
+//a point set indexed by (pixel, sample, dimension). `scramble` is derived from the seed,
+//the pixel and the dimension, so neighbouring pixels and dimensions are decorrelated.
+pub trait Sequence {
+    //dimensions past this one are filled with independent random numbers
+    const MAX_DIMENSIONS: u32 = u32::MAX;
+
+    fn sample_1d(&self, sample_index: u64, dimension: u32, scramble: u64) -> f64;
+
+    //a well distributed pair, for the dimensions dimension and dimension + 1
+    fn sample_2d(&self, sample_index: u64, dimension: u32, scramble: u64) -> (f64, f64);
+}
+
+//drives a Sequence: keeps track of the current dimension and hands out independent
+//numbers once a bounce has used up its share
+pub struct SequenceSampler<S: Sequence> {
+    sequence: S,
+    seed: u64,
+    pixel_index: u64,
+    sample_index: u64,
+    dimension: u32,
+    end: u32,
+    rng: Pcg32,
+}
+
+impl<S: Sequence> SequenceSampler<S> {
+    pub fn new(sequence: S, seed: u64) -> Self {
+        SequenceSampler {
+            sequence,
+            seed,
+            pixel_index: 0,
+            sample_index: 0,
+            dimension: 0,
+            end: CAMERA_DIMENSIONS,
+            rng: Pcg32::new(seed, 0),
+        }
+    }
+
+    fn scramble(&self, dimension: u32) -> u64 {
+        let pixel = utilities::pixel_seed(self.seed, self.pixel_index);
+        utilities::pixel_seed(pixel, dimension as u64)
+    }
+
+    fn take(&mut self, count: u32) -> Option<u32> {
+        let dimension = self.dimension;
+        if dimension + count > self.end || dimension + count > S::MAX_DIMENSIONS {
+            return None;
+        }
+        self.dimension += count;
+        Some(dimension)
+    }
+}
+
+impl<S: Sequence> Sampler for SequenceSampler<S> {
+    fn start_pixel_sample(&mut self, pixel_index: u64, sample_index: u64) {
+        self.pixel_index = pixel_index;
+        self.sample_index = sample_index;
+        self.dimension = 0;
+        self.end = CAMERA_DIMENSIONS;
+        self.rng = Pcg32::new(utilities::pixel_seed(self.seed, pixel_index), sample_index);
+    }
+
+    fn start_bounce(&mut self, depth: usize) {
+        self.dimension = CAMERA_DIMENSIONS + depth as u32 * BOUNCE_DIMENSIONS;
+        self.end = self.dimension + BOUNCE_DIMENSIONS;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        match self.take(1) {
+            Some(dimension) => self
+                .sequence
+                .sample_1d(self.sample_index, dimension, self.scramble(dimension)),
+            None => self.rng.next_f64(),
+        }
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        match self.take(2) {
+            Some(dimension) => self
+                .sequence
+                .sample_2d(self.sample_index, dimension, self.scramble(dimension)),
+            None => (self.rng.next_f64(), self.rng.next_f64()),
+        }
+    }
+}
+
+//jittered strata, visited in a different random order in every pixel and dimension.
+//Pairs are stratified over an nx * ny grid with nx * ny equal to the sample count.
+pub struct Stratified {
+    pub samples: u32,
+}
+
+impl Sequence for Stratified {
+    fn sample_1d(&self, sample_index: u64, _dimension: u32, scramble: u64) -> f64 {
+        let n = self.samples;
+        let stratum = permute(sample_index as u32 % n, n, scramble as u32);
+        let jitter = to_unit(utilities::pixel_seed(scramble, sample_index) as u32);
+        (stratum as f64 + jitter) / n as f64
+    }
+
+    fn sample_2d(&self, sample_index: u64, _dimension: u32, scramble: u64) -> (f64, f64) {
+        let n = self.samples;
+        let nx = (1..=(n as f64).sqrt() as u32).rev().find(|d| n.is_multiple_of(*d)).unwrap_or(1);
+        let ny = n / nx;
+        let stratum = permute(sample_index as u32 % n, n, scramble as u32);
+        let jitter = utilities::pixel_seed(scramble, sample_index);
+        let (jx, jy) = (to_unit(jitter as u32), to_unit((jitter >> 32) as u32));
+        (
+            ((stratum % nx) as f64 + jx) / nx as f64,
+            ((stratum / nx) as f64 + jy) / ny as f64,
+        )
+    }
+}
+
+//the Halton sequence with one prime base per dimension, randomized per pixel and
+//dimension by permuting the digits. Permuting (rather than shifting) keeps the large
+//bases from crowding their first samples into one small interval.
+pub struct Halton;
+
+const PRIMES: [u64; 32] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97, 101, 103,
+    107, 109, 113, 127, 131,
+];
+
+
+impl Sequence for Halton {
+    const MAX_DIMENSIONS: u32 = PRIMES.len() as u32;
+
+    fn sample_1d(&self, sample_index: u64, dimension: u32, scramble: u64) -> f64 {
+        scrambled_radical_inverse(PRIMES[dimension as usize], sample_index, scramble)
+    }
+
+    fn sample_2d(&self, sample_index: u64, dimension: u32, scramble: u64) -> (f64, f64) {
+        (
+            scrambled_radical_inverse(PRIMES[dimension as usize], sample_index, scramble),
+            scrambled_radical_inverse(
+                PRIMES[dimension as usize + 1],
+                sample_index,
+                utilities::pixel_seed(scramble, 1),
+            ),
+        )
+    }
+}
+
+//mirrors the base-`base` digits of index around the radix point, passing the digit at
+//every position through its own permutation. The (infinitely many) leading zeros are
+//permuted too, down to the precision of the result.
+fn scrambled_radical_inverse(base: u64, mut index: u64, scramble: u64) -> f64 {
+    let inv_base = 1.0 / base as f64;
+    let (mut result, mut weight) = (0.0, inv_base);
+    let mut position = 0;
+    while weight > 1e-10 {
+        let digit = index % base;
+        let seed = utilities::pixel_seed(scramble, position) as u32;
+        result += permute(digit as u32, base as u32, seed) as f64 * weight;
+        index /= base;
+        weight *= inv_base;
+        position += 1;
+    }
+    result.min(ONE_MINUS_EPSILON)
+}
+
+//Owen-scrambled Sobol points. Every pair of dimensions uses the first two Sobol
+//dimensions with its own index shuffle and scramble ("padding", Burley 2020), which
+//keeps each pair well stratified without direction-number tables.
+pub struct Sobol;
+
+impl Sequence for Sobol {
+    fn sample_1d(&self, sample_index: u64, _dimension: u32, scramble: u64) -> f64 {
+        let index = nested_uniform_scramble(sample_index as u32, scramble as u32);
+        to_unit(nested_uniform_scramble(index.reverse_bits(), (scramble >> 32) as u32))
+    }
+
+    fn sample_2d(&self, sample_index: u64, _dimension: u32, scramble: u64) -> (f64, f64) {
+        let index = nested_uniform_scramble(sample_index as u32, scramble as u32);
+        let seed = utilities::pixel_seed(scramble, 1);
+        (
+            to_unit(nested_uniform_scramble(index.reverse_bits(), seed as u32)),
+            to_unit(nested_uniform_scramble(sobol_second_dimension(index), (seed >> 32) as u32)),
+        )
+    }
+}
+
+const ONE_MINUS_EPSILON: f64 = 1.0 - f64::EPSILON / 2.0;
+
+fn to_unit(x: u32) -> f64 {
+    x as f64 * (1.0 / 4_294_967_296.0)
+}
+
+//the second Sobol dimension; its direction numbers follow v[k + 1] = v[k] ^ (v[k] >> 1)
+fn sobol_second_dimension(mut index: u32) -> u32 {
+    let (mut v, mut result) = (1u32 << 31, 0u32);
+    while index != 0 {
+        if index & 1 != 0 {
+            result ^= v;
+        }
+        index >>= 1;
+        v ^= v >> 1;
+    }
+    result
+}
+
+//hash-based Owen scrambling of a 32-bit fixed point value (Laine-Karras permutation)
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    let mut x = x.reverse_bits();
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50_b47c);
+    x ^= x.wrapping_mul(0xb82f_1e52);
+    x ^= x.wrapping_mul(0xc7af_e638);
+    x ^= x.wrapping_mul(0x8d22_f6e6);
+    x.reverse_bits()
+}
+
+//a pseudo-random permutation of 0..len selected by `seed` (Kensler 2013)
+fn permute(mut i: u32, len: u32, seed: u32) -> u32 {
+    let mut w = len.wrapping_sub(1);
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    loop {
+        i ^= seed;
+        i = i.wrapping_mul(0xe170_893d);
+        i ^= seed >> 16;
+        i ^= (i & w) >> 4;
+        i ^= seed >> 8;
+        i = i.wrapping_mul(0x0929_eb3f);
+        i ^= seed >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | seed >> 27);
+        i = i.wrapping_mul(0x6935_fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dc_b303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e50_1cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860_a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < len {
+            break;
+        }
+    }
+    i.wrapping_add(seed) % len
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KINDS: [SamplerKind; 4] = [
+        SamplerKind::Independent,
+        SamplerKind::Stratified,
+        SamplerKind::Halton,
+        SamplerKind::Sobol,
+    ];
+
+    //the camera dimensions and three bounces, each reading past its share
+    fn path(sampler: &mut dyn Sampler, pixel_index: u64, sample_index: u64) -> Vec<f64> {
+        sampler.start_pixel_sample(pixel_index, sample_index);
+        let mut values: Vec<f64> = (0..CAMERA_DIMENSIONS).map(|_| sampler.get_1d()).collect();
+        for depth in 0..3 {
+            sampler.start_bounce(depth);
+            for _ in 0..BOUNCE_DIMENSIONS + 2 {
+                let (a, b) = sampler.get_2d();
+                values.extend([a, b, sampler.get_1d()]);
+            }
+        }
+        values
+    }
+
+    #[test]
+    fn values_are_in_the_unit_interval_and_repeatable() {
+        for kind in KINDS {
+            let mut sampler = kind.create(3, 8);
+            for pixel_index in 0..4 {
+                for sample_index in 0..8 {
+                    let values = path(&mut *sampler, pixel_index, sample_index);
+                    assert!(values.iter().all(|&x| (0.0..1.0).contains(&x)), "{:?}", kind);
+                    //another sampler that has seen other pixels first gives the same values
+                    let mut other = kind.create(3, 8);
+                    path(&mut *other, pixel_index + 1, 0);
+                    assert_eq!(values, path(&mut *other, pixel_index, sample_index), "{:?}", kind);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn seeds_and_pixels_are_decorrelated() {
+        for kind in KINDS {
+            let first = path(&mut *kind.create(1, 4), 0, 0);
+            assert_ne!(first, path(&mut *kind.create(2, 4), 0, 0), "{:?}", kind);
+            assert_ne!(first, path(&mut *kind.create(1, 4), 1, 0), "{:?}", kind);
+        }
+    }
+
+    //the 2D pixel offsets of the first 16 samples of a pixel
+    fn offsets(kind: SamplerKind, pixel_index: u64) -> Vec<(f64, f64)> {
+        let mut sampler = kind.create(5, 16);
+        (0..16)
+            .map(|s| {
+                sampler.start_pixel_sample(pixel_index, s);
+                sampler.get_2d()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn stratified_and_sobol_fill_every_cell_of_a_grid() {
+        for kind in [SamplerKind::Stratified, SamplerKind::Sobol] {
+            for pixel_index in 0..8 {
+                let mut cells = [0; 16];
+                for (x, y) in offsets(kind, pixel_index) {
+                    cells[(y * 4.0) as usize * 4 + (x * 4.0) as usize] += 1;
+                }
+                assert_eq!(cells, [1; 16], "{:?}", kind);
+            }
+        }
+    }
+
+    #[test]
+    fn halton_is_stratified_in_each_base() {
+        for pixel_index in 0..8 {
+            let points = offsets(SamplerKind::Halton, pixel_index);
+            let mut base2 = [0; 16];
+            let mut base3 = [0; 9];
+            for (i, &(x, y)) in points.iter().enumerate() {
+                base2[(x * 16.0) as usize] += 1;
+                if i < 9 {
+                    base3[(y * 9.0) as usize] += 1;
+                }
+            }
+            assert_eq!(base2, [1; 16]);
+            assert_eq!(base3, [1; 9]);
+        }
+    }
+
+    #[test]
+    fn permute_is_a_permutation() {
+        for len in [1, 2, 3, 7, 16, 100] {
+            for seed in [0, 1, 0xdead_beef] {
+                let mut seen = vec![false; len as usize];
+                for i in 0..len {
+                    seen[permute(i, len, seed) as usize] = true;
+                }
+                assert!(seen.iter().all(|&s| s), "len {} seed {}", len, seed);
+            }
+        }
+    }
+}
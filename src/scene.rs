@@ -0,0 +1,163 @@
+use crate::camera::Camera;
+use crate::color::*;
+use crate::environment::Environment;
+use crate::hittable_list::{self, HittableList};
+use crate::light::LightList;
+use crate::sampler::{Sampler, SamplerKind};
+use cgmath::*;
+use std::sync::Arc;
+
+//everything needed to build a Camera except the aspect ratio, which follows the image size
+pub struct CameraSettings {
+    pub look_from: Point3<f64>,
+    pub look_at: Point3<f64>,
+    pub vup: Vector3<f64>,
+    pub fov: f64,
+    pub aperture: f64,
+    pub focus_dist: f64,
+    pub time0: f64,
+    pub time1: f64,
+}
+
+impl CameraSettings {
+    pub fn new(look_from: Point3<f64>, look_at: Point3<f64>, fov: f64) -> Self {
+        CameraSettings {
+            look_from,
+            look_at,
+            vup: Vector3::new(0.0, 1.0, 0.0),
+            fov,
+            aperture: 0.0,
+            focus_dist: 10.0,
+            time0: 0.0,
+            time1: 1.0,
+        }
+    }
+}
+
+pub struct Scene {
+    pub world: HittableList,
+    pub lights: LightList, //emissive objects, also present in world, and delta lights
+    pub camera: CameraSettings,
+    pub background: Arc<dyn Environment>, //also in lights if it is sampled
+    pub image_width: usize,
+    pub image_height: usize,
+    pub samples_per_pixel: usize,
+    pub limits: PathLimits,
+    pub sampler: SamplerKind,
+    pub color_mode: ColorMode,
+}
+
+impl Scene {
+    pub fn new(world: HittableList, camera: CameraSettings) -> Self {
+        let aspect_ratio = 3.0 / 2.0;
+        let image_height = 400;
+        Scene {
+            world,
+            lights: LightList::new(),
+            camera,
+            background: Arc::new(SKYBLUE),
+            image_width: (image_height as f64 * aspect_ratio) as usize,
+            image_height,
+            samples_per_pixel: 20,
+            limits: PathLimits::default(),
+            sampler: SamplerKind::Sobol,
+            color_mode: ColorMode::Rgb,
+        }
+    }
+
+    pub fn camera(&self) -> Camera {
+        let c = &self.camera;
+        Camera::new(
+            c.look_from,
+            c.look_at,
+            c.vup,
+            c.fov,
+            self.image_width as f64 / self.image_height as f64,
+            c.aperture,
+            c.focus_dist,
+            c.time0,
+            c.time1,
+        )
+    }
+
+    fn with_square_image(mut self, image_height: usize) -> Self {
+        self.image_width = image_height;
+        self.image_height = image_height;
+        self
+    }
+}
+
+pub const BUILTIN_SCENES: [&str; 8] = [
+    "random",
+    "two_spheres",
+    "simple_light",
+    "cornell_box",
+    "perlin_spheres",
+    "earth",
+    "cornell_smoke",
+    "forest",
+];
+
+//the hard-coded scenes from the books, selected by name. Randomly generated content
+//(sphere placement, noise tables) is drawn from `sampler`.
+pub fn builtin(name: &str, sampler: &mut dyn Sampler) -> Option<Scene> {
+    let scene = match name {
+        "random" => {
+            let mut camera = CameraSettings::new(Point3::new(13.0, 2.0, 3.0), Point3::new(0.0, 0.0, 0.0), 20.0);
+            camera.aperture = 0.1;
+            Scene::new(hittable_list::random_scene(sampler), camera)
+        }
+
+        "two_spheres" => {
+            let mut camera = CameraSettings::new(Point3::new(13.0, 2.0, 3.0), Point3::new(0.0, 0.0, 0.0), 20.0);
+            camera.aperture = 0.1;
+            Scene::new(hittable_list::two_spheres(), camera)
+        }
+
+        "simple_light" => {
+            let camera = CameraSettings::new(Point3::new(26.0, 3.0, 6.0), Point3::new(0.0, 2.0, 0.0), 20.0);
+            let (world, lights) = hittable_list::simple_light(sampler);
+            let mut scene = Scene::new(world, camera);
+            scene.lights = LightList::from_shapes(lights);
+            scene.background = Arc::new(BLACK);
+            scene
+        }
+
+        "cornell_box" => {
+            let camera = CameraSettings::new(Point3::new(278.0, 278.0, -800.0), Point3::new(278.0, 278.0, 0.0), 40.0);
+            let (world, lights) = hittable_list::cornell_box();
+            let mut scene = Scene::new(world, camera).with_square_image(600);
+            scene.lights = LightList::from_shapes(lights);
+            scene.samples_per_pixel = 500;
+            scene.background = Arc::new(BLACK);
+            scene
+        }
+
+        "perlin_spheres" => {
+            let camera = CameraSettings::new(Point3::new(13.0, 2.0, 3.0), Point3::new(0.0, 0.0, 0.0), 20.0);
+            Scene::new(hittable_list::two_perlin_spheres(sampler), camera)
+        }
+
+        "earth" => {
+            let camera = CameraSettings::new(Point3::new(13.0, 2.0, 3.0), Point3::new(0.0, 0.0, 0.0), 20.0);
+            Scene::new(hittable_list::earth(), camera)
+        }
+
+        "cornell_smoke" => {
+            let camera = CameraSettings::new(Point3::new(278.0, 278.0, -800.0), Point3::new(278.0, 278.0, 0.0), 40.0);
+            let (world, lights) = hittable_list::cornell_smoke();
+            let mut scene = Scene::new(world, camera).with_square_image(600);
+            scene.lights = LightList::from_shapes(lights);
+            scene.background = Arc::new(BLACK);
+            scene
+        }
+
+        "forest" => {
+            let camera = CameraSettings::new(Point3::new(0.0, 5.0, 310.0), Point3::new(0.0, 1.0, 270.0), 40.0);
+            Scene::new(hittable_list::forest(sampler), camera)
+        }
+
+        _ => return None,
+    };
+    Some(scene)
+}
@@ -0,0 +1,1210 @@
+//A line-based scene description format. Each non-empty line is one directive followed
+//by `key=value` parameters; `#` starts a comment. Vectors and colors are written as
+//`x,y,z`, file names may be quoted. Example:
+//
+//    camera from=278,278,-800 at=278,278,0 fov=40
+//    render width=600 height=600 samples=200 max_depth=20 rr_depth=5 sampler=sobol color_mode=rgb
+//    background color=0,0,0
+//    texture marble noise scale=4
+//    material white lambertian albedo=0.73,0.73,0.73
+//    material light diffuse_light emit=15,15,15
+//    material lamp diffuse_light temperature=2700 lumens=8000 sides=1 profile=spot cone=50 falloff=30
+//    material copper metallic_roughness color=0.95,0.64,0.54 roughness=0.3 metallic=1
+//    material bottle dielectric ior=1.52 roughness=0.1 absorption=1.2,0.15,0.9
+//    material prism dielectric sellmeier=1.7376,0.3137,1.8988,0.01319,0.06231,155.24
+//    material rusty mix a=copper b=white weight=marble
+//    material varnished coated base=white ior=1.5 color=0.95,0.85,0.6
+//    xz_rect x=213,343 z=227,332 y=554 material=light
+//    box min=0,0,0 max=165,330,165 material=white rotate_y=15 translate=265,0,295
+//    mesh file=models/shapes.obj scale=0.5 rotate=1,0,1,30 translate=0,-1,0
+//    mesh file=models/lantern.gltf material=white
+//    mesh file=models/torus.ply
+//    object pillar box min=-1,0,-1 max=1,10,1 material=white
+//    instance pillar translate=100,0,400 material=light
+//    quad corner=0,0,0 u=1,0,0 v=0,1,1 material=white
+//    light directional direction=-1,-2,-1 temperature=5800 intensity=3
+//    light spot position=0,5,0 at=0,0,0 cone=30 falloff=20 lumens=1200
+//    light quad corner=-1,4,-1 u=2,0,0 v=0,0,2 emit=4,4,4 sides=1
+//
+//Textures and materials are named and must be declared before they are used. Any
+//parameter that takes a texture also accepts an inline color, and `roughness`,
+//`metallic` and a mix's `weight` also accept a single number. `object` defines a named
+//object that is not rendered itself; each `instance` of it shares its geometry and may
+//add a transform and replace its materials.
+//
+//A dielectric's `ior` is a number or one of bk7, sf11, diamond and water. Instead, the
+//Cauchy (`cauchy=a,b`) or Sellmeier (`sellmeier=b1,b2,b3,c1,c2,c3`) coefficients may be
+//given, for wavelengths in micrometers; the dispersion shows with color_mode=spectral.
+//
+//Instead of a `background` color, `environment map file=studio.hdr rotate=90` or
+//`environment sky sun=1,0.3,-1 turbidity=3` surround the scene with an HDR panorama or a
+//daylight sky that also lights it; see Parser::environment.
+//
+//Lights are either emissive objects, which are sampled directly, or point, spot and
+//directional lights, which have no shape and cannot be seen; see Parser::light.
+//
+//A diffuse_light given by `power` (watts) or `lumens` emits that much in total from
+//every object it is put on, whatever the object's size; see diffuse_light for the rest.
+
+use crate::bvh::BVH;
+use crate::color::{luminance, Color, ColorMode, PathLimits, BLACK};
+use crate::environment::{Environment, EnvironmentLight, EnvironmentMap, Sky};
+use crate::gltf_import;
+use crate::hittable::Hittable;
+use crate::hittable_list::HittableList;
+use crate::instance::Instance;
+use crate::light::{DirectionalLight, Light, LightList, PointLight, SpotLight};
+use crate::material::{
+    Coated, Dielectric, DiffuseLight, EmissionProfile, Isotropic, Lambertian, Material, Metal, MetallicRoughness,
+    MixMaterial, LUMENS_PER_WATT,
+};
+use crate::mesh::TriangleMesh;
+use crate::obj;
+use crate::objects::{ConstantMedium, Cubic, MovingSphere, Quad, Sphere, Triangle, XyRect, XzRect, YzRect};
+use crate::ply;
+use crate::sampler::{Sampler, SamplerKind};
+use crate::scene::{CameraSettings, Scene};
+use crate::spectrum::{blackbody, Ior};
+use crate::stl;
+use crate::texture::{CheckerTexture, ImageTexture, NoiseTexture, SolidColor, Texture, VertexColorTexture};
+use crate::transform::Transform;
+use cgmath::*;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+#[derive(Debug)]
+pub struct SceneError {
+    pub line: usize, //0 when the error is not tied to a line
+    pub message: String,
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.line == 0 {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "line {}: {}", self.line, self.message)
+        }
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+//random content such as noise tables is drawn from `sampler`
+pub fn load(path: &str, sampler: &mut dyn Sampler) -> Result<Scene, SceneError> {
+    let source = fs::read_to_string(path).map_err(|e| SceneError {
+        line: 0,
+        message: format!("cannot read scene file {}: {}", path, e),
+    })?;
+    let base_dir = Path::new(path).parent().unwrap_or_else(|| Path::new(""));
+    parse(&source, base_dir, sampler)
+}
+
+//relative file names inside the scene are resolved against base_dir
+pub fn parse(source: &str, base_dir: &Path, sampler: &mut dyn Sampler) -> Result<Scene, SceneError> {
+    let mut parser = Parser {
+        sampler,
+        base_dir: base_dir.to_path_buf(),
+        textures: HashMap::new(),
+        materials: HashMap::new(),
+        prototypes: HashMap::new(),
+        world: HittableList::new(),
+        lights: LightList::new(),
+        camera: None,
+        background: None,
+        image_width: None,
+        image_height: None,
+        samples_per_pixel: None,
+        limits: PathLimits::default(),
+        sampler_kind: None,
+        color_mode: None,
+    };
+
+    for (index, text) in source.lines().enumerate() {
+        if let Some(directive) = Directive::tokenize(index + 1, text)? {
+            parser.directive(directive)?;
+        }
+    }
+
+    parser.finish()
+}
+
+//one line of the file: the directive keyword, an optional name and its parameters
+struct Directive {
+    line: usize,
+    words: Vec<String>,
+    params: Vec<(String, String)>,
+}
+
+impl Directive {
+    fn tokenize(line: usize, text: &str) -> Result<Option<Directive>, SceneError> {
+        let mut tokens = Vec::new();
+        let mut current = String::new();
+        let mut in_quotes = false;
+
+        for c in text.chars() {
+            match c {
+                '"' => in_quotes = !in_quotes,
+                '#' if !in_quotes => break,
+                c if c.is_whitespace() && !in_quotes => {
+                    if !current.is_empty() {
+                        tokens.push(std::mem::take(&mut current));
+                    }
+                }
+                c => current.push(c),
+            }
+        }
+        if in_quotes {
+            return Err(SceneError { line, message: String::from("unterminated string") });
+        }
+        if !current.is_empty() {
+            tokens.push(current);
+        }
+        if tokens.is_empty() {
+            return Ok(None);
+        }
+
+        let mut directive = Directive { line, words: Vec::new(), params: Vec::new() };
+        for token in tokens {
+            match token.find('=') {
+                Some(eq) => {
+                    let (key, value) = (&token[..eq], &token[eq + 1..]);
+                    if directive.params.iter().any(|(k, _)| k == key) {
+                        return Err(directive.error(format!("parameter `{}` given twice", key)));
+                    }
+                    directive.params.push((key.to_string(), value.to_string()));
+                }
+                None if directive.params.is_empty() => directive.words.push(token),
+                None => {
+                    return Err(directive.error(format!("expected key=value, found `{}`", token)));
+                }
+            }
+        }
+        Ok(Some(directive))
+    }
+
+    fn error(&self, message: String) -> SceneError {
+        SceneError { line: self.line, message }
+    }
+
+    //the keyword and the `count - 1` words that follow it
+    fn words(&self, count: usize, usage: &str) -> Result<&[String], SceneError> {
+        if self.words.len() != count {
+            return Err(self.error(format!("expected `{}`", usage)));
+        }
+        Ok(&self.words)
+    }
+
+    fn take(&mut self, key: &str) -> Option<String> {
+        let index = self.params.iter().position(|(k, _)| k == key)?;
+        Some(self.params.remove(index).1)
+    }
+
+    fn require(&mut self, key: &str) -> Result<String, SceneError> {
+        self.take(key)
+            .ok_or_else(|| self.error(format!("`{}` needs the parameter `{}`", self.words[0], key)))
+    }
+
+    fn parse_numbers(&self, key: &str, value: &str, count: usize) -> Result<Vec<f64>, SceneError> {
+        let numbers: Result<Vec<f64>, _> = value.split(',').map(|s| s.trim().parse::<f64>()).collect();
+        match numbers {
+            Ok(numbers) if numbers.len() == count && numbers.iter().all(|x| x.is_finite()) => Ok(numbers),
+            _ => Err(self.error(format!(
+                "`{}` expects {} number{}, found `{}`",
+                key,
+                count,
+                if count == 1 { "" } else { "s separated by commas" },
+                value
+            ))),
+        }
+    }
+
+    fn number(&mut self, key: &str) -> Result<Option<f64>, SceneError> {
+        match self.take(key) {
+            Some(value) => Ok(Some(self.parse_numbers(key, &value, 1)?[0])),
+            None => Ok(None),
+        }
+    }
+
+    fn require_number(&mut self, key: &str) -> Result<f64, SceneError> {
+        let value = self.require(key)?;
+        Ok(self.parse_numbers(key, &value, 1)?[0])
+    }
+
+    fn positive_number(&mut self, key: &str) -> Result<Option<f64>, SceneError> {
+        match self.number(key)? {
+            Some(x) if x <= 0.0 => Err(self.error(format!("`{}` must be positive", key))),
+            x => Ok(x),
+        }
+    }
+
+    fn count(&mut self, key: &str) -> Result<Option<usize>, SceneError> {
+        match self.take(key) {
+            Some(value) => match value.parse::<usize>() {
+                Ok(n) if n > 0 => Ok(Some(n)),
+                _ => Err(self.error(format!("`{}` expects a positive integer, found `{}`", key, value))),
+            },
+            None => Ok(None),
+        }
+    }
+
+    //at least 2, as the renderer spreads pixels from one edge of the view to the other
+    fn image_size(&mut self, key: &str) -> Result<Option<usize>, SceneError> {
+        match self.take(key) {
+            Some(value) => match value.parse::<usize>() {
+                Ok(n) if n >= 2 => Ok(Some(n)),
+                _ => Err(self.error(format!("`{}` expects an image size of at least 2 pixels, found `{}`", key, value))),
+            },
+            None => Ok(None),
+        }
+    }
+
+    fn pair(&mut self, key: &str) -> Result<Option<(f64, f64)>, SceneError> {
+        match self.take(key) {
+            Some(value) => {
+                let v = self.parse_numbers(key, &value, 2)?;
+                Ok(Some((v[0], v[1])))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn require_pair(&mut self, key: &str) -> Result<(f64, f64), SceneError> {
+        let value = self.require(key)?;
+        let v = self.parse_numbers(key, &value, 2)?;
+        Ok((v[0], v[1]))
+    }
+
+    fn vector(&mut self, key: &str) -> Result<Option<Vector3<f64>>, SceneError> {
+        match self.take(key) {
+            Some(value) => {
+                let v = self.parse_numbers(key, &value, 3)?;
+                Ok(Some(Vector3::new(v[0], v[1], v[2])))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn require_vector(&mut self, key: &str) -> Result<Vector3<f64>, SceneError> {
+        let value = self.require(key)?;
+        let v = self.parse_numbers(key, &value, 3)?;
+        Ok(Vector3::new(v[0], v[1], v[2]))
+    }
+
+    fn require_point(&mut self, key: &str) -> Result<Point3<f64>, SceneError> {
+        Ok(Point3::from_vec(self.require_vector(key)?))
+    }
+
+    //every parameter must have been consumed, so typos do not go unnoticed
+    fn finish(self) -> Result<(), SceneError> {
+        match self.params.first() {
+            Some((key, _)) => Err(self.error(format!("unknown parameter `{}` for `{}`", key, self.words[0]))),
+            None => Ok(()),
+        }
+    }
+}
+
+//a named object that is only rendered through `instance`
+struct Prototype {
+    object: Arc<dyn Hittable>,
+    samplable: bool, //see is_samplable
+    emissive: bool,
+    power_material: Option<Arc<dyn Material>>, //see power_material
+}
+
+struct Parser<'a> {
+    sampler: &'a mut dyn Sampler,
+    base_dir: PathBuf,
+    textures: HashMap<String, Arc<dyn Texture>>,
+    materials: HashMap<String, Arc<dyn Material>>,
+    prototypes: HashMap<String, Prototype>,
+    world: HittableList,
+    lights: LightList,
+    camera: Option<CameraSettings>,
+    background: Option<Arc<dyn Environment>>,
+    image_width: Option<usize>,
+    image_height: Option<usize>,
+    samples_per_pixel: Option<usize>,
+    limits: PathLimits,
+    sampler_kind: Option<SamplerKind>,
+    color_mode: Option<ColorMode>,
+}
+
+impl<'a> Parser<'a> {
+    fn directive(&mut self, mut d: Directive) -> Result<(), SceneError> {
+        let keyword = d.words[0].clone();
+        match keyword.as_str() {
+            "camera" => {
+                d.words(1, "camera from=x,y,z at=x,y,z ...")?;
+                if self.camera.is_some() {
+                    return Err(d.error(String::from("the camera is defined twice")));
+                }
+                let mut camera = CameraSettings::new(d.require_point("from")?, d.require_point("at")?, 40.0);
+                if let Some(vup) = d.vector("up")? {
+                    camera.vup = vup;
+                }
+                if let Some(fov) = d.number("fov")? {
+                    if fov <= 0.0 || fov >= 180.0 {
+                        return Err(d.error(String::from("`fov` must be between 0 and 180 degrees")));
+                    }
+                    camera.fov = fov;
+                }
+                if let Some(aperture) = d.number("aperture")? {
+                    if aperture < 0.0 {
+                        return Err(d.error(String::from("`aperture` must not be negative")));
+                    }
+                    camera.aperture = aperture;
+                }
+                if let Some(focus_dist) = d.positive_number("focus_dist")? {
+                    camera.focus_dist = focus_dist;
+                }
+                if let Some((time0, time1)) = d.pair("shutter")? {
+                    camera.time0 = time0;
+                    camera.time1 = time1;
+                }
+                self.camera = Some(camera);
+                d.finish()
+            }
+            "render" => {
+                d.words(1, "render width=.. height=.. samples=.. max_depth=.. ...")?;
+                self.image_width = d.image_size("width")?.or(self.image_width);
+                self.image_height = d.image_size("height")?.or(self.image_height);
+                self.samples_per_pixel = d.count("samples")?.or(self.samples_per_pixel);
+                let limits = &mut self.limits;
+                limits.max_depth = d.count("max_depth")?.unwrap_or(limits.max_depth);
+                limits.max_diffuse = d.count("max_diffuse")?.unwrap_or(limits.max_diffuse);
+                limits.max_specular = d.count("max_specular")?.unwrap_or(limits.max_specular);
+                limits.max_volume = d.count("max_volume")?.unwrap_or(limits.max_volume);
+                limits.rr_min_bounces = d.count("rr_depth")?.unwrap_or(limits.rr_min_bounces);
+                if let Some(name) = d.take("sampler") {
+                    match SamplerKind::from_name(&name) {
+                        Some(kind) => self.sampler_kind = Some(kind),
+                        None => return Err(d.error(format!("unknown sampler `{}`", name))),
+                    }
+                }
+                if let Some(name) = d.take("color_mode") {
+                    match ColorMode::from_name(&name) {
+                        Some(mode) => self.color_mode = Some(mode),
+                        None => return Err(d.error(format!("unknown color mode `{}`", name))),
+                    }
+                }
+                d.finish()
+            }
+            "background" => {
+                d.words(1, "background color=r,g,b")?;
+                if self.background.is_some() {
+                    return Err(d.error(String::from("the background is defined twice")));
+                }
+                self.background = Some(Arc::new(d.require_vector("color")?));
+                d.finish()
+            }
+            "environment" => {
+                d.words(2, "environment map|sky key=value ...")?;
+                if self.background.is_some() {
+                    return Err(d.error(String::from("the background is defined twice")));
+                }
+                self.environment(&mut d)?;
+                d.finish()
+            }
+            "texture" => {
+                let name = d.words(3, "texture <name> <kind> ...")?[1].clone();
+                let texture = self.texture(&mut d)?;
+                if self.textures.insert(name.clone(), texture).is_some() {
+                    return Err(d.error(format!("texture `{}` is defined twice", name)));
+                }
+                d.finish()
+            }
+            "material" => {
+                let name = d.words(3, "material <name> <kind> ...")?[1].clone();
+                let material = self.material(&mut d)?;
+                if self.materials.insert(name.clone(), material).is_some() {
+                    return Err(d.error(format!("material `{}` is defined twice", name)));
+                }
+                d.finish()
+            }
+            "light" => {
+                d.words(2, "light <kind> key=value ...")?;
+                self.light(&mut d)?;
+                d.finish()
+            }
+            "object" => {
+                let name = d.words(3, "object <name> <kind> ...")?[1].clone();
+                d.words.drain(..2);
+                let samplable = self.is_samplable(&d);
+                let emissive = self.is_emissive(&d);
+                let power_material = self.power_material(&d);
+                let object = self.object(&mut d)?;
+                let prototype = Prototype {
+                    object: Arc::from(self.modifiers(&mut d, object)?),
+                    samplable,
+                    emissive,
+                    power_material,
+                };
+                //instances fit the power to their own placement; this only fails early
+                if let Some(material) = &prototype.power_material {
+                    self.fit_power(&d, &prototype.object, material)?;
+                }
+                if self.prototypes.insert(name.clone(), prototype).is_some() {
+                    return Err(d.error(format!("object `{}` is defined twice", name)));
+                }
+                d.finish()
+            }
+            "instance" => {
+                let name = d.words(2, "instance <object> key=value ...")?[1].clone();
+                let prototype = self
+                    .prototypes
+                    .get(&name)
+                    .ok_or_else(|| d.error(format!("unknown object `{}`", name)))?;
+                let (object, samplable, emissive) = (Arc::clone(&prototype.object), prototype.samplable, prototype.emissive);
+                let mut power_material = prototype.power_material.clone();
+                let mut material = if d.params.iter().any(|(k, _)| k == "material") {
+                    power_material = self.power_material(&d);
+                    Some(self.require_material(&mut d)?)
+                } else {
+                    None
+                };
+                let emissive = match &material {
+                    Some(material) => samplable && material.is_emissive(),
+                    None => emissive,
+                };
+                let matrix = self.transform(&mut d)?.unwrap_or_else(Matrix4::identity);
+                if let Some(power_material) = power_material {
+                    let placed = Transform::new(Arc::clone(&object), matrix).map_err(|e| d.error(e.to_string()))?;
+                    material = Some(self.fit_power(&d, &placed, &power_material)?);
+                }
+
+                let instance = Instance::new(object, matrix, material).map_err(|e| d.error(e.to_string()))?;
+                let instance: Arc<dyn Hittable> = Arc::new(instance);
+                if emissive {
+                    self.lights.add_shape(Arc::clone(&instance));
+                }
+                self.world.add_shared(instance);
+                d.finish()
+            }
+            _ => {
+                d.words(1, &format!("{} key=value ...", keyword))?;
+                let emissive = self.is_emissive(&d);
+                let power_material = self.power_material(&d);
+                let object = self.object(&mut d)?;
+                let mut object: Arc<dyn Hittable> = Arc::from(self.modifiers(&mut d, object)?);
+                if let Some(material) = power_material {
+                    let material = self.fit_power(&d, &object, &material)?;
+                    object = Arc::new(Instance::new(object, Matrix4::identity(), Some(material)).unwrap());
+                }
+                if emissive {
+                    self.lights.add_shape(Arc::clone(&object));
+                }
+                self.world.add_shared(object);
+                d.finish()
+            }
+        }
+    }
+
+    fn texture(&mut self, d: &mut Directive) -> Result<Arc<dyn Texture>, SceneError> {
+        let texture: Arc<dyn Texture> = match d.words[2].as_str() {
+            "solid" => Arc::new(SolidColor::new(d.require_vector("color")?)),
+            "checker" => {
+                let even = self.require_texture(d, "even")?;
+                let odd = self.require_texture(d, "odd")?;
+                Arc::new(CheckerTexture::new(Box::new(even), Box::new(odd)))
+            }
+            "noise" => Arc::new(NoiseTexture::new(d.number("scale")?.unwrap_or(1.0), self.sampler)),
+            "image" => {
+                let file = self.base_dir.join(d.require("file")?);
+                if !file.is_file() {
+                    return Err(d.error(format!("image file {} does not exist", file.display())));
+                }
+                let texture = ImageTexture::load(&file)
+                    .map_err(|e| d.error(format!("cannot load image {}: {}", file.display(), e)))?;
+                Arc::new(texture)
+            }
+            "vertex_color" => Arc::new(VertexColorTexture::new(
+                d.vector("fallback")?.unwrap_or_else(|| Color::new(0.8, 0.8, 0.8)),
+            )),
+            kind => return Err(d.error(format!("unknown texture kind `{}`", kind))),
+        };
+        Ok(texture)
+    }
+
+    fn material(&self, d: &mut Directive) -> Result<Arc<dyn Material>, SceneError> {
+        let material: Arc<dyn Material> = match d.words[2].as_str() {
+            "lambertian" => Arc::new(Lambertian::new_texture(self.require_texture(d, "albedo")?)),
+            "metal" => Arc::new(Metal::new(d.require_vector("albedo")?, d.number("fuzz")?.unwrap_or(0.0))),
+            "metallic_roughness" => Arc::new(MetallicRoughness::new(
+                self.require_texture(d, "color")?,
+                self.scalar_param(d, "roughness", 0.5)?,
+                self.scalar_param(d, "metallic", 0.0)?,
+            )),
+            "dielectric" => Arc::new(
+                Dielectric::new_dispersive(self.ior(d)?)
+                    .with_roughness(d.number("roughness")?.unwrap_or(0.0))
+                    .with_absorption(d.vector("absorption")?.unwrap_or(BLACK)),
+            ),
+            "diffuse_light" => Arc::new(self.diffuse_light(d)?),
+            "isotropic" => Arc::new(Isotropic::new(Box::new(self.require_texture(d, "albedo")?))),
+            "mix" => Arc::new(MixMaterial::new(
+                self.named_material(d, "a")?,
+                self.named_material(d, "b")?,
+                self.scalar_param(d, "weight", 0.5)?,
+            )),
+            "coated" => Arc::new(
+                Coated::new(self.named_material(d, "base")?, d.number("ior")?.unwrap_or(1.5))
+                    .with_color(d.vector("color")?.unwrap_or_else(|| Color::new(1.0, 1.0, 1.0))),
+            ),
+            kind => return Err(d.error(format!("unknown material kind `{}`", kind))),
+        };
+        Ok(material)
+    }
+
+    //checked before the object consumes its parameters; a medium never counts as a light,
+    //and meshes cannot be sampled as lights, so they only shine when hit
+    fn is_samplable(&self, d: &Directive) -> bool {
+        d.words[0] != "mesh" && d.params.iter().all(|(k, _)| k != "density")
+    }
+
+    fn is_emissive(&self, d: &Directive) -> bool {
+        self.is_samplable(d) && self.materials_param(d).is_some_and(|material| material.is_emissive())
+    }
+
+    fn object(&self, d: &mut Directive) -> Result<Box<dyn Hittable>, SceneError> {
+        const OBJECTS: [&str; 9] = [
+            "sphere",
+            "moving_sphere",
+            "xy_rect",
+            "xz_rect",
+            "yz_rect",
+            "box",
+            "triangle",
+            "quad",
+            "mesh",
+        ];
+        if !OBJECTS.contains(&d.words[0].as_str()) {
+            return Err(d.error(format!("unknown directive `{}`", d.words[0])));
+        }
+        if d.words[0] == "mesh" {
+            return self.mesh(d);
+        }
+
+        //a medium only uses its boundary's shape, so the material may be left out
+        let material = if d.params.iter().any(|(k, _)| k == "density") && !d.params.iter().any(|(k, _)| k == "material") {
+            Arc::new(Lambertian::new(Color::new(0.0, 0.0, 0.0)))
+        } else {
+            self.require_material(d)?
+        };
+
+        let object: Box<dyn Hittable> = match d.words[0].as_str() {
+            "sphere" => {
+                let radius = d.require_number("radius")?;
+                Box::new(Sphere::new(d.require_point("center")?, radius, material))
+            }
+            "moving_sphere" => {
+                let center0 = d.require_point("center0")?;
+                let center1 = d.require_point("center1")?;
+                let (time0, time1) = d.pair("time")?.unwrap_or((0.0, 1.0));
+                if time1 <= time0 {
+                    return Err(d.error(String::from("`time` must be an increasing interval")));
+                }
+                let radius = d.require_number("radius")?;
+                Box::new(MovingSphere::new(center0, center1, time0, time1, radius, material))
+            }
+            "xy_rect" => {
+                let (x0, x1) = self.interval(d, "x")?;
+                let (y0, y1) = self.interval(d, "y")?;
+                Box::new(XyRect::new(x0, x1, y0, y1, d.require_number("z")?, material))
+            }
+            "xz_rect" => {
+                let (x0, x1) = self.interval(d, "x")?;
+                let (z0, z1) = self.interval(d, "z")?;
+                Box::new(XzRect::new(x0, x1, z0, z1, d.require_number("y")?, material))
+            }
+            "yz_rect" => {
+                let (y0, y1) = self.interval(d, "y")?;
+                let (z0, z1) = self.interval(d, "z")?;
+                Box::new(YzRect::new(y0, y1, z0, z1, d.require_number("x")?, material))
+            }
+            "box" => {
+                let p0 = d.require_point("min")?;
+                let p1 = d.require_point("max")?;
+                if p0.x >= p1.x || p0.y >= p1.y || p0.z >= p1.z {
+                    return Err(d.error(String::from("`min` must be smaller than `max` on every axis")));
+                }
+                Box::new(Cubic::new(p0, p1, material))
+            }
+            "triangle" => {
+                let (p0, p1, p2) = (d.require_point("a")?, d.require_point("b")?, d.require_point("c")?);
+                Box::new(Triangle::new(p0, p1, p2, material))
+            }
+            "quad" => {
+                let (q, u, v) = (d.require_point("corner")?, d.require_vector("u")?, d.require_vector("v")?);
+                if u.cross(v).magnitude2() == 0.0 {
+                    return Err(d.error(String::from("`u` and `v` must span a parallelogram")));
+                }
+                Box::new(Quad::new(q, u, v, material))
+            }
+            _ => unreachable!(),
+        };
+        Ok(object)
+    }
+
+    //a model file; the materials it defines are used unless `material` overrides them
+    fn mesh(&self, d: &mut Directive) -> Result<Box<dyn Hittable>, SceneError> {
+        let file = self.base_dir.join(d.require("file")?);
+        let material = if d.params.iter().any(|(k, _)| k == "material") {
+            Some(self.require_material(d)?)
+        } else {
+            None
+        };
+
+        let extension = file.extension().map(|e| e.to_string_lossy().to_lowercase());
+        let mut meshes = HittableList::new();
+        match extension.as_deref() {
+            Some("obj") => {
+                let groups = obj::load(&file).map_err(|e| d.error(format!("cannot load {}: {}", file.display(), e)))?;
+                for group in groups {
+                    let material = material.as_ref().map_or(group.material, Arc::clone);
+                    meshes.add(Box::new(TriangleMesh::new(group.data, material)));
+                }
+            }
+            //PLY vertex colors show through the default material
+            Some("ply") | Some("stl") => {
+                let loaded = if extension.as_deref() == Some("ply") {
+                    ply::load(&file)
+                } else {
+                    stl::load(&file)
+                };
+                let data = loaded.map_err(|e| d.error(format!("cannot load {}: {}", file.display(), e)))?;
+                let material = material.unwrap_or_else(|| {
+                    Arc::new(Lambertian::new_texture(Arc::new(VertexColorTexture::new(Color::new(0.8, 0.8, 0.8)))))
+                });
+                meshes.add(Box::new(TriangleMesh::new(data, material)));
+            }
+            //only the geometry is taken from a glTF file, its cameras are ignored here
+            Some("gltf") | Some("glb") => {
+                meshes = gltf_import::load(&file, material)
+                    .map_err(|e| d.error(format!("cannot load {}: {}", file.display(), e)))?
+                    .world;
+            }
+            _ => return Err(d.error(format!("unsupported mesh format: {}", file.display()))),
+        }
+        Ok(Box::new(BVH::new(meshes, 0.0, 1.0)))
+    }
+
+    //transforms in the order they are written, then fill with a participating medium
+    fn modifiers(&self, d: &mut Directive, mut object: Box<dyn Hittable>) -> Result<Box<dyn Hittable>, SceneError> {
+        if let Some(matrix) = self.transform(d)? {
+            object = Box::new(Transform::new(Arc::from(object), matrix).map_err(|e| d.error(e.to_string()))?);
+        }
+        if let Some(density) = d.positive_number("density")? {
+            let phase = match self.texture_param(d, "phase")? {
+                Some(texture) => texture,
+                None => Arc::new(SolidColor::new(Color::new(1.0, 1.0, 1.0))),
+            };
+            object = Box::new(ConstantMedium::new(object, Box::new(phase), density));
+        }
+        Ok(object)
+    }
+
+    //scale=s or scale=x,y,z, rotate_x/rotate_y/rotate_z=degrees, rotate=x,y,z,degrees
+    //about an axis, and translate=x,y,z; each applies after the ones before it
+    fn transform(&self, d: &mut Directive) -> Result<Option<Matrix4<f64>>, SceneError> {
+        const TRANSFORMS: [&str; 6] = ["scale", "rotate_x", "rotate_y", "rotate_z", "rotate", "translate"];
+        let keys: Vec<String> = d
+            .params
+            .iter()
+            .map(|(k, _)| k.clone())
+            .filter(|k| TRANSFORMS.contains(&k.as_str()))
+            .collect();
+        if keys.is_empty() {
+            return Ok(None);
+        }
+
+        let mut matrix = Matrix4::identity();
+        for key in keys {
+            let value = d.take(&key).unwrap_or_default();
+            let step = match key.as_str() {
+                "translate" => {
+                    let v = d.parse_numbers(&key, &value, 3)?;
+                    Matrix4::from_translation(Vector3::new(v[0], v[1], v[2]))
+                }
+                "scale" => {
+                    let count = if value.contains(',') { 3 } else { 1 };
+                    let v = d.parse_numbers(&key, &value, count)?;
+                    let v = if count == 1 { [v[0]; 3] } else { [v[0], v[1], v[2]] };
+                    if v.contains(&0.0) {
+                        return Err(d.error(String::from("`scale` must not be 0")));
+                    }
+                    Matrix4::from_nonuniform_scale(v[0], v[1], v[2])
+                }
+                "rotate_x" => Matrix4::from_angle_x(Deg(d.parse_numbers(&key, &value, 1)?[0])),
+                "rotate_y" => Matrix4::from_angle_y(Deg(d.parse_numbers(&key, &value, 1)?[0])),
+                "rotate_z" => Matrix4::from_angle_z(Deg(d.parse_numbers(&key, &value, 1)?[0])),
+                _ => {
+                    let v = d.parse_numbers(&key, &value, 4)?;
+                    let axis = Vector3::new(v[0], v[1], v[2]);
+                    if axis.magnitude2() == 0.0 {
+                        return Err(d.error(String::from("`rotate` needs a non-zero axis")));
+                    }
+                    Matrix4::from_axis_angle(axis.normalize(), Deg(v[3]))
+                }
+            };
+            matrix = step * matrix;
+        }
+        //a tiny scale passes the checks above but still leaves nothing to invert
+        if matrix.invert().is_none() {
+            return Err(d.error(String::from("the transform cannot be inverted")));
+        }
+        Ok(Some(matrix))
+    }
+
+    fn interval(&self, d: &mut Directive, key: &str) -> Result<(f64, f64), SceneError> {
+        let (a, b) = d.require_pair(key)?;
+        if a >= b {
+            return Err(d.error(format!("`{}` must be an increasing interval", key)));
+        }
+        Ok((a, b))
+    }
+
+    //either a color written inline or the name of a texture declared earlier
+    fn texture_param(&self, d: &mut Directive, key: &str) -> Result<Option<Arc<dyn Texture>>, SceneError> {
+        let value = match d.take(key) {
+            Some(value) => value,
+            None => return Ok(None),
+        };
+        if value.contains(',') || value.parse::<f64>().is_ok() {
+            let c = d.parse_numbers(key, &value, 3)?;
+            return Ok(Some(Arc::new(SolidColor::new(Color::new(c[0], c[1], c[2])))));
+        }
+        match self.textures.get(&value) {
+            Some(texture) => Ok(Some(Arc::clone(texture))),
+            None => Err(d.error(format!("unknown texture `{}`", value))),
+        }
+    }
+
+    //emit=<color or texture> is the radiance, or temperature=<kelvin> a blackbody color
+    //scaled by `intensity`; power=<watts> or lumens=<lm> instead fixes the output of
+    //every object it is put on, and then emit must be a color, whose brightness is
+    //ignored. sides=1 emits only along the surface normal, and profile= shapes the
+    //emission: cosine exponent=n, spot cone=degrees falloff=degrees, or table values=a,b,...
+    //from the normal out to 90 degrees.
+    fn diffuse_light(&self, d: &mut Directive) -> Result<DiffuseLight, SceneError> {
+        let temperature = d.positive_number("temperature")?;
+        let watts = self.power(d)?;
+
+        let emit: Arc<dyn Texture> = match (temperature, watts) {
+            (Some(_), _) if d.params.iter().any(|(k, _)| k == "emit") => {
+                return Err(d.error(String::from("give either `emit` or `temperature`")));
+            }
+            (Some(kelvin), _) => Arc::new(SolidColor::new(blackbody(kelvin))),
+            (None, Some(_)) => {
+                let color = d.vector("emit")?.unwrap_or_else(|| Color::new(1.0, 1.0, 1.0));
+                if luminance(color) <= 0.0 {
+                    return Err(d.error(String::from("`emit` must have a brightness to take the color from")));
+                }
+                Arc::new(SolidColor::new(color / luminance(color)))
+            }
+            (None, None) => self.require_texture(d, "emit")?,
+        };
+        let mut light = DiffuseLight::new(emit);
+        match d.number("intensity")? {
+            Some(_) if watts.is_some() => {
+                return Err(d.error(String::from("`intensity` cannot be combined with `power` or `lumens`")));
+            }
+            Some(intensity) if intensity >= 0.0 => light = light.with_intensity(intensity),
+            Some(_) => return Err(d.error(String::from("`intensity` must not be negative"))),
+            None => {}
+        }
+        match d.count("sides")? {
+            None | Some(2) => {}
+            Some(1) => light = light.one_sided(),
+            Some(_) => return Err(d.error(String::from("`sides` must be 1 or 2"))),
+        }
+        let profile = match d.take("profile").as_deref() {
+            None | Some("lambertian") => EmissionProfile::Lambertian,
+            Some("cosine") => EmissionProfile::Cosine {
+                exponent: d.number("exponent")?.unwrap_or(1.0).max(0.0),
+            },
+            Some("spot") => {
+                let cone = d.require_number("cone")?;
+                EmissionProfile::spot(cone, d.number("falloff")?.unwrap_or(cone))
+            }
+            Some("table") => {
+                let values = d.require("values")?;
+                let count = values.split(',').count();
+                let values = d.parse_numbers("values", &values, count)?;
+                if values.iter().any(|&v| v < 0.0) {
+                    return Err(d.error(String::from("`values` must not be negative")));
+                }
+                EmissionProfile::Table(values)
+            }
+            Some(profile) => {
+                return Err(d.error(format!(
+                    "unknown emission profile `{}`, expected lambertian, cosine, spot or table",
+                    profile
+                )))
+            }
+        };
+        light = light.with_profile(profile);
+        if let Some(watts) = watts {
+            light = light.with_power(watts);
+        }
+        Ok(light)
+    }
+
+    //an image or a sky that is both the background and a light. `map file=` takes a
+    //Radiance .hdr panorama, turned about the y axis by `rotate` degrees; `sky sun=x,y,z
+    //turbidity=t` the Preetham sky for a sun in that direction. Both are scaled by `intensity`.
+    fn environment(&mut self, d: &mut Directive) -> Result<(), SceneError> {
+        let (environment, width, height): (Arc<dyn Environment>, usize, usize) = match d.words[1].as_str() {
+            "map" => {
+                let file = self.base_dir.join(d.require("file")?);
+                let map = EnvironmentMap::load(&file).map_err(|e| d.error(format!("cannot load {}: {}", file.display(), e)))?;
+                let map = map
+                    .with_rotation(d.number("rotate")?.unwrap_or(0.0))
+                    .with_intensity(d.number("intensity")?.unwrap_or(1.0));
+                let (width, height) = (map.width(), map.height());
+                (Arc::new(map), width, height)
+            }
+            "sky" => {
+                let sun = d.require_vector("sun")?;
+                if sun.y <= 0.0 {
+                    return Err(d.error(String::from("the `sun` of a sky must be above the horizon")));
+                }
+                let sky = Sky::new(sun, d.number("turbidity")?.unwrap_or(3.0))
+                    .with_intensity(d.number("intensity")?.unwrap_or(1.0));
+                (Arc::new(sky), 256, 128)
+            }
+            kind => return Err(d.error(format!("unknown environment `{}`, expected map or sky", kind))),
+        };
+        self.lights.add(Arc::new(EnvironmentLight::new(environment.as_ref(), width, height)));
+        self.background = Some(environment);
+        Ok(())
+    }
+
+    //`power` in watts or `lumens`
+    fn power(&self, d: &mut Directive) -> Result<Option<f64>, SceneError> {
+        let watts = match (d.number("power")?, d.number("lumens")?) {
+            (Some(_), Some(_)) => return Err(d.error(String::from("give either `power` or `lumens`"))),
+            (Some(watts), None) => Some(watts),
+            (None, Some(lumens)) => Some(lumens / LUMENS_PER_WATT),
+            (None, None) => None,
+        };
+        if watts.is_some_and(|w| w < 0.0) {
+            return Err(d.error(String::from("the power of a light must not be negative")));
+        }
+        Ok(watts)
+    }
+
+    //point, spot and directional lights, which have no shape, and spheres and quads that
+    //emit. The first take color=r,g,b or temperature=kelvin times intensity=s, which is
+    //in watts per steradian, or per square meter for a directional light; point and spot
+    //lights may give power= or lumens= instead. The shapes take a `material` or the
+    //parameters of a diffuse_light.
+    fn light(&mut self, d: &mut Directive) -> Result<(), SceneError> {
+        let kind = d.words[1].clone();
+        match kind.as_str() {
+            "point" | "spot" | "directional" => {
+                let watts = if kind == "directional" { None } else { self.power(d)? };
+                let color = match (d.positive_number("temperature")?, d.vector("color")?) {
+                    (Some(_), Some(_)) => return Err(d.error(String::from("give either `color` or `temperature`"))),
+                    (Some(kelvin), None) => blackbody(kelvin),
+                    (None, Some(color)) => color,
+                    (None, None) => Color::new(1.0, 1.0, 1.0),
+                };
+                let intensity = match (d.number("intensity")?, watts) {
+                    (Some(_), Some(_)) => {
+                        return Err(d.error(String::from("`intensity` cannot be combined with `power` or `lumens`")));
+                    }
+                    (Some(intensity), None) if intensity < 0.0 => {
+                        return Err(d.error(String::from("`intensity` must not be negative")));
+                    }
+                    (Some(intensity), None) => color * intensity,
+                    (None, Some(_)) if luminance(color) <= 0.0 => {
+                        return Err(d.error(String::from("`color` must have a brightness to take the color from")));
+                    }
+                    (None, Some(_)) => color / luminance(color),
+                    (None, None) => color,
+                };
+
+                let light: Arc<dyn Light> = match kind.as_str() {
+                    "point" => {
+                        let light = PointLight::new(d.require_point("position")?, intensity);
+                        Arc::new(match watts {
+                            Some(watts) => light.with_power(watts),
+                            None => light,
+                        })
+                    }
+                    "spot" => {
+                        let (position, at) = (d.require_point("position")?, d.require_point("at")?);
+                        if position == at {
+                            return Err(d.error(String::from("`at` must differ from `position`")));
+                        }
+                        let cone = d.require_number("cone")?;
+                        let light = SpotLight::new(position, at, intensity, cone, d.number("falloff")?.unwrap_or(cone));
+                        Arc::new(match watts {
+                            Some(watts) => light.with_power(watts),
+                            None => light,
+                        })
+                    }
+                    _ => {
+                        let direction = d.require_vector("direction")?;
+                        if direction.magnitude2() == 0.0 {
+                            return Err(d.error(String::from("`direction` must not be zero")));
+                        }
+                        Arc::new(DirectionalLight::new(direction, intensity))
+                    }
+                };
+                self.lights.add(light);
+            }
+            "sphere" | "quad" => {
+                //built a second time once a power is fitted to the area
+                type Shape = Box<dyn Fn(Arc<dyn Material>) -> Arc<dyn Hittable>>;
+                let shape: Shape = if kind == "sphere" {
+                    let (center, radius) = (d.require_point("center")?, d.require_number("radius")?);
+                    Box::new(move |material| Arc::new(Sphere::new(center, radius, material)))
+                } else {
+                    let (q, u, v) = (d.require_point("corner")?, d.require_vector("u")?, d.require_vector("v")?);
+                    if u.cross(v).magnitude2() == 0.0 {
+                        return Err(d.error(String::from("`u` and `v` must span a parallelogram")));
+                    }
+                    Box::new(move |material| Arc::new(Quad::new(q, u, v, material)))
+                };
+                let material = if d.params.iter().any(|(k, _)| k == "material") {
+                    self.require_material(d)?
+                } else {
+                    Arc::new(self.diffuse_light(d)?)
+                };
+                if !material.is_emissive() {
+                    return Err(d.error(String::from("the material of a light must be emissive")));
+                }
+
+                let mut object = shape(Arc::clone(&material));
+                if material.fit_to_area(1.0).is_some() {
+                    object = shape(self.fit_power(d, &object, &material)?);
+                }
+                self.lights.add_shape(Arc::clone(&object));
+                self.world.add_shared(object);
+            }
+            _ => {
+                return Err(d.error(format!(
+                    "unknown light kind `{}`, expected point, spot, directional, sphere or quad",
+                    kind
+                )))
+            }
+        }
+        Ok(())
+    }
+
+    //checked before the object consumes its parameters: the material of a light given by
+    //its power, which still has to be fitted to the object's area. A medium only borrows
+    //the shape of its boundary.
+    fn power_material(&self, d: &Directive) -> Option<Arc<dyn Material>> {
+        if d.params.iter().any(|(k, _)| k == "density") {
+            return None;
+        }
+        self.materials_param(d).filter(|material| material.fit_to_area(1.0).is_some())
+    }
+
+    //the material named by `material`, left in place for the object to take
+    fn materials_param(&self, d: &Directive) -> Option<Arc<dyn Material>> {
+        d.params
+            .iter()
+            .find(|(k, _)| k == "material")
+            .and_then(|(_, name)| self.materials.get(name))
+            .cloned()
+    }
+
+    //material fitted to the area of `object`, see power_material
+    fn fit_power(&self, d: &Directive, object: &dyn Hittable, material: &Arc<dyn Material>) -> Result<Arc<dyn Material>, SceneError> {
+        match object.area() {
+            Some(area) if area > 0.0 => Ok(material.fit_to_area(area).unwrap_or_else(|| Arc::clone(material))),
+            _ => Err(d.error(String::from(
+                "a light given by its power needs a surface whose area is known, \
+                 which curved surfaces lose under a non-uniform scale",
+            ))),
+        }
+    }
+
+    //a constant `ior`, a named glass, or the coefficients of a dispersion formula
+    fn ior(&self, d: &mut Directive) -> Result<Ior, SceneError> {
+        if let Some(value) = d.take("cauchy") {
+            let c = d.parse_numbers("cauchy", &value, 2)?;
+            return Ok(Ior::Cauchy { a: c[0], b: c[1] });
+        }
+        if let Some(value) = d.take("sellmeier") {
+            let c = d.parse_numbers("sellmeier", &value, 6)?;
+            return Ok(Ior::Sellmeier {
+                b: [c[0], c[1], c[2]],
+                c: [c[3], c[4], c[5]],
+            });
+        }
+        let value = d.require("ior")?;
+        if let Some(ior) = Ior::from_name(&value) {
+            return Ok(ior);
+        }
+        if value.parse::<f64>().is_err() {
+            return Err(d.error(format!(
+                "`ior` expects a number or one of {}, found `{}`",
+                Ior::NAMES.join(", "),
+                value
+            )));
+        }
+        Ok(Ior::Constant(d.parse_numbers("ior", &value, 1)?[0]))
+    }
+
+    //a single number, or a texture whose red channel holds the value
+    fn scalar_param(&self, d: &mut Directive, key: &str, default: f64) -> Result<Arc<dyn Texture>, SceneError> {
+        if let Some(x) = d.params.iter().find(|(k, _)| k == key).and_then(|(_, v)| v.parse::<f64>().ok()) {
+            d.take(key);
+            return Ok(Arc::new(SolidColor::new(Color::new(x, x, x))));
+        }
+        Ok(self
+            .texture_param(d, key)?
+            .unwrap_or_else(|| Arc::new(SolidColor::new(Color::new(default, default, default)))))
+    }
+
+    fn require_texture(&self, d: &mut Directive, key: &str) -> Result<Arc<dyn Texture>, SceneError> {
+        match self.texture_param(d, key)? {
+            Some(texture) => Ok(texture),
+            None => Err(d.error(format!("`{}` needs the parameter `{}`", d.words.last().unwrap(), key))),
+        }
+    }
+
+    fn require_material(&self, d: &mut Directive) -> Result<Arc<dyn Material>, SceneError> {
+        self.named_material(d, "material")
+    }
+
+    fn named_material(&self, d: &mut Directive, key: &str) -> Result<Arc<dyn Material>, SceneError> {
+        let name = d.require(key)?;
+        match self.materials.get(&name) {
+            Some(material) => Ok(Arc::clone(material)),
+            None => Err(d.error(format!("unknown material `{}`", name))),
+        }
+    }
+
+    fn finish(self) -> Result<Scene, SceneError> {
+        let camera = self.camera.ok_or(SceneError {
+            line: 0,
+            message: String::from("the scene has no camera"),
+        })?;
+        let mut scene = Scene::new(self.world, camera);
+        scene.lights = self.lights;
+        if let Some(background) = self.background {
+            scene.background = background;
+        }
+        scene.image_width = self.image_width.unwrap_or(scene.image_width);
+        scene.image_height = self.image_height.unwrap_or(scene.image_height);
+        scene.samples_per_pixel = self.samples_per_pixel.unwrap_or(scene.samples_per_pixel);
+        scene.limits = self.limits;
+        scene.sampler = self.sampler_kind.unwrap_or(scene.sampler);
+        scene.color_mode = self.color_mode.unwrap_or(scene.color_mode);
+        Ok(scene)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::IndependentSampler;
+
+    fn parse_str(source: &str) -> Result<Scene, SceneError> {
+        parse(source, &std::env::temp_dir(), &mut IndependentSampler::new(1))
+    }
+
+    fn error_of(source: &str) -> SceneError {
+        parse_str(source).err().expect("the scene should not parse")
+    }
+
+    #[test]
+    fn bundled_scenes_load() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes");
+        let mut count = 0;
+        for entry in fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_some_and(|e| e == "scene") {
+                let result = load(path.to_str().unwrap(), &mut IndependentSampler::new(1));
+                if let Err(e) = result {
+                    panic!("{}: {}", path.display(), e);
+                }
+                count += 1;
+            }
+        }
+        assert!(count > 0);
+    }
+
+    #[test]
+    fn settings_and_objects_are_read() {
+        let source = "# a comment, then a blank line
+
+camera from=1,2,3 at=0,0,0 fov=30 aperture=0.1
+render width=40 height=30 samples=7 max_depth=5 sampler=halton
+background color=0.1,0.2,0.3
+material m lambertian albedo=0.5,0.5,0.5
+material lamp diffuse_light emit=4,4,4
+sphere center=0,0,0 radius=1 material=m
+xz_rect x=-1,1 z=-1,1 y=3 material=lamp
+";
+        let scene = parse_str(source).unwrap();
+        assert_eq!((scene.image_width, scene.image_height, scene.samples_per_pixel), (40, 30, 7));
+        assert_eq!(scene.limits.max_depth, 5);
+        assert_eq!(scene.sampler, SamplerKind::Halton);
+        assert_eq!(scene.camera.look_from, Point3::new(1.0, 2.0, 3.0));
+        assert_eq!(scene.camera.fov, 30.0);
+        assert_eq!(scene.camera.aperture, 0.1);
+        assert_eq!(scene.background.radiance(Vector3::new(0.0, 1.0, 0.0)), Color::new(0.1, 0.2, 0.3));
+        assert_eq!(scene.world.objects.len(), 2);
+        assert_eq!(scene.lights.len(), 1, "only the emissive rectangle is a light");
+    }
+
+    #[test]
+    fn errors_name_their_line() {
+        let cases = [
+            ("camera from=0,0,1 at=0,0,0\nsphere center=0,0,0 radius=1 material=nope\n", 2, "unknown material"),
+            ("camera from=0,0,1 at=0,0,0\ncamera from=0,0,1 at=0,0,0\n", 2, "defined twice"),
+            ("\n\ncamera from=0,0,1 at=0,0,0 colour=1\n", 3, "unknown parameter `colour`"),
+            ("camera from=0,0,1 at=0,0,0\nteapot size=1\n", 2, "teapot"),
+            ("camera from=0,0,1 at=0,0,0 aperture=-0.1\n", 1, "aperture"),
+            ("camera from=0,0,1 at=0,0,0\nrender width=ten\n", 2, "width"),
+            ("camera from=0,0,1 at=0,0,0\nrender width=64 height=1\n", 2, "at least 2 pixels"),
+            ("material m lambertian albedo=1,1,1\n", 0, "no camera"),
+        ];
+        for (source, line, message) in cases {
+            let error = error_of(source);
+            assert_eq!(error.line, line, "{}", error);
+            assert!(error.message.contains(message), "{}", error);
+        }
+    }
+
+    #[test]
+    fn power_lights_need_a_known_area() {
+        let source = "camera from=0,0,5 at=0,0,0\nmaterial lamp diffuse_light emit=1,1,1 power=100\n";
+        let flat = format!("{}quad corner=0,0,0 u=1,0,0 v=0,1,0 material=lamp scale=1,2,1\n", source);
+        assert!(parse_str(&flat).is_ok());
+        let squashed = format!("{}sphere center=0,0,0 radius=1 material=lamp scale=1,2,1\n", source);
+        let error = error_of(&squashed);
+        assert_eq!(error.line, 3);
+        assert!(error.message.contains("non-uniform scale"), "{}", error.message);
+    }
+
+    #[test]
+    fn unreadable_image_is_a_scene_error() {
+        let name = format!("ray_tracer_corrupt_texture_{}.png", std::process::id());
+        let file = std::env::temp_dir().join(&name);
+        fs::write(&file, b"not a png").unwrap();
+        let error = error_of(&format!("camera from=0,0,1 at=0,0,0\ntexture t image file={}\n", name));
+        fs::remove_file(&file).unwrap();
+        assert_eq!(error.line, 2);
+        assert!(error.message.contains("cannot load image"), "{}", error.message);
+    }
+
+    #[test]
+    fn vanishing_scale_is_a_scene_error() {
+        let source = "camera from=0,0,1 at=0,0,0\nmaterial m lambertian albedo=0.5,0.5,0.5\n\
+                      sphere center=0,0,0 radius=1 material=m scale=1e-200\n";
+        let error = error_of(source);
+        assert_eq!(error.line, 3);
+        assert!(error.message.contains("cannot be inverted"), "{}", error.message);
+    }
+}
@@ -0,0 +1,217 @@
+//Spectral rendering. In spectral mode every camera path carries three wavelengths in
+//place of the red, green and blue channels, drawn with a density that follows the eye's
+//sensitivity. RGB colors of textures, materials and lights are upsampled to smooth
+//spectra and read at those wavelengths; the CIE 1931 color matching functions turn the
+//result back into RGB. Dielectrics may give their index of refraction as a function of
+//wavelength, which is what makes dispersion visible: at such a surface the path keeps
+//only its first, "hero" wavelength (Wilkie et al. 2014, "Hero Wavelength Spectral
+//Sampling").
+
+use crate::color::{luminance, Color};
+use cgmath::*;
+use std::sync::OnceLock;
+
+//the range the color matching functions are integrated over, in nanometers
+pub const LAMBDA_MIN: f64 = 360.0;
+pub const LAMBDA_MAX: f64 = 830.0;
+
+//the sodium d line, where indices of refraction are usually quoted
+const LAMBDA_D: f64 = 587.6;
+
+//a wavelength in nanometers for the uniform number u, distributed as `wavelength_pdf`
+pub fn sample_wavelength(u: f64) -> f64 {
+    538.0 - 138.888889 * (0.85691062 - 1.82750197 * u).atanh()
+}
+
+//roughly proportional to the luminous efficiency, so that little time is spent on
+//wavelengths that barely show
+pub fn wavelength_pdf(lambda: f64) -> f64 {
+    if !(LAMBDA_MIN..=LAMBDA_MAX).contains(&lambda) {
+        return 0.0;
+    }
+    0.0039398042 / (0.0072 * (lambda - 538.0)).cosh().powi(2)
+}
+
+//a Gaussian with different widths on either side of its peak
+fn lobe(lambda: f64, mu: f64, sigma_below: f64, sigma_above: f64) -> f64 {
+    let sigma = if lambda < mu { sigma_below } else { sigma_above };
+    let t = (lambda - mu) / sigma;
+    (-0.5 * t * t).exp()
+}
+
+//the CIE 1931 standard observer, after the multi-lobe fit of Wyman, Sloan and Shirley,
+//"Simple Analytic Approximations to the CIE XYZ Color Matching Functions" (2013)
+fn cie_xyz(lambda: f64) -> Vector3<f64> {
+    Vector3::new(
+        1.056 * lobe(lambda, 599.8, 37.9, 31.0) + 0.362 * lobe(lambda, 442.0, 16.0, 26.7)
+            - 0.065 * lobe(lambda, 501.1, 20.4, 26.2),
+        0.821 * lobe(lambda, 568.8, 46.9, 40.5) + 0.286 * lobe(lambda, 530.9, 16.3, 31.1),
+        1.217 * lobe(lambda, 437.0, 11.8, 36.0) + 0.681 * lobe(lambda, 459.0, 26.0, 13.8),
+    )
+}
+
+//the color matching functions for the linear sRGB primaries
+fn srgb_matching(lambda: f64) -> Color {
+    let xyz_to_rgb = Matrix3::new(
+        3.2404542, -0.9692660, 0.0556434, //
+        -1.5371385, 1.8760108, -0.2040259, //
+        -0.4985314, 0.0415560, 1.0572252,
+    );
+    xyz_to_rgb * cie_xyz(lambda)
+}
+
+//the color of a black body at `kelvin`, by Planck's law, scaled to a luminance of one.
+//Low temperatures lie outside the sRGB gamut and lose their negative blue.
+pub fn blackbody(kelvin: f64) -> Color {
+    const H: f64 = 6.62607015e-34;
+    const C: f64 = 2.99792458e8;
+    const K: f64 = 1.380649e-23;
+    let mut rgb = Color::new(0.0, 0.0, 0.0);
+    for step in 0..=(LAMBDA_MAX - LAMBDA_MIN) as usize {
+        let lambda = (LAMBDA_MIN + step as f64) * 1e-9;
+        let radiance = 2.0 * H * C * C / (lambda.powi(5) * ((H * C / (lambda * K * kelvin)).exp() - 1.0));
+        rgb += srgb_matching(LAMBDA_MIN + step as f64) * radiance;
+    }
+    let rgb = rgb.map(|x| x.max(0.0));
+    rgb / luminance(rgb)
+}
+
+//three smooth spectra that sum to one everywhere, peaking in the blue, green and red
+fn basis(lambda: f64) -> Vector3<f64> {
+    let sigmoid = |x: f64| 1.0 / (1.0 + (-x).exp());
+    let above_blue = sigmoid((lambda - 490.0) / 12.0);
+    let above_green = sigmoid((lambda - 590.0) / 12.0);
+    Vector3::new(1.0 - above_blue, above_blue - above_green, above_green)
+}
+
+struct Tables {
+    //the RGB of the constant spectrum 1, which must come out white
+    white: Color,
+    //RGB colors to the weights of the basis spectra that reproduce them
+    to_basis: Matrix3<f64>,
+}
+
+fn tables() -> &'static Tables {
+    static TABLES: OnceLock<Tables> = OnceLock::new();
+    TABLES.get_or_init(|| {
+        let mut white = Color::new(0.0, 0.0, 0.0);
+        let mut columns = [Color::new(0.0, 0.0, 0.0); 3];
+        for step in 0..=(LAMBDA_MAX - LAMBDA_MIN) as usize {
+            let lambda = LAMBDA_MIN + step as f64;
+            let rgb = srgb_matching(lambda);
+            let b = basis(lambda);
+            white += rgb;
+            for (i, column) in columns.iter_mut().enumerate() {
+                *column += rgb * b[i];
+            }
+        }
+        let [r, g, b] = columns.map(|c| c.div_element_wise(white));
+        Tables {
+            white,
+            to_basis: Matrix3::from_cols(r, g, b)
+                .invert()
+                .expect("the basis spectra must be independent"),
+        }
+    })
+}
+
+//the value at `lambda` of a smooth spectrum whose color is c. Colors inside the gamut
+//but far from white may need a slightly negative spectrum, which is clamped.
+pub fn from_rgb(c: Color, lambda: f64) -> f64 {
+    (tables().to_basis * c).dot(basis(lambda)).max(0.0)
+}
+
+//the RGB contribution of the radiance `value` carried at `lambda`, a wavelength drawn by
+//`sample_wavelength`. Averaged over wavelengths it inverts `from_rgb`.
+pub fn to_rgb(value: f64, lambda: f64) -> Color {
+    let pdf = wavelength_pdf(lambda);
+    if pdf <= 0.0 {
+        return Color::new(0.0, 0.0, 0.0);
+    }
+    srgb_matching(lambda).div_element_wise(tables().white) * (value / pdf)
+}
+
+//the wavelengths of one path. They come from one sample shifted by thirds, so they are
+//spread over the spectrum while each is still distributed as `wavelength_pdf`.
+#[derive(Clone, Copy, Debug)]
+pub struct Wavelengths {
+    lambda: [f64; 3],
+}
+
+impl Wavelengths {
+    pub fn sample(u: f64) -> Self {
+        Wavelengths {
+            lambda: [0.0, 1.0, 2.0].map(|i| sample_wavelength((u + i / 3.0).fract())),
+        }
+    }
+
+    pub fn hero(&self) -> f64 {
+        self.lambda[0]
+    }
+
+    //c upsampled and read at each wavelength
+    pub fn spectrum(&self, c: Color) -> Color {
+        Color::new(
+            from_rgb(c, self.lambda[0]),
+            from_rgb(c, self.lambda[1]),
+            from_rgb(c, self.lambda[2]),
+        )
+    }
+
+    //the RGB contribution of radiance carried at these wavelengths
+    pub fn to_rgb(&self, radiance: Color) -> Color {
+        (to_rgb(radiance.x, self.lambda[0]) + to_rgb(radiance.y, self.lambda[1]) + to_rgb(radiance.z, self.lambda[2]))
+            / 3.0
+    }
+}
+
+//an index of refraction that may vary with wavelength; wavelengths are in micrometers
+#[derive(Clone, Copy, Debug)]
+pub enum Ior {
+    Constant(f64),
+    //n = a + b / λ²
+    Cauchy { a: f64, b: f64 },
+    //n² = 1 + Σ b[i] λ² / (λ² - c[i])
+    Sellmeier { b: [f64; 3], c: [f64; 3] },
+}
+
+impl Ior {
+    //Schott N-BK7, the common crown glass
+    pub const BK7: Ior = Ior::Sellmeier {
+        b: [1.03961212, 0.231792344, 1.01046945],
+        c: [0.00600069867, 0.0200179144, 103.560653],
+    };
+    //Schott SF11, a dense flint glass with strong dispersion
+    pub const SF11: Ior = Ior::Sellmeier {
+        b: [1.73759695, 0.313747346, 1.89878101],
+        c: [0.013188707, 0.0623068142, 155.23629],
+    };
+    pub const DIAMOND: Ior = Ior::Sellmeier {
+        b: [0.3306, 4.3356, 0.0],
+        c: [0.030625, 0.011236, 0.0],
+    };
+    pub const WATER: Ior = Ior::Cauchy { a: 1.3199, b: 0.00488 };
+
+    pub const NAMES: [&'static str; 4] = ["bk7", "sf11", "diamond", "water"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "bk7" => Some(Ior::BK7),
+            "sf11" => Some(Ior::SF11),
+            "diamond" => Some(Ior::DIAMOND),
+            "water" => Some(Ior::WATER),
+            _ => None,
+        }
+    }
+
+    //at `wavelength` in nanometers, or at the d line for paths that carry none
+    pub fn at(&self, wavelength: Option<f64>) -> f64 {
+        let l = wavelength.unwrap_or(LAMBDA_D) / 1000.0;
+        let l2 = l * l;
+        match *self {
+            Ior::Constant(n) => n,
+            Ior::Cauchy { a, b } => a + b / l2,
+            Ior::Sellmeier { b, c } => (1.0 + (0..3).map(|i| b[i] * l2 / (l2 - c[i])).sum::<f64>()).sqrt(),
+        }
+    }
+}
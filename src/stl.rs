@@ -0,0 +1,203 @@
+//STL import, binary and ASCII. STL stores every triangle with its own three corners;
+//corners at the same position are welded into one vertex so the mesh is indexed like
+//the others. The stored facet normals are ignored, since they are often missing or
+//wrong, and triangles are shaded flat.
+
+use crate::mesh::{MeshData, MeshError};
+use cgmath::*;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+const HEADER_SIZE: usize = 84; //80 bytes of free text and the triangle count
+const TRIANGLE_SIZE: usize = 50; //normal, three corners, attribute byte count
+
+pub fn load(path: &Path) -> Result<MeshData, MeshError> {
+    let bytes = fs::read(path).map_err(|e| MeshError {
+        line: 0,
+        message: format!("cannot read {}: {}", path.display(), e),
+    })?;
+    parse(&bytes)
+}
+
+pub fn parse(bytes: &[u8]) -> Result<MeshData, MeshError> {
+    //some exporters start binary files with "solid" too, so a size that matches the
+    //binary triangle count wins. Anything else that starts with "solid" is ASCII, even
+    //if it has no facets or is malformed, so the error says what is actually wrong.
+    let ascii = binary_size(bytes) != Some(bytes.len()) && bytes.trim_ascii_start().starts_with(b"solid");
+    let triangles = if ascii { parse_ascii(bytes)? } else { parse_binary(bytes)? };
+    if triangles.is_empty() {
+        return Err(MeshError {
+            line: 0,
+            message: String::from("the file contains no triangles"),
+        });
+    }
+    Ok(weld(&triangles))
+}
+
+//the file size implied by the triangle count in a binary header
+fn binary_size(bytes: &[u8]) -> Option<usize> {
+    let count = bytes.get(80..HEADER_SIZE)?;
+    let count = u32::from_le_bytes([count[0], count[1], count[2], count[3]]) as usize;
+    Some(HEADER_SIZE + count * TRIANGLE_SIZE)
+}
+
+fn parse_binary(bytes: &[u8]) -> Result<Vec<[Point3<f64>; 3]>, MeshError> {
+    let expected = binary_size(bytes).unwrap_or(HEADER_SIZE);
+    if bytes.len() < expected {
+        return Err(MeshError {
+            line: 0,
+            message: format!("binary STL is truncated: expected {} bytes, found {}", expected, bytes.len()),
+        });
+    }
+
+    let float = |offset: usize| {
+        f32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]]) as f64
+    };
+    let point = |offset: usize| Point3::new(float(offset), float(offset + 4), float(offset + 8));
+    Ok((HEADER_SIZE..expected)
+        .step_by(TRIANGLE_SIZE)
+        .map(|t| [point(t + 12), point(t + 24), point(t + 36)])
+        .collect())
+}
+
+fn parse_ascii(bytes: &[u8]) -> Result<Vec<[Point3<f64>; 3]>, MeshError> {
+    let text = String::from_utf8_lossy(bytes);
+    let mut triangles = Vec::new();
+    let mut corners = Vec::with_capacity(3);
+
+    for (i, line) in text.lines().enumerate() {
+        let error = |message: String| MeshError { line: i + 1, message };
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["vertex", x, y, z] => {
+                let mut coordinates = [0.0; 3];
+                for (c, word) in coordinates.iter_mut().zip([x, y, z]) {
+                    *c = word.parse().map_err(|_| error(format!("invalid number `{}`", word)))?;
+                }
+                corners.push(Point3::new(coordinates[0], coordinates[1], coordinates[2]));
+            }
+            ["vertex", ..] => return Err(error(String::from("vertex needs 3 coordinates"))),
+            ["endloop"] => {
+                if corners.len() != 3 {
+                    return Err(error(format!("facet has {} vertices, expected 3", corners.len())));
+                }
+                triangles.push([corners[0], corners[1], corners[2]]);
+                corners.clear();
+            }
+            //solid, facet normal, outer loop, endfacet, endsolid
+            _ => {}
+        }
+    }
+    Ok(triangles)
+}
+
+fn weld(triangles: &[[Point3<f64>; 3]]) -> MeshData {
+    let mut data = MeshData::default();
+    let mut vertices: HashMap<[u64; 3], u32> = HashMap::new();
+    for triangle in triangles {
+        let mut face = [0u32; 3];
+        for (k, p) in triangle.iter().enumerate() {
+            //adding 0.0 turns -0.0 into 0.0, so both weld together
+            let key = [(p.x + 0.0).to_bits(), (p.y + 0.0).to_bits(), (p.z + 0.0).to_bits()];
+            face[k] = *vertices.entry(key).or_insert_with(|| {
+                data.positions.push(*p);
+                (data.positions.len() - 1) as u32
+            });
+        }
+        data.indices.push(face);
+    }
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TETRAHEDRON: [[[f32; 3]; 3]; 4] = [
+        [[0.0, 0.0, 0.0], [0.0, 1.0, 0.0], [1.0, 0.0, 0.0]],
+        [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]],
+        [[0.0, 0.0, 0.0], [0.0, 0.0, 1.0], [0.0, 1.0, 0.0]],
+        [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+    ];
+
+    fn binary(header: &[u8], triangles: &[[[f32; 3]; 3]]) -> Vec<u8> {
+        let mut bytes = header.to_vec();
+        bytes.resize(80, b' ');
+        bytes.extend((triangles.len() as u32).to_le_bytes());
+        for triangle in triangles {
+            bytes.extend([0u8; 12]); //the normal, which is ignored
+            for corner in triangle {
+                for c in corner {
+                    bytes.extend(c.to_le_bytes());
+                }
+            }
+            bytes.extend([0u8; 2]);
+        }
+        bytes
+    }
+
+    fn ascii(triangles: &[[[f32; 3]; 3]]) -> String {
+        let mut text = String::from("solid tetrahedron\n");
+        for triangle in triangles {
+            text.push_str("  facet normal 0 0 0\n    outer loop\n");
+            for [x, y, z] in triangle {
+                text.push_str(&format!("      vertex {} {} {}\n", x, y, z));
+            }
+            text.push_str("    endloop\n  endfacet\n");
+        }
+        text.push_str("endsolid tetrahedron\n");
+        text
+    }
+
+    fn error_of(bytes: &[u8]) -> MeshError {
+        parse(bytes).err().expect("the file should not parse")
+    }
+
+    #[test]
+    fn binary_and_ascii_give_the_same_welded_mesh() {
+        for bytes in [binary(b"binary", &TETRAHEDRON), ascii(&TETRAHEDRON).into_bytes()] {
+            let data = parse(&bytes).unwrap();
+            assert_eq!(data.positions.len(), 4);
+            assert_eq!(data.indices.len(), 4);
+            assert_eq!(data.positions[data.indices[3][2] as usize], Point3::new(0.0, 0.0, 1.0));
+        }
+    }
+
+    #[test]
+    fn binary_with_a_solid_header_is_binary() {
+        let data = parse(&binary(b"solid but binary, facet", &TETRAHEDRON)).unwrap();
+        assert_eq!(data.indices.len(), 4);
+    }
+
+    #[test]
+    fn ascii_errors_are_reported_as_such() {
+        let empty = error_of(b"solid nothing\nendsolid nothing\n");
+        assert!(empty.message.contains("no triangles"), "{}", empty.message);
+
+        let text = ascii(&TETRAHEDRON).replacen("vertex 0 1 0", "vertex 0 one 0", 1);
+        let error = error_of(text.as_bytes());
+        assert_eq!(error.line, 5);
+        assert!(error.message.contains("invalid number `one`"), "{}", error.message);
+
+        let text = ascii(&TETRAHEDRON).replacen("      vertex 0 0 0\n", "", 1);
+        let error = error_of(text.as_bytes());
+        assert!(error.message.contains("facet has 2 vertices"), "{}", error.message);
+    }
+
+    #[test]
+    fn truncated_binary_is_reported() {
+        let mut bytes = binary(b"binary", &TETRAHEDRON);
+        bytes.truncate(bytes.len() - 10);
+        let error = error_of(&bytes);
+        assert!(error.message.contains("truncated"), "{}", error.message);
+    }
+
+    #[test]
+    fn bundled_model_loads() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes/models/bracket.stl");
+        let data = load(&path).unwrap();
+        assert!(!data.indices.is_empty());
+        assert!(data.positions.len() < 3 * data.indices.len(), "corners should be welded");
+    }
+}
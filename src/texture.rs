@@ -1,12 +1,31 @@
 use crate::color::Color;
+use crate::hittable::HitRecord;
+use crate::sampler::Sampler;
 use crate::utilities;
-use image::open;
+use image::{open, ImageError};
 use cgmath::*;
+use std::path::Path;
+use std::sync::Arc;
 //trait Textrue
 const BYTES_PER_PIXEL: usize = 3;
 
-pub trait Texture {
+pub trait Texture: Send + Sync {
     fn value(&self, u: f64, v: f64, p: Point3<f64>) -> Color;
+
+    //the value at a surface hit; textures that need more than (u, v, p) override this
+    fn value_at(&self, rec: &HitRecord) -> Color {
+        self.value(rec.u, rec.v, rec.p)
+    }
+}
+
+impl<T: Texture + ?Sized> Texture for Arc<T> {
+    fn value(&self, u: f64, v: f64, p: Point3<f64>) -> Color {
+        (**self).value(u, v, p)
+    }
+
+    fn value_at(&self, rec: &HitRecord) -> Color {
+        (**self).value_at(rec)
+    }
 }
 
 //struct SolidColor
@@ -26,6 +45,28 @@ impl Texture for SolidColor {
     }
 }
 
+//the interpolated vertex color of a mesh, e.g. from a colored PLY scan. Surfaces
+//without vertex colors get the fallback.
+pub struct VertexColorTexture {
+    fallback: Color,
+}
+
+impl VertexColorTexture {
+    pub fn new(fallback: Color) -> Self {
+        VertexColorTexture { fallback }
+    }
+}
+
+impl Texture for VertexColorTexture {
+    fn value(&self, _u: f64, _v: f64, _p: Point3<f64>) -> Color {
+        self.fallback
+    }
+
+    fn value_at(&self, rec: &HitRecord) -> Color {
+        rec.color.unwrap_or(self.fallback)
+    }
+}
+
 //struct CheckerTexture
 pub struct CheckerTexture {
     even: Box<dyn Texture>,
@@ -69,15 +110,12 @@ pub struct Perlin {
 }
 
 impl Perlin {
-    pub fn new() -> Self {
-        let mut ranfloat = vec![0.0; Self::point_count()];
-        for i in 0..Self::point_count() {
-            ranfloat[i] = utilities::random_double();
-        }
+    pub fn new(sampler: &mut dyn Sampler) -> Self {
+        let ranfloat = (0..Self::point_count()).map(|_| utilities::random_double(sampler)).collect();
 
-        let perm_x = Self::perlin_generate_perm();
-        let perm_y = Self::perlin_generate_perm();
-        let perm_z = Self::perlin_generate_perm();
+        let perm_x = Self::perlin_generate_perm(sampler);
+        let perm_y = Self::perlin_generate_perm(sampler);
+        let perm_z = Self::perlin_generate_perm(sampler);
 
         Self {
             ranfloat,
@@ -93,10 +131,10 @@ impl Perlin {
         let (u, v, w) = (u*u*(3.0-2.0*u), v*v*(3.0-2.0*v), w*w*(3.0-2.0*w));
 
         let mut c = vec![vec![vec![0.0; 2]; 2]; 2];
-        for di in 0..2 {
-            for dj in 0..2 {
-                for dk in 0..2 {
-                    c[di][dj][dk] = self.ranfloat[(
+        for (di, plane) in c.iter_mut().enumerate() {
+            for (dj, row) in plane.iter_mut().enumerate() {
+                for (dk, cell) in row.iter_mut().enumerate() {
+                    *cell = self.ranfloat[(
                         self.perm_x[((i as i32+di as i32) & 255) as usize] ^
                         self.perm_y[((j as i32+dj as i32) & 255) as usize] ^ 
                         self.perm_z[((k as i32+dk as i32) & 255) as usize]
@@ -113,35 +151,29 @@ impl Perlin {
         256
     }
 
-    fn perlin_generate_perm() -> Vec<i32> {
-        let mut p = vec![0; Self::point_count()];
+    fn perlin_generate_perm(sampler: &mut dyn Sampler) -> Vec<i32> {
+        let mut p: Vec<i32> = (0..Self::point_count() as i32).collect();
 
-        for i in 0..Self::point_count() {
-            p[i] = i as i32
-        }
-
-        Self::permute(&mut p, Self::point_count());
+        Self::permute(&mut p, Self::point_count(), sampler);
 
         p
     }
 
-    fn permute(p: &mut Vec<i32>, n: usize) { //randomize the preceding n element in p
+    fn permute(p: &mut [i32], n: usize, sampler: &mut dyn Sampler) { //randomize the preceding n element in p
         for i in (0..n).rev() {
-            let target = utilities::random_int_with_bounds(0, i as i32 + 1);
-            let tmp = p[i];
-            p[i] = p[target as usize];
-            p[target as usize] = tmp;
+            let target = utilities::random_int_with_bounds(sampler, 0, i as i32 + 1);
+            p.swap(i, target as usize);
         }
     }
     
     fn trilinear_interp(c: Vec<Vec<Vec<f64>>>, u: f64, v: f64,  w: f64) -> f64 {
         let mut accum = 0.0;
-        for i in 0..2 {
-            for j in 0..2 {
-                for k in 0..2 {
+        for (i, plane) in c.iter().enumerate() {
+            for (j, row) in plane.iter().enumerate() {
+                for (k, cell) in row.iter().enumerate() {
                     accum += (i as f64*u + (1.0-i as f64)*(1.0-u)) *
                             (j as f64*v + (1.0-j as f64)*(1.0-v)) * 
-                            (k as f64*w + (1.0-k as f64)*(1.0-w as f64)) * c[i][j][k];
+                            (k as f64*w + (1.0-k as f64)*(1.0-w)) * cell;
                 }
             }
         }
@@ -157,9 +189,9 @@ pub struct NoiseTexture {
 }
 
 impl NoiseTexture {
-    pub fn new(scale: f64) -> Self {
+    pub fn new(scale: f64, sampler: &mut dyn Sampler) -> Self {
         Self {
-            noise: Perlin::new(),
+            noise: Perlin::new(sampler),
             scale,
         }
     }
@@ -180,14 +212,33 @@ pub struct ImageTexture {
 }
 
 impl ImageTexture {
+    //panics if the image cannot be read; see `load`
     pub fn new(filename: &str) -> Self {
-        let img_buf = open(filename).unwrap().into_rgb8();
+        match ImageTexture::load(Path::new(filename)) {
+            Ok(texture) => texture,
+            Err(e) => panic!("cannot load image {}: {}", filename, e),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, ImageError> {
+        let img_buf = open(path)?.into_rgb8();
         let (width, height) = (img_buf.width() as usize, img_buf.height() as usize);
-        ImageTexture {
+        Ok(ImageTexture {
             data: img_buf.into_raw(),
             width,
             height,
             bytes_per_scanline: width * BYTES_PER_PIXEL,
+        })
+    }
+
+    //tightly packed 8-bit RGB rows, top row first, e.g. an image embedded in a model
+    pub fn from_rgb8(data: Vec<u8>, width: usize, height: usize) -> Self {
+        assert_eq!(data.len(), width * height * BYTES_PER_PIXEL, "image data does not match its size");
+        ImageTexture {
+            data,
+            width,
+            height,
+            bytes_per_scanline: width * BYTES_PER_PIXEL,
         }
     }
 }
@@ -0,0 +1,182 @@
+use crate::aabb::AABB;
+use crate::bvh::BVH;
+use crate::hittable::{HitRecord, Hittable};
+use crate::instance::Instance;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::transform::SingularMatrix;
+use cgmath::*;
+
+//rebuild once refitting has made the tree this much more expensive than when it was built
+const REBUILD_FACTOR: f64 = 1.5;
+
+//the top level of a two-level acceleration structure: a BVH over instances, each
+//referring to a bottom-level structure such as a mesh's own BVH. When instances move,
+//only this level changes; `update` refits it, and rebuilds it when the refitted tree
+//has become too slow to trace.
+pub struct TLAS {
+    bvh: BVH<Instance>,
+    positions: Vec<usize>, //where each instance, by the index it was given in, sits in the BVH
+    time0: f64,
+    time1: f64,
+    built_cost: f64,
+    dirty: bool,
+}
+
+impl TLAS {
+    //time0 - time1: the shutter interval, as for BVH::new
+    pub fn new(instances: Vec<Instance>, time0: f64, time1: f64) -> Self {
+        let (bvh, order) = BVH::from_primitives_ordered(instances, time0, time1);
+        let mut tlas = TLAS {
+            built_cost: bvh.sah_cost(),
+            bvh,
+            positions: Vec::new(),
+            time0,
+            time1,
+            dirty: false,
+        };
+        tlas.set_order(&order);
+        tlas
+    }
+
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+
+    //instances keep the index they had in the vector given to `new`
+    pub fn instance(&self, index: usize) -> &Instance {
+        &self.bvh.primitives()[self.positions[index]]
+    }
+
+    //takes effect for rendering after the next `update`
+    pub fn set_matrix(&mut self, index: usize, matrix: Matrix4<f64>) -> Result<(), SingularMatrix> {
+        self.bvh.primitives_mut()[self.positions[index]].set_matrix(matrix)?;
+        self.dirty = true;
+        Ok(())
+    }
+
+    //brings the tree up to date after instances have moved; returns whether it had to
+    //be rebuilt rather than refit
+    pub fn update(&mut self) -> bool {
+        if !self.dirty {
+            return false;
+        }
+        self.dirty = false;
+
+        self.bvh.refit(self.time0, self.time1);
+        if self.bvh.sah_cost() > REBUILD_FACTOR * self.built_cost {
+            self.rebuild();
+            return true;
+        }
+        false
+    }
+
+    pub fn rebuild(&mut self) {
+        let bvh = std::mem::replace(&mut self.bvh, BVH::from_primitives(Vec::new(), self.time0, self.time1));
+
+        //back to the original order, so indices stay valid across rebuilds
+        let mut slots: Vec<Option<Instance>> = (0..self.positions.len()).map(|_| None).collect();
+        let mut ids = vec![0; self.positions.len()];
+        for (id, &position) in self.positions.iter().enumerate() {
+            ids[position] = id;
+        }
+        for (position, instance) in bvh.into_primitives().into_iter().enumerate() {
+            slots[ids[position]] = Some(instance);
+        }
+        let instances = slots.into_iter().map(|slot| slot.unwrap()).collect();
+
+        let (bvh, order) = BVH::from_primitives_ordered(instances, self.time0, self.time1);
+        self.built_cost = bvh.sah_cost();
+        self.bvh = bvh;
+        self.set_order(&order);
+        self.dirty = false;
+    }
+
+    fn set_order(&mut self, order: &[usize]) {
+        self.positions = vec![0; order.len()];
+        for (position, &id) in order.iter().enumerate() {
+            self.positions[id] = position;
+        }
+    }
+}
+
+impl Hittable for TLAS {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, sampler: &mut dyn Sampler) -> Option<HitRecord> {
+        debug_assert!(!self.dirty, "TLAS::update must be called after instances move");
+        self.bvh.hit(r, t_min, t_max, sampler)
+    }
+
+    fn bounding_box(&self, time0: f64, time1: f64, output_box: &mut AABB) -> bool {
+        self.bvh.bounding_box(time0, time1, output_box)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::material::Lambertian;
+    use crate::objects::Sphere;
+    use crate::sampler::IndependentSampler;
+    use std::sync::Arc;
+
+    fn at(x: f64) -> Matrix4<f64> {
+        Matrix4::from_translation(Vector3::new(x, 0.0, 0.0))
+    }
+
+    //unit spheres in a row along x, three apart
+    fn row(count: usize) -> TLAS {
+        let sphere: Arc<dyn Hittable> =
+            Arc::new(Sphere::new(Point3::new(0.0, 0.0, 0.0), 1.0, Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)))));
+        let instances = (0..count)
+            .map(|i| Instance::new(Arc::clone(&sphere), at(3.0 * i as f64), None).unwrap())
+            .collect();
+        TLAS::new(instances, 0.0, 1.0)
+    }
+
+    //where a ray down the z axis at x first hits, if anywhere
+    fn hit_at(tlas: &TLAS, x: f64) -> Option<Point3<f64>> {
+        let ray = Ray::new(Point3::new(x, 0.0, -10.0), Vector3::new(0.0, 0.0, 1.0), 0.0);
+        let mut sampler = IndependentSampler::new(1);
+        tlas.hit(&ray, 0.001, f64::INFINITY, &mut sampler).map(|rec| rec.p)
+    }
+
+    #[test]
+    fn hits_follow_a_moved_instance() {
+        let mut tlas = row(8);
+        assert!(hit_at(&tlas, 0.0).is_some());
+        assert!(hit_at(&tlas, 50.0).is_none());
+
+        tlas.set_matrix(0, at(50.0)).unwrap();
+        assert!(!tlas.update(), "one instance moving a little should only refit");
+        assert!(hit_at(&tlas, 0.0).is_none());
+        let p = hit_at(&tlas, 50.0).expect("the moved instance should be hit");
+        assert!((p - Point3::new(50.0, 0.0, -1.0)).magnitude() < 1e-9);
+        assert_eq!(tlas.instance(0).matrix(), at(50.0));
+    }
+
+    #[test]
+    fn scrambling_the_instances_triggers_a_rebuild() {
+        let count = 16;
+        let mut tlas = row(count);
+        //neighbours end up far apart, so each node of the old tree now spans most of
+        //the row
+        let slot = |i: usize| 3.0 * (i * 7 % count) as f64;
+        for i in 0..count {
+            tlas.set_matrix(i, at(slot(i))).unwrap();
+        }
+        assert!(tlas.update(), "a badly degraded tree should be rebuilt");
+        assert!(tlas.bvh.sah_cost() <= REBUILD_FACTOR * tlas.built_cost);
+
+        //indices survive the rebuild, and every instance is where it was moved to
+        for i in 0..count {
+            assert_eq!(tlas.instance(i).matrix(), at(slot(i)));
+            assert!(hit_at(&tlas, slot(i)).is_some());
+        }
+        assert!(!tlas.update(), "nothing moved since the rebuild");
+    }
+}
@@ -0,0 +1,184 @@
+use crate::aabb::AABB;
+use crate::hittable::{HitRecord, Hittable};
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::utilities;
+use cgmath::Transform as _; //the trait for transform_point, shadowed by our struct
+use cgmath::*;
+use std::fmt;
+use std::sync::Arc;
+
+//an object placed by an affine transform. Rays are taken into object space with the
+//inverse matrix; normals come back with the inverse transpose. The object is shared,
+//so the same geometry can be placed any number of times, and the matrix can change
+//between frames.
+pub struct Transform {
+    ptr: Arc<dyn Hittable>,
+    matrix: Matrix4<f64>,
+    inverse: Matrix4<f64>,
+}
+
+//the matrix given to a Transform cannot be inverted, e.g. it scales by 0
+#[derive(Debug)]
+pub struct SingularMatrix;
+
+impl fmt::Display for SingularMatrix {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "the transform cannot be inverted")
+    }
+}
+
+impl std::error::Error for SingularMatrix {}
+
+impl Transform {
+    pub fn new(ptr: Arc<dyn Hittable>, matrix: Matrix4<f64>) -> Result<Self, SingularMatrix> {
+        Ok(Transform {
+            ptr,
+            matrix,
+            inverse: matrix.invert().ok_or(SingularMatrix)?,
+        })
+    }
+
+    pub fn matrix(&self) -> Matrix4<f64> {
+        self.matrix
+    }
+
+    //a BVH holding this object must be refit or rebuilt afterwards. On error the old
+    //matrix is kept.
+    pub fn set_matrix(&mut self, matrix: Matrix4<f64>) -> Result<(), SingularMatrix> {
+        self.inverse = matrix.invert().ok_or(SingularMatrix)?;
+        self.matrix = matrix;
+        Ok(())
+    }
+
+    //direction is not normalized, so t is the same in both spaces
+    fn to_object(&self, r: &Ray) -> Ray {
+        let mut ray = Ray::new(
+            self.inverse.transform_point(r.origin),
+            self.inverse.transform_vector(r.direction),
+            r.time,
+        );
+        ray.wavelength = r.wavelength;
+        ray
+    }
+}
+
+impl Hittable for Transform {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, sampler: &mut dyn Sampler) -> Option<HitRecord> {
+        let mut rec = self.ptr.hit(&self.to_object(r), t_min, t_max, sampler)?;
+        rec.p = self.matrix.transform_point(rec.p);
+        //the normal already faces the ray, and a linear map keeps the sign of
+        //dot(direction, normal), so front_face carries over unchanged. The inverse transpose
+        //is not stored, to keep instances small.
+        let normal_matrix = linear_part(&self.inverse).transpose();
+        rec.normal = utilities::unit_vector(normal_matrix * rec.normal);
+        Some(rec)
+    }
+
+    //the box around the eight transformed corners of the object's box. Not cached,
+    //since it is only needed while building or refitting a BVH.
+    fn bounding_box(&self, time0: f64, time1: f64, output_box: &mut AABB) -> bool {
+        let mut object_box = AABB::empty();
+        if !self.ptr.bounding_box(time0, time1, &mut object_box) {
+            return false;
+        }
+
+        let (min, max) = (object_box.min(), object_box.max());
+        let mut bbox = AABB::empty();
+        for i in 0..8 {
+            let corner = Point3::new(
+                if i & 1 == 0 { min.x } else { max.x },
+                if i & 2 == 0 { min.y } else { max.y },
+                if i & 4 == 0 { min.z } else { max.z },
+            );
+            bbox = bbox.include_point(self.matrix.transform_point(corner));
+        }
+        *output_box = bbox;
+        true
+    }
+
+    //solid angles are only preserved by rotations and uniform scales; in general the
+    //density picks up the Jacobian |det A| / |A w|^3 of the direction map A
+    fn pdf_value(&self, origin: Point3<f64>, direction: Vector3<f64>) -> f64 {
+        let w = utilities::unit_vector(direction);
+        let object_direction = self.inverse.transform_vector(w);
+        let length = object_direction.magnitude();
+        let jacobian = self.inverse.determinant().abs() / (length * length * length);
+        self.ptr.pdf_value(self.inverse.transform_point(origin), object_direction) * jacobian
+    }
+
+    fn random(&self, origin: Point3<f64>, sampler: &mut dyn Sampler) -> Vector3<f64> {
+        let direction = self.ptr.random(self.inverse.transform_point(origin), sampler);
+        self.matrix.transform_vector(direction)
+    }
+
+    fn area(&self) -> Option<f64> {
+        self.ptr.transformed_area(&linear_part(&self.matrix))
+    }
+
+    fn transformed_area(&self, m: &Matrix3<f64>) -> Option<f64> {
+        self.ptr.transformed_area(&(m * linear_part(&self.matrix)))
+    }
+}
+
+fn linear_part(m: &Matrix4<f64>) -> Matrix3<f64> {
+    Matrix3::from_cols(m.x.truncate(), m.y.truncate(), m.z.truncate())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::material::Lambertian;
+    use crate::objects::{Cubic, Sphere, XzRect};
+
+    fn sphere() -> Arc<dyn Hittable> {
+        Arc::new(Sphere::new(Point3::new(0.0, 0.0, 0.0), 1.0, Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)))))
+    }
+
+    fn close(a: Option<f64>, b: f64) -> bool {
+        a.is_some_and(|a| (a - b).abs() < 1e-9 * b.max(1.0))
+    }
+
+    #[test]
+    fn area_is_exact_for_flat_surfaces_under_any_transform() {
+        let material = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let rect: Arc<dyn Hittable> = Arc::new(XzRect::new(0.0, 2.0, 0.0, 3.0, 1.0, material.clone()));
+        let stretch = Matrix4::from_nonuniform_scale(2.0, 5.0, 0.5);
+        assert!(close(Transform::new(Arc::clone(&rect), stretch).unwrap().area(), 2.0 * 2.0 * 3.0 * 0.5));
+
+        //a shear along x by y leaves a rectangle in the xz plane as it is
+        let mut shear = Matrix4::identity();
+        shear.y.x = 1.0;
+        let placed = Transform::new(Arc::clone(&rect), Matrix4::from_angle_x(Deg(30.0)) * shear).unwrap();
+        assert!(close(placed.area(), 6.0));
+
+        //nested transforms compose; a unit cube stretched to 1 x 2 x 3
+        let cube: Arc<dyn Hittable> = Arc::new(Cubic::new(Point3::new(0.0, 0.0, 0.0), Point3::new(1.0, 1.0, 1.0), material));
+        let inner: Arc<dyn Hittable> = Arc::new(Transform::new(cube, Matrix4::from_nonuniform_scale(1.0, 2.0, 1.0)).unwrap());
+        let outer = Transform::new(inner, Matrix4::from_angle_y(Deg(40.0)) * Matrix4::from_nonuniform_scale(1.0, 1.0, 3.0)).unwrap();
+        assert!(close(outer.area(), 2.0 * (1.0 * 2.0 + 2.0 * 3.0 + 3.0 * 1.0)));
+    }
+
+    #[test]
+    fn curved_surfaces_only_know_their_area_under_uniform_scales() {
+        let turned = Matrix4::from_angle_z(Deg(25.0)) * Matrix4::from_scale(2.0);
+        assert!(close(Transform::new(sphere(), turned).unwrap().area(), 16.0 * std::f64::consts::PI));
+        let squashed = Matrix4::from_nonuniform_scale(1.0, 0.5, 1.0);
+        assert_eq!(Transform::new(sphere(), squashed).unwrap().area(), None);
+    }
+
+    #[test]
+    fn singular_matrix_is_an_error() {
+        assert!(Transform::new(sphere(), Matrix4::from_scale(0.0)).is_err());
+        assert!(Transform::new(sphere(), Matrix4::from_scale(1e-200)).is_err());
+    }
+
+    #[test]
+    fn failed_set_matrix_keeps_the_old_matrix() {
+        let matrix = Matrix4::from_translation(Vector3::new(1.0, 2.0, 3.0));
+        let mut transform = Transform::new(sphere(), matrix).unwrap();
+        assert!(transform.set_matrix(Matrix4::from_nonuniform_scale(1.0, 0.0, 1.0)).is_err());
+        assert_eq!(transform.matrix(), matrix);
+    }
+}
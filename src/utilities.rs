@@ -1,38 +1,46 @@
-use rand::Rng;
+use crate::sampler::Sampler;
 use std::f64::{self, consts};
 use cgmath::*;
 
+//derive an independent stream for one pixel from the user seed (splitmix64 finalizer)
+pub fn pixel_seed(seed: u64, pixel_index: u64) -> u64 {
+    let mut z = seed ^ pixel_index.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
 pub fn degrees_to_radians(degrees: f64) -> f64 {
     degrees * consts::PI / 180.0
 }
 
-pub fn random_double() -> f64 {
-    rand::thread_rng().gen_range(0.0, f64::MAX) / f64::MAX
+pub fn random_double(sampler: &mut dyn Sampler) -> f64 {
+    sampler.get_1d()
 }
 
-pub fn random_double_with_bounds(lower: f64, upper: f64) -> f64 {
-    lower + (upper - lower) * random_double()
+pub fn random_double_with_bounds(sampler: &mut dyn Sampler, lower: f64, upper: f64) -> f64 {
+    lower + (upper - lower) * random_double(sampler)
 }
 
 pub fn unit_vector(v: Vector3<f64>) -> Vector3<f64> {
     v / v.magnitude()
 }
 
-pub fn random_vec3(lower: f64, upper: f64) -> Vector3<f64> {
+pub fn random_vec3(sampler: &mut dyn Sampler, lower: f64, upper: f64) -> Vector3<f64> {
     Vector3::new(
-        random_double_with_bounds(lower, upper),
-        random_double_with_bounds(lower, upper),
-        random_double_with_bounds(lower, upper),
+        random_double_with_bounds(sampler, lower, upper),
+        random_double_with_bounds(sampler, lower, upper),
+        random_double_with_bounds(sampler, lower, upper),
     )
 }
 
-pub fn random() -> Vector3<f64> {
-    Vector3::new(random_double(), random_double(), random_double())
+pub fn random(sampler: &mut dyn Sampler) -> Vector3<f64> {
+    Vector3::new(random_double(sampler), random_double(sampler), random_double(sampler))
 }
 
-pub fn random_in_unit_sphere() -> Vector3<f64> {
+pub fn random_in_unit_sphere(sampler: &mut dyn Sampler) -> Vector3<f64> {
     loop {
-        let p = random_vec3(-1.0, 1.0);
+        let p = random_vec3(sampler, -1.0, 1.0);
         if p.magnitude2() >= 1.0 {
             continue;
         }
@@ -40,8 +48,30 @@ pub fn random_in_unit_sphere() -> Vector3<f64> {
     }
 }
 
-pub fn random_unit_vector() -> Vector3<f64> {
-    unit_vector(random_in_unit_sphere())
+pub fn random_unit_vector(sampler: &mut dyn Sampler) -> Vector3<f64> {
+    unit_vector(random_in_unit_sphere(sampler))
+}
+
+//cosine-weighted direction on the hemisphere around +z
+pub fn random_cosine_direction(sampler: &mut dyn Sampler) -> Vector3<f64> {
+    let (r1, r2) = sampler.get_2d();
+    let phi = 2.0 * consts::PI * r1;
+    let z = (1.0 - r2).sqrt();
+
+    Vector3::new(phi.cos() * r2.sqrt(), phi.sin() * r2.sqrt(), z)
+}
+
+//direction toward a sphere of the given radius at distance sqrt(distance_squared) along +z,
+//uniform over the subtended cone
+pub fn random_to_sphere(sampler: &mut dyn Sampler, radius: f64, distance_squared: f64) -> Vector3<f64> {
+    let (r1, r2) = sampler.get_2d();
+    let z = 1.0 + r2 * ((1.0 - radius * radius / distance_squared).sqrt() - 1.0);
+
+    let phi = 2.0 * consts::PI * r1;
+    let x = phi.cos() * (1.0 - z * z).sqrt();
+    let y = phi.sin() * (1.0 - z * z).sqrt();
+
+    Vector3::new(x, y, z)
 }
 
 pub fn clamp(x: f64, lower: f64, upper: f64) -> f64 {
@@ -53,14 +83,13 @@ pub fn clamp(x: f64, lower: f64, upper: f64) -> f64 {
     x
 }
 
-pub fn random_int_with_bounds(lower: i32, upper: i32) -> i32 {
-    let range = (upper - lower) as usize;
-    let rand_number = rand::random::<usize>() % range;
-    rand_number as i32 + lower
+pub fn random_int_with_bounds(sampler: &mut dyn Sampler, lower: i32, upper: i32) -> i32 {
+    let range = (upper - lower) as f64;
+    lower + ((random_double(sampler) * range) as i32).min(upper - lower - 1)
 }
 
 pub fn near_zero(v: Vector3<f64>) -> bool {
-    if v.magnitude() < 0.01 { true } else { false }
+    v.magnitude() < 0.01
 }
 
 